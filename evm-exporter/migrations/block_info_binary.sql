@@ -0,0 +1,4 @@
+ALTER TABLE block_info
+    ALTER COLUMN block TYPE BYTEA USING convert_to(block, 'UTF8'),
+    ALTER COLUMN receipt TYPE BYTEA USING convert_to(receipt, 'UTF8'),
+    ALTER COLUMN statuses TYPE BYTEA USING convert_to(statuses, 'UTF8');
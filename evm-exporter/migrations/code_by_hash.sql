@@ -0,0 +1,10 @@
+-- Codes are stored once under their keccak hash and `byte_code` only keeps
+-- the hash. The hashes are filled in by the backfill, which then drops the
+-- `code` column.
+
+CREATE TABLE IF NOT EXISTS code (
+    hash BYTEA PRIMARY KEY,
+    code BYTEA NOT NULL
+);
+
+ALTER TABLE byte_code ADD COLUMN code_hash BYTEA;
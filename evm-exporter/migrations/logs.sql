@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS logs (
+    id BIGSERIAL PRIMARY KEY,
+    block_number BIGINT NOT NULL,
+    block_hash CHARACTER VARYING(128) NOT NULL,
+    transaction_hash CHARACTER VARYING(128) NOT NULL,
+    transaction_index BIGINT NOT NULL,
+    log_index BIGINT NOT NULL,
+    transaction_log_index BIGINT NOT NULL,
+    address CHARACTER VARYING(64) NOT NULL,
+    topic0 CHARACTER VARYING(128),
+    topic1 CHARACTER VARYING(128),
+    topic2 CHARACTER VARYING(128),
+    topic3 CHARACTER VARYING(128),
+    data TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS logs_block_number_idx ON logs(block_number, log_index);
+CREATE INDEX IF NOT EXISTS logs_block_hash_idx ON logs(block_hash);
+CREATE INDEX IF NOT EXISTS logs_address_idx ON logs(address, block_number);
+CREATE INDEX IF NOT EXISTS logs_topic0_idx ON logs(topic0, block_number);
+CREATE INDEX IF NOT EXISTS logs_topic1_idx ON logs(topic1, block_number);
+CREATE INDEX IF NOT EXISTS logs_topic2_idx ON logs(topic2, block_number);
+CREATE INDEX IF NOT EXISTS logs_topic3_idx ON logs(topic3, block_number);
@@ -0,0 +1,99 @@
+-- Hashes, addresses and code as BYTEA, U256 values as NUMERIC(78) and
+-- heights as BIGINT. Text hashes and addresses are `0x` prefixed hex, code
+-- and log data plain hex, and U256 values decimal.
+
+ALTER TABLE balance
+    ALTER COLUMN balance TYPE NUMERIC(78) USING balance::NUMERIC(78),
+    ALTER COLUMN address TYPE BYTEA USING decode(substr(address, 3), 'hex'),
+    ALTER COLUMN height DROP DEFAULT;
+DROP INDEX IF EXISTS balance_address_idx;
+CREATE INDEX IF NOT EXISTS balance_address_height_idx ON balance(address, height);
+
+ALTER TABLE nonce
+    ALTER COLUMN nonce TYPE NUMERIC(78) USING nonce::NUMERIC(78),
+    ALTER COLUMN address TYPE BYTEA USING decode(substr(address, 3), 'hex'),
+    ALTER COLUMN height DROP DEFAULT;
+DROP INDEX IF EXISTS nonce_address_idx;
+CREATE INDEX IF NOT EXISTS nonce_address_height_idx ON nonce(address, height);
+
+ALTER TABLE byte_code
+    ALTER COLUMN code TYPE BYTEA USING decode(code, 'hex'),
+    ALTER COLUMN address TYPE BYTEA USING decode(substr(address, 3), 'hex'),
+    ALTER COLUMN height DROP DEFAULT;
+DROP INDEX IF EXISTS byte_code_address_idx;
+CREATE INDEX IF NOT EXISTS byte_code_address_height_idx ON byte_code(address, height);
+
+ALTER TABLE state
+    ALTER COLUMN value TYPE BYTEA USING decode(substr(value, 3), 'hex'),
+    ALTER COLUMN idx TYPE BYTEA USING decode(substr(idx, 3), 'hex'),
+    ALTER COLUMN address TYPE BYTEA USING decode(substr(address, 3), 'hex'),
+    ALTER COLUMN height DROP DEFAULT;
+DROP INDEX IF EXISTS state_address_idx;
+DROP INDEX IF EXISTS state_idx_idx;
+CREATE INDEX IF NOT EXISTS state_address_idx_height_idx ON state(address, idx, height);
+
+ALTER TABLE issuance
+    ALTER COLUMN value TYPE NUMERIC(78) USING value::NUMERIC(78),
+    ALTER COLUMN height DROP DEFAULT;
+
+ALTER TABLE allowances
+    ALTER COLUMN owner TYPE BYTEA USING decode(substr(owner, 3), 'hex'),
+    ALTER COLUMN spender TYPE BYTEA USING decode(substr(spender, 3), 'hex'),
+    ALTER COLUMN value TYPE NUMERIC(78) USING value::NUMERIC(78),
+    ALTER COLUMN height DROP DEFAULT;
+DROP INDEX IF EXISTS allowances_owner_idx;
+DROP INDEX IF EXISTS allowances_spender_idx;
+CREATE INDEX IF NOT EXISTS allowances_owner_spender_height_idx ON allowances(owner, spender, height);
+
+ALTER TABLE common
+    ALTER COLUMN latest_height DROP DEFAULT,
+    ALTER COLUMN lowest_height DROP DEFAULT;
+
+ALTER TABLE block_info
+    ALTER COLUMN block_hash TYPE BYTEA USING decode(substr(block_hash, 3), 'hex'),
+    ALTER COLUMN block_height TYPE BIGINT USING block_height::BIGINT;
+
+-- The block hash and index were a JSON pair in `transaction_index`.
+ALTER TABLE transactions ADD COLUMN block_hash BYTEA;
+UPDATE transactions SET block_hash = decode(substr(transaction_index::JSONB->>0, 3), 'hex');
+ALTER TABLE transactions
+    ALTER COLUMN transaction_hash TYPE BYTEA USING decode(substr(transaction_hash, 3), 'hex'),
+    ALTER COLUMN block_hash SET NOT NULL,
+    ALTER COLUMN transaction_index TYPE BIGINT USING (transaction_index::JSONB->>1)::BIGINT;
+
+ALTER TABLE logs
+    ALTER COLUMN block_hash TYPE BYTEA USING decode(substr(block_hash, 3), 'hex'),
+    ALTER COLUMN transaction_hash TYPE BYTEA USING decode(substr(transaction_hash, 3), 'hex'),
+    ALTER COLUMN address TYPE BYTEA USING decode(substr(address, 3), 'hex'),
+    ALTER COLUMN topic0 TYPE BYTEA USING decode(substr(topic0, 3), 'hex'),
+    ALTER COLUMN topic1 TYPE BYTEA USING decode(substr(topic1, 3), 'hex'),
+    ALTER COLUMN topic2 TYPE BYTEA USING decode(substr(topic2, 3), 'hex'),
+    ALTER COLUMN topic3 TYPE BYTEA USING decode(substr(topic3, 3), 'hex'),
+    ALTER COLUMN data TYPE BYTEA USING decode(data, 'hex');
+
+-- Pending entries are transient, so these tables start over empty instead of
+-- being converted.
+DROP TABLE IF EXISTS pending_transactions, pending_byte_code, pending_state;
+
+CREATE TABLE pending_transactions (
+    id BIGSERIAL PRIMARY KEY,
+    sign_address BYTEA NOT NULL,
+    pending_balance NUMERIC(78) NOT NULL,
+    pending_nonce NUMERIC(78) NOT NULL
+);
+CREATE INDEX pending_transactions_sign_address_idx ON pending_transactions(sign_address);
+
+CREATE TABLE pending_byte_code (
+    id BIGSERIAL PRIMARY KEY,
+    code BYTEA NOT NULL,
+    address BYTEA NOT NULL
+);
+CREATE INDEX pending_byte_code_address_idx ON pending_byte_code(address);
+
+CREATE TABLE pending_state (
+    id BIGSERIAL PRIMARY KEY,
+    value BYTEA NOT NULL,
+    idx BYTEA NOT NULL,
+    address BYTEA NOT NULL
+);
+CREATE INDEX pending_state_address_idx_idx ON pending_state(address, idx);
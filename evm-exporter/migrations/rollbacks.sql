@@ -0,0 +1,4 @@
+-- Number of rollbacks ever started, which tells readers caching historical
+-- data that heights they already read may have been rewritten.
+
+ALTER TABLE common ADD COLUMN IF NOT EXISTS rollbacks BIGINT NOT NULL DEFAULT 0;
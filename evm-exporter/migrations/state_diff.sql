@@ -0,0 +1,7 @@
+-- State diff of every height exported with one, as JSON: the old and new
+-- values of the balances, nonces, codes and slots the block changed.
+
+CREATE TABLE IF NOT EXISTS state_diff (
+    height BIGINT PRIMARY KEY,
+    diff BYTEA NOT NULL
+);
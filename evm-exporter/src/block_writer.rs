@@ -0,0 +1,172 @@
+use {
+    crate::{
+        error::Result,
+        types::{Block, Change, Receipt, StateDiff, TransactionStatus},
+        utils::code_hash,
+        Getter, Setter,
+    },
+    primitive_types::{H160, H256, U256},
+};
+
+/// Everything exported for one height.
+///
+/// `Setter::commit_block` writes it in a single unit together with the new
+/// latest height, so readers never observe a half-written height.
+#[derive(Default)]
+pub struct BlockChanges {
+    pub height: u32,
+    pub block_info: Option<(Block, Vec<Receipt>, Vec<TransactionStatus>)>,
+    pub balances: Vec<(H160, U256)>,
+    pub nonces: Vec<(H160, U256)>,
+    pub byte_codes: Vec<(H160, Vec<u8>)>,
+    pub states: Vec<(H160, H256, H256)>,
+    pub allowances: Vec<(H160, H160, U256)>,
+    pub total_issuance: Option<U256>,
+    /// Written along when set, see `BlockWriter::record_state_diff`.
+    pub state_diff: Option<StateDiff>,
+}
+
+impl BlockChanges {
+    /// The balances, nonces, codes and slots changed from their values before
+    /// `height`, which are read from `getter` a kind at a time, see
+    /// `Getter::get_balances`.
+    pub fn state_diff(&self, getter: &dyn Getter) -> Result<StateDiff> {
+        let before = self.height.saturating_sub(1);
+        let mut diff = StateDiff::new();
+        let addresses = self
+            .balances
+            .iter()
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+        let froms = getter.get_balances(before, &addresses)?;
+        for ((address, balance), from) in self.balances.iter().zip(froms) {
+            if from != *balance {
+                diff.entry(*address).or_default().balance = Some(Change { from, to: *balance });
+            }
+        }
+        let addresses = self
+            .nonces
+            .iter()
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+        let froms = getter.get_nonces(before, &addresses)?;
+        for ((address, nonce), from) in self.nonces.iter().zip(froms) {
+            if from != *nonce {
+                diff.entry(*address).or_default().nonce = Some(Change { from, to: *nonce });
+            }
+        }
+        let addresses = self
+            .byte_codes
+            .iter()
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+        let froms = getter.get_code_hashes(before, &addresses)?;
+        for ((address, code), from) in self.byte_codes.iter().zip(froms) {
+            let to = Some(code_hash(code));
+            if from != to {
+                diff.entry(*address).or_default().code_hash = Some(Change { from, to });
+            }
+        }
+        let slots = self
+            .states
+            .iter()
+            .map(|(address, index, _)| (*address, *index))
+            .collect::<Vec<_>>();
+        let froms = getter.get_states(before, &slots)?;
+        for ((address, index, value), from) in self.states.iter().zip(froms) {
+            if from != *value {
+                diff.entry(*address)
+                    .or_default()
+                    .storage
+                    .insert(*index, Change { from, to: *value });
+            }
+        }
+        Ok(diff)
+    }
+}
+
+/// Collects the writes of one height, returned by `Setter::begin_block`.
+///
+/// Nothing is visible to readers until `commit` succeeds; dropping the writer
+/// discards the block.
+pub struct BlockWriter<'a> {
+    setter: &'a dyn Setter,
+    changes: BlockChanges,
+}
+
+impl<'a> BlockWriter<'a> {
+    pub fn new(setter: &'a dyn Setter, height: u32) -> Self {
+        Self {
+            setter,
+            changes: BlockChanges {
+                height,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        self.changes.height
+    }
+
+    pub fn set_block_info(
+        &mut self,
+        block: Block,
+        receipts: Vec<Receipt>,
+        statuses: Vec<TransactionStatus>,
+    ) {
+        self.changes.block_info = Some((block, receipts, statuses));
+    }
+
+    pub fn set_balance(&mut self, address: H160, balance: U256) {
+        self.changes.balances.push((address, balance));
+    }
+
+    pub fn set_nonce(&mut self, address: H160, nonce: U256) {
+        self.changes.nonces.push((address, nonce));
+    }
+
+    pub fn set_byte_code(&mut self, address: H160, code: Vec<u8>) {
+        self.changes.byte_codes.push((address, code));
+    }
+
+    pub fn set_state(&mut self, address: H160, index: H256, value: H256) {
+        self.changes.states.push((address, index, value));
+    }
+
+    pub fn set_balances(&mut self, balances: Vec<(H160, U256)>) {
+        self.changes.balances.extend(balances);
+    }
+
+    pub fn set_nonces(&mut self, nonces: Vec<(H160, U256)>) {
+        self.changes.nonces.extend(nonces);
+    }
+
+    pub fn set_byte_codes(&mut self, codes: Vec<(H160, Vec<u8>)>) {
+        self.changes.byte_codes.extend(codes);
+    }
+
+    pub fn set_states(&mut self, states: Vec<(H160, H256, H256)>) {
+        self.changes.states.extend(states);
+    }
+
+    pub fn set_allowances(&mut self, owner: H160, spender: H160, value: U256) {
+        self.changes.allowances.push((owner, spender, value));
+    }
+
+    pub fn set_total_issuance(&mut self, value: U256) {
+        self.changes.total_issuance = Some(value);
+    }
+
+    /// Computes the state diff of the changes set so far against `getter`,
+    /// which has to read the store being written, and writes it along with
+    /// them. Call it once every change is set.
+    pub fn record_state_diff(&mut self, getter: &dyn Getter) -> Result<()> {
+        self.changes.state_diff = Some(self.changes.state_diff(getter)?);
+        Ok(())
+    }
+
+    pub fn commit(self) -> Result<()> {
+        self.setter.commit_block(self.changes)
+    }
+}
@@ -0,0 +1,546 @@
+use {
+    crate::{
+        AccountBasic, AccountRange, Block, Getter, IndexedLog, Receipt, Result, StateDiff,
+        StorageRange, TransactionStatus,
+    },
+    lru::LruCache,
+    primitive_types::{H160, H256, U256},
+    std::{
+        collections::BTreeMap,
+        hash::Hash,
+        num::NonZeroUsize,
+        sync::{
+            atomic::{AtomicU32, AtomicU64, Ordering},
+            Arc, Mutex, PoisonError,
+        },
+    },
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Cache<K, V> {
+    lru: Mutex<LruCache<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq, V: Clone> Cache<K, V> {
+    fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            lru: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn lookup(&self, key: &K) -> Option<V> {
+        self.lookup_if(key, |_| true)
+    }
+
+    /// Cached value of `key` if `valid` holds for it.
+    fn lookup_if(&self, key: &K, valid: impl FnOnce(&V) -> bool) -> Option<V> {
+        let cached = self
+            .lru
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .filter(|value| valid(value))
+            .cloned();
+        if cached.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        cached
+    }
+
+    fn insert(&self, key: K, value: V) {
+        self.lru
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(key, value);
+    }
+
+    /// Cached value of `key`, loaded on a miss. `None` is not cached since
+    /// the value may still be written.
+    fn get_or_load(&self, key: K, load: impl FnOnce() -> Result<Option<V>>) -> Result<Option<V>> {
+        if let Some(value) = self.lookup(&key) {
+            return Ok(Some(value));
+        }
+        let value = load()?;
+        if let Some(value) = &value {
+            self.insert(key, value.clone());
+        }
+        Ok(value)
+    }
+
+    fn get_or_load_value(&self, key: K, load: impl FnOnce() -> Result<V>) -> Result<V> {
+        if let Some(value) = self.lookup(&key) {
+            return Ok(value);
+        }
+        let value = load()?;
+        self.insert(key, value.clone());
+        Ok(value)
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn clear(&self) {
+        self.lru
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> Cache<K, (u32, V)> {
+    /// `get_or_load` for values kept with the height of their block, which
+    /// are only cached and served while `historical` holds for that height.
+    fn get_or_load_at(
+        &self,
+        key: K,
+        historical: impl Fn(u32) -> bool,
+        load: impl FnOnce() -> Result<Option<(u32, V)>>,
+    ) -> Result<Option<V>> {
+        if let Some((_, value)) = self.lookup_if(&key, |(height, _)| historical(*height)) {
+            return Ok(Some(value));
+        }
+        let loaded = load()?;
+        if let Some((height, value)) = &loaded {
+            if historical(*height) {
+                self.insert(key, (*height, value.clone()));
+            }
+        }
+        Ok(loaded.map(|(_, value)| value))
+    }
+}
+
+/// Height of a block numbered `number`, saturating above `u32::MAX`.
+fn block_height(number: U256) -> u32 {
+    number.try_into().unwrap_or(u32::MAX)
+}
+
+/// `Getter` decorator keeping data that can no longer change in bounded LRU
+/// caches, one per data kind.
+///
+/// Blocks, receipts and statuses are keyed by block hash and codes by their
+/// keccak hash. Versioned values and block data are only cached below the
+/// latest height seen through `latest_height`, so reads at the latest height
+/// and pending values always reach the store. Block data is kept with the
+/// height of its block and only served while that is below the latest height.
+/// A change of `Getter::rollback_count`, or a latest height lower than the
+/// previous one, means the store was rolled back and drops every cache, even
+/// when the re-export already got past the old latest height.
+pub struct CachingGetter<G: ?Sized> {
+    inner: Arc<G>,
+    latest: AtomicU32,
+    rollbacks: AtomicU64,
+    block_hashes: Cache<U256, (u32, H256)>,
+    block_heights: Cache<H256, (u32, U256)>,
+    blocks: Cache<H256, (u32, Block)>,
+    receipts: Cache<H256, (u32, Vec<Receipt>)>,
+    statuses: Cache<H256, (u32, Vec<TransactionStatus>)>,
+    transaction_indexes: Cache<H256, (u32, (H256, u32))>,
+    balances: Cache<(u32, H160), U256>,
+    nonces: Cache<(u32, H160), U256>,
+    byte_codes: Cache<(u32, H160), Vec<u8>>,
+    code_hashes: Cache<(u32, H160), Option<H256>>,
+    codes: Cache<H256, Vec<u8>>,
+    state_exists: Cache<(u32, H160), bool>,
+    states: Cache<(u32, H160, H256), H256>,
+    total_issuances: Cache<u32, U256>,
+    allowances: Cache<(u32, H160, H160), U256>,
+}
+
+impl<G: Getter + ?Sized> CachingGetter<G> {
+    /// Every cache keeps up to `capacity` entries.
+    pub fn new(inner: Arc<G>, capacity: NonZeroUsize) -> Self {
+        Self {
+            inner,
+            latest: AtomicU32::new(0),
+            rollbacks: AtomicU64::new(0),
+            block_hashes: Cache::new(capacity),
+            block_heights: Cache::new(capacity),
+            blocks: Cache::new(capacity),
+            receipts: Cache::new(capacity),
+            statuses: Cache::new(capacity),
+            transaction_indexes: Cache::new(capacity),
+            balances: Cache::new(capacity),
+            nonces: Cache::new(capacity),
+            byte_codes: Cache::new(capacity),
+            code_hashes: Cache::new(capacity),
+            codes: Cache::new(capacity),
+            state_exists: Cache::new(capacity),
+            states: Cache::new(capacity),
+            total_issuances: Cache::new(capacity),
+            allowances: Cache::new(capacity),
+        }
+    }
+
+    /// Hits and misses of every cache, by data kind.
+    pub fn stats(&self) -> BTreeMap<&'static str, CacheStats> {
+        BTreeMap::from([
+            ("block_hash", self.block_hashes.stats()),
+            ("block_height", self.block_heights.stats()),
+            ("block", self.blocks.stats()),
+            ("receipts", self.receipts.stats()),
+            ("statuses", self.statuses.stats()),
+            ("transaction_index", self.transaction_indexes.stats()),
+            ("balance", self.balances.stats()),
+            ("nonce", self.nonces.stats()),
+            ("byte_code", self.byte_codes.stats()),
+            ("code_hash", self.code_hashes.stats()),
+            ("code", self.codes.stats()),
+            ("state_exists", self.state_exists.stats()),
+            ("state", self.states.stats()),
+            ("total_issuance", self.total_issuances.stats()),
+            ("allowances", self.allowances.stats()),
+        ])
+    }
+
+    pub fn clear(&self) {
+        self.block_hashes.clear();
+        self.block_heights.clear();
+        self.blocks.clear();
+        self.receipts.clear();
+        self.statuses.clear();
+        self.transaction_indexes.clear();
+        self.balances.clear();
+        self.nonces.clear();
+        self.byte_codes.clear();
+        self.code_hashes.clear();
+        self.codes.clear();
+        self.state_exists.clear();
+        self.states.clear();
+        self.total_issuances.clear();
+        self.allowances.clear();
+    }
+
+    fn is_historical(&self, height: u32) -> bool {
+        height < self.latest.load(Ordering::Relaxed)
+    }
+
+    fn block_data<K: Hash + Eq, V: Clone>(
+        &self,
+        cache: &Cache<K, (u32, V)>,
+        key: K,
+        load: impl FnOnce() -> Result<Option<(u32, V)>>,
+    ) -> Result<Option<V>> {
+        cache.get_or_load_at(key, |height| self.is_historical(height), load)
+    }
+
+    /// `value` of the block `block_hash` along with the block height, which
+    /// is `u32::MAX`, never cached, when the block is unknown.
+    fn at_block<V>(&self, block_hash: H256, value: Option<V>) -> Result<Option<(u32, V)>> {
+        Ok(match value {
+            Some(value) => {
+                let height = self.get_height_by_block_hash(block_hash)?;
+                Some((height.map_or(u32::MAX, block_height), value))
+            }
+            None => None,
+        })
+    }
+
+    fn versioned<K: Hash + Eq, V: Clone>(
+        &self,
+        cache: &Cache<K, V>,
+        height: u32,
+        key: K,
+        load: impl FnOnce() -> Result<V>,
+    ) -> Result<V> {
+        if self.is_historical(height) {
+            cache.get_or_load_value(key, load)
+        } else {
+            load()
+        }
+    }
+}
+
+impl<G: Getter + ?Sized> Getter for CachingGetter<G> {
+    fn latest_height(&self) -> Result<u32> {
+        // read first, a rollback after it then shows in the next call
+        let rollbacks = self.inner.rollback_count()?;
+        let height = self.inner.latest_height()?;
+        let rolled_back = rollbacks != self.rollbacks.swap(rollbacks, Ordering::Relaxed);
+        if height < self.latest.swap(height, Ordering::Relaxed) || rolled_back {
+            self.clear();
+        }
+        Ok(height)
+    }
+    fn lowest_height(&self) -> Result<u32> {
+        self.inner.lowest_height()
+    }
+    fn rollback_count(&self) -> Result<u64> {
+        self.inner.rollback_count()
+    }
+    fn get_balance(&self, height: u32, address: H160) -> Result<U256> {
+        self.versioned(&self.balances, height, (height, address), || {
+            self.inner.get_balance(height, address)
+        })
+    }
+    fn get_nonce(&self, height: u32, address: H160) -> Result<U256> {
+        self.versioned(&self.nonces, height, (height, address), || {
+            self.inner.get_nonce(height, address)
+        })
+    }
+    fn get_byte_code(&self, height: u32, address: H160) -> Result<Vec<u8>> {
+        self.versioned(&self.byte_codes, height, (height, address), || {
+            self.inner.get_byte_code(height, address)
+        })
+    }
+    fn get_code_hash(&self, height: u32, address: H160) -> Result<Option<H256>> {
+        self.versioned(&self.code_hashes, height, (height, address), || {
+            self.inner.get_code_hash(height, address)
+        })
+    }
+    fn get_code_by_hash(&self, hash: H256) -> Result<Option<Vec<u8>>> {
+        self.codes
+            .get_or_load(hash, || self.inner.get_code_by_hash(hash))
+    }
+    fn get_account_basic(&self, height: u32, address: H160) -> Result<AccountBasic> {
+        Ok(AccountBasic {
+            balance: self.get_balance(height, address)?,
+            code: self.get_byte_code(height, address)?,
+            nonce: self.get_nonce(height, address)?,
+        })
+    }
+    fn addr_state_exists(&self, height: u32, address: H160) -> Result<bool> {
+        self.versioned(&self.state_exists, height, (height, address), || {
+            self.inner.addr_state_exists(height, address)
+        })
+    }
+    fn get_state(&self, height: u32, address: H160, index: H256) -> Result<H256> {
+        self.versioned(&self.states, height, (height, address, index), || {
+            self.inner.get_state(height, address, index)
+        })
+    }
+    fn get_balances(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        self.inner.get_balances(height, addresses)
+    }
+    fn get_nonces(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        self.inner.get_nonces(height, addresses)
+    }
+    fn get_code_hashes(&self, height: u32, addresses: &[H160]) -> Result<Vec<Option<H256>>> {
+        self.inner.get_code_hashes(height, addresses)
+    }
+    fn get_states(&self, height: u32, slots: &[(H160, H256)]) -> Result<Vec<H256>> {
+        self.inner.get_states(height, slots)
+    }
+    fn get_storage_range(
+        &self,
+        height: u32,
+        address: H160,
+        start: H256,
+        limit: usize,
+    ) -> Result<StorageRange> {
+        self.inner.get_storage_range(height, address, start, limit)
+    }
+    fn get_account_range(&self, height: u32, start: H160, limit: usize) -> Result<AccountRange> {
+        self.inner.get_account_range(height, start, limit)
+    }
+    fn get_block_hash_by_height(&self, height: U256) -> Result<Option<H256>> {
+        self.block_data(&self.block_hashes, height, || {
+            let block_hash = self.inner.get_block_hash_by_height(height)?;
+            Ok(block_hash.map(|block_hash| (block_height(height), block_hash)))
+        })
+    }
+    fn get_height_by_block_hash(&self, block_hash: H256) -> Result<Option<U256>> {
+        self.block_data(&self.block_heights, block_hash, || {
+            let height = self.inner.get_height_by_block_hash(block_hash)?;
+            Ok(height.map(|height| (block_height(height), height)))
+        })
+    }
+    fn get_block_by_hash(&self, block_hash: H256) -> Result<Option<Block>> {
+        self.block_data(&self.blocks, block_hash, || {
+            let block = self.inner.get_block_by_hash(block_hash)?;
+            Ok(block.map(|block| (block_height(block.header.number), block)))
+        })
+    }
+    fn get_transaction_receipt_by_block_hash(
+        &self,
+        block_hash: H256,
+    ) -> Result<Option<Vec<Receipt>>> {
+        self.block_data(&self.receipts, block_hash, || {
+            let receipts = self
+                .inner
+                .get_transaction_receipt_by_block_hash(block_hash)?;
+            self.at_block(block_hash, receipts)
+        })
+    }
+    fn get_transaction_status_by_block_hash(
+        &self,
+        block_hash: H256,
+    ) -> Result<Option<Vec<TransactionStatus>>> {
+        self.block_data(&self.statuses, block_hash, || {
+            let statuses = self
+                .inner
+                .get_transaction_status_by_block_hash(block_hash)?;
+            self.at_block(block_hash, statuses)
+        })
+    }
+    fn get_transaction_index_by_tx_hash(&self, tx_hash: H256) -> Result<Option<(H256, u32)>> {
+        self.block_data(&self.transaction_indexes, tx_hash, || {
+            match self.inner.get_transaction_index_by_tx_hash(tx_hash)? {
+                Some(index) => self.at_block(index.0, Some(index)),
+                None => Ok(None),
+            }
+        })
+    }
+    fn get_pending_balance(&self, address: H160) -> Result<Option<U256>> {
+        self.inner.get_pending_balance(address)
+    }
+    fn get_pending_nonce(&self, address: H160) -> Result<Option<U256>> {
+        self.inner.get_pending_nonce(address)
+    }
+    fn get_pending_byte_code(&self, address: H160) -> Result<Option<Vec<u8>>> {
+        self.inner.get_pending_byte_code(address)
+    }
+    fn get_pending_state(&self, address: H160, index: H256) -> Result<Option<H256>> {
+        self.inner.get_pending_state(address, index)
+    }
+    fn get_total_issuance(&self, height: u32) -> Result<U256> {
+        self.versioned(&self.total_issuances, height, height, || {
+            self.inner.get_total_issuance(height)
+        })
+    }
+    fn get_allowances(&self, height: u32, owner: H160, spender: H160) -> Result<U256> {
+        self.versioned(&self.allowances, height, (height, owner, spender), || {
+            self.inner.get_allowances(height, owner, spender)
+        })
+    }
+    fn get_logs(
+        &self,
+        from: u32,
+        to: u32,
+        addresses: &[H160],
+        topics: &[Option<Vec<H256>>],
+        limit: usize,
+    ) -> Result<Vec<IndexedLog>> {
+        self.inner.get_logs(from, to, addresses, topics, limit)
+    }
+    fn get_state_diff(&self, height: u32) -> Result<Option<StateDiff>> {
+        self.inner.get_state_diff(height)
+    }
+}
+
+#[cfg(all(test, feature = "memory"))]
+mod tests {
+    use {
+        super::CachingGetter,
+        crate::{ConnectionType, Getter, MemoryDb, MemoryGetter, MemorySetter, Setter},
+        primitive_types::{H160, H256, U256},
+        std::{num::NonZeroUsize, sync::Arc},
+    };
+
+    #[test]
+    fn test_caching_getter() {
+        let db = MemoryDb::new();
+        let getter = CachingGetter::new(
+            Arc::new(MemoryGetter::new(
+                ConnectionType::Memory(db.clone()),
+                String::new(),
+            )),
+            NonZeroUsize::new(16).unwrap(),
+        );
+        let setter = MemorySetter::new(ConnectionType::Memory(db), String::new());
+        let addr = H160::repeat_byte(8);
+
+        setter.set_balance(2, addr, U256::from(1)).unwrap();
+        setter.set_height(5).unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 5);
+        assert_eq!(getter.get_balance(4, addr).unwrap(), U256::from(1));
+        assert_eq!(getter.get_balance(4, addr).unwrap(), U256::from(1));
+        assert_eq!(getter.stats()["balance"].hits, 1);
+
+        // The latest height is never cached.
+        setter.set_balance(5, addr, U256::from(2)).unwrap();
+        assert_eq!(getter.get_balance(5, addr).unwrap(), U256::from(2));
+
+        // A lower latest height means a rollback and drops the caches.
+        setter.rollback_to(1).unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 1);
+        setter.set_balance(2, addr, U256::from(3)).unwrap();
+        setter.set_height(5).unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 5);
+        assert_eq!(getter.get_balance(4, addr).unwrap(), U256::from(3));
+
+        // So does a rollback whose re-export is back at the old height by
+        // the next poll.
+        setter.rollback_to(1).unwrap();
+        setter.set_balance(2, addr, U256::from(4)).unwrap();
+        setter.set_height(5).unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 5);
+        assert_eq!(getter.get_balance(4, addr).unwrap(), U256::from(4));
+    }
+
+    #[test]
+    fn test_block_data_after_rollback() {
+        let db = MemoryDb::new();
+        let getter = CachingGetter::new(
+            Arc::new(MemoryGetter::new(
+                ConnectionType::Memory(db.clone()),
+                String::new(),
+            )),
+            NonZeroUsize::new(16).unwrap(),
+        );
+        let setter = MemorySetter::new(ConnectionType::Memory(db.clone()), String::new());
+        let export = |height: u32, block_hash: H256| {
+            let mut data = db.write().unwrap();
+            data.block_hash.insert(U256::from(height), block_hash);
+            data.block_height.insert(block_hash, U256::from(height));
+            data.latest_height = height;
+        };
+        for height in 1..=3 {
+            export(height, H256::repeat_byte(height as u8));
+        }
+        assert_eq!(getter.latest_height().unwrap(), 3);
+
+        for _ in 0..2 {
+            assert_eq!(
+                getter.get_block_hash_by_height(U256::from(2)).unwrap(),
+                Some(H256::repeat_byte(2))
+            );
+            assert_eq!(
+                getter
+                    .get_height_by_block_hash(H256::repeat_byte(3))
+                    .unwrap(),
+                Some(U256::from(3))
+            );
+        }
+        // Blocks at the latest height are not cached.
+        assert_eq!(getter.stats()["block_hash"].hits, 1);
+        assert_eq!(getter.stats()["block_height"].hits, 0);
+
+        // Re-exported heights come from the store once the rollback is seen.
+        setter.rollback_to(1).unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 1);
+        assert_eq!(
+            getter
+                .get_height_by_block_hash(H256::repeat_byte(2))
+                .unwrap(),
+            None
+        );
+        export(2, H256::repeat_byte(0x22));
+        export(3, H256::repeat_byte(0x33));
+        assert_eq!(getter.latest_height().unwrap(), 3);
+        assert_eq!(
+            getter.get_block_hash_by_height(U256::from(2)).unwrap(),
+            Some(H256::repeat_byte(0x22))
+        );
+        assert_eq!(
+            getter
+                .get_height_by_block_hash(H256::repeat_byte(0x22))
+                .unwrap(),
+            Some(U256::from(2))
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Binary encoding of the block, receipts and statuses payloads.
+//!
+//! An encoded payload starts with a version byte and a flags byte, followed
+//! by the RLP body, zstd compressed when `FLAG_ZSTD` is set. Payloads written
+//! before this encoding are JSON and start with `{` or `[`, which no version
+//! byte does, so the decoders read both.
+
+use {
+    crate::{
+        error::{Error, Result},
+        Block, Receipt, TransactionStatus,
+    },
+    ethereum::{EnvelopedDecodable, EnvelopedDecoderError, EnvelopedEncodable},
+    rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream},
+    std::borrow::Cow,
+};
+
+pub const ENCODING_VERSION: u8 = 1;
+
+/// The body is zstd compressed.
+pub const FLAG_ZSTD: u8 = 0x01;
+
+/// Bodies shorter than this are stored uncompressed.
+#[cfg(feature = "zstd")]
+const COMPRESS_THRESHOLD: usize = 256;
+
+pub fn encode_block(block: &Block) -> Result<Vec<u8>> {
+    encode_payload(rlp::encode(block).to_vec())
+}
+
+pub fn decode_block(data: &[u8]) -> Result<Block> {
+    if is_json(data) {
+        return Ok(serde_json::from_slice(data)?);
+    }
+    Ok(rlp::decode(&decode_payload(data)?)?)
+}
+
+/// Receipts are typed, so each one is stored as its EIP-2718 envelope.
+pub fn encode_receipts(receipts: &[Receipt]) -> Result<Vec<u8>> {
+    let mut s = RlpStream::new_list(receipts.len());
+    for receipt in receipts {
+        s.append(&EnvelopedEncodable::encode(receipt).to_vec());
+    }
+    encode_payload(s.out().to_vec())
+}
+
+pub fn decode_receipts(data: &[u8]) -> Result<Vec<Receipt>> {
+    if is_json(data) {
+        return Ok(serde_json::from_slice(data)?);
+    }
+    let envelopes: Vec<Vec<u8>> = Rlp::new(&decode_payload(data)?).as_list()?;
+    envelopes
+        .iter()
+        .map(|envelope| {
+            <Receipt as EnvelopedDecodable>::decode(envelope).map_err(|e| match e {
+                EnvelopedDecoderError::UnknownTypeId => {
+                    DecoderError::Custom("Unknown receipt type").into()
+                }
+                EnvelopedDecoderError::Payload(e) => e.into(),
+            })
+        })
+        .collect()
+}
+
+pub fn encode_statuses(statuses: &[TransactionStatus]) -> Result<Vec<u8>> {
+    encode_payload(rlp::encode_list(statuses).to_vec())
+}
+
+pub fn decode_statuses(data: &[u8]) -> Result<Vec<TransactionStatus>> {
+    if is_json(data) {
+        return Ok(serde_json::from_slice(data)?);
+    }
+    Ok(Rlp::new(&decode_payload(data)?).as_list()?)
+}
+
+/// Whether `data` was written before the binary encoding.
+pub fn is_json(data: &[u8]) -> bool {
+    matches!(data.first(), Some(b'{' | b'['))
+}
+
+fn encode_payload(body: Vec<u8>) -> Result<Vec<u8>> {
+    #[cfg(feature = "zstd")]
+    if body.len() >= COMPRESS_THRESHOLD {
+        let compressed = zstd::encode_all(body.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)?;
+        if compressed.len() < body.len() {
+            return Ok(with_header(FLAG_ZSTD, compressed));
+        }
+    }
+    Ok(with_header(0, body))
+}
+
+fn with_header(flags: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(body.len() + 2);
+    payload.push(ENCODING_VERSION);
+    payload.push(flags);
+    payload.extend(body);
+    payload
+}
+
+fn decode_payload(data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let unsupported = |version, flags| Error::UnsupportedEncoding { version, flags };
+    match data {
+        [ENCODING_VERSION, 0, body @ ..] => Ok(Cow::Borrowed(body)),
+        #[cfg(feature = "zstd")]
+        [ENCODING_VERSION, FLAG_ZSTD, body @ ..] => Ok(Cow::Owned(zstd::decode_all(body)?)),
+        [version, flags, ..] => Err(unsupported(*version, *flags)),
+        _ => Err(unsupported(data.first().copied().unwrap_or_default(), 0)),
+    }
+}
+
+impl Encodable for TransactionStatus {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(7);
+        s.append(&self.transaction_hash);
+        s.append(&self.transaction_index);
+        s.append(&self.from);
+        match &self.to {
+            Some(to) => s.append(to),
+            None => s.append_empty_data(),
+        };
+        match &self.contract_address {
+            Some(address) => s.append(address),
+            None => s.append_empty_data(),
+        };
+        s.append_list(&self.logs);
+        s.append(&self.logs_bloom);
+    }
+}
+
+impl Decodable for TransactionStatus {
+    fn decode(rlp: &Rlp) -> std::result::Result<Self, DecoderError> {
+        if rlp.item_count()? != 7 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let optional = |i| {
+            let item = rlp.at(i)?;
+            if item.is_empty() {
+                Ok(None)
+            } else {
+                item.as_val().map(Some)
+            }
+        };
+        Ok(Self {
+            transaction_hash: rlp.val_at(0)?,
+            transaction_index: rlp.val_at(1)?,
+            from: rlp.val_at(2)?,
+            to: optional(3)?,
+            contract_address: optional(4)?,
+            logs: rlp.list_at(5)?,
+            logs_bloom: rlp.val_at(6)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{decode_statuses, encode_statuses},
+        crate::TransactionStatus,
+        ethereum::Log,
+        ethereum_types::Bloom,
+        primitive_types::{H160, H256},
+    };
+
+    #[test]
+    fn test_statuses_round_trip() {
+        let statuses = vec![TransactionStatus {
+            transaction_hash: H256::repeat_byte(1),
+            transaction_index: 3,
+            from: H160::repeat_byte(2),
+            to: None,
+            contract_address: Some(H160::repeat_byte(3)),
+            logs: vec![Log {
+                address: H160::repeat_byte(4),
+                topics: vec![H256::repeat_byte(5)],
+                data: vec![6; 300],
+            }],
+            logs_bloom: Bloom::repeat_byte(7),
+        }];
+        let json = serde_json::to_vec(&statuses).unwrap();
+
+        for data in [encode_statuses(&statuses).unwrap(), json] {
+            let decoded = decode_statuses(&data).unwrap();
+            assert_eq!(decoded.len(), 1);
+            assert_eq!(decoded[0].transaction_hash, statuses[0].transaction_hash);
+            assert_eq!(decoded[0].to, None);
+            assert_eq!(decoded[0].contract_address, statuses[0].contract_address);
+            assert_eq!(decoded[0].logs[0].data, statuses[0].logs[0].data);
+            assert_eq!(decoded[0].logs_bloom, statuses[0].logs_bloom);
+        }
+    }
+}
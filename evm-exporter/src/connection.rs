@@ -0,0 +1,149 @@
+use {
+    crate::{
+        error::{Error, Result},
+        Getter, Setter,
+    },
+    std::sync::Arc,
+};
+
+#[cfg(feature = "postgres")]
+use crate::{PgGetter, PgSetter};
+
+#[cfg(feature = "redis")]
+use crate::{RedisGetter, RedisSetter};
+
+#[cfg(feature = "redis-cluster")]
+use crate::{RedisClusterGetter, RedisClusterSetter};
+
+#[cfg(feature = "memory")]
+use crate::{MemoryDb, MemoryGetter, MemorySetter};
+
+#[cfg(feature = "rocksdb")]
+use crate::{RocksDb, RocksGetter, RocksSetter};
+
+pub enum ConnectionType {
+    #[cfg(feature = "redis")]
+    Redis(String),
+    #[cfg(feature = "redis-cluster")]
+    RedisCluster(Vec<String>),
+    #[cfg(feature = "postgres")]
+    Postgres(String),
+    #[cfg(feature = "memory")]
+    Memory(MemoryDb),
+    #[cfg(feature = "rocksdb")]
+    RocksDB(RocksDb),
+}
+
+impl ConnectionType {
+    /// Picks the backend from the URI scheme:
+    ///
+    /// - `postgres://` or `postgresql://`
+    /// - `redis://` or `rediss://`
+    /// - `redis+cluster://[user:pass@]host1:port1,host2:port2` (or `rediss+cluster://`)
+    /// - `memory://<name>`, every url with the same name shares one store
+    /// - `rocksdb://<path>`, or `rocksdb://<path>?secondary=<path>` to follow a
+    ///   store written by another process
+    ///
+    /// Redis urls may end with pool settings, see `RedisPoolOptions`.
+    pub fn from_url(url: &str) -> Result<Self> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| Error::UnsupportedUrl(url.to_string()))?;
+
+        match scheme {
+            #[cfg(feature = "postgres")]
+            "postgres" | "postgresql" => Ok(ConnectionType::Postgres(url.to_string())),
+            #[cfg(feature = "redis")]
+            "redis" | "rediss" => Ok(ConnectionType::Redis(url.to_string())),
+            #[cfg(feature = "redis-cluster")]
+            "redis+cluster" | "rediss+cluster" => {
+                let node_scheme = scheme.trim_end_matches("+cluster");
+                // Every node url gets the query, it holds the pool settings.
+                let (rest, query) = match rest.split_once('?') {
+                    Some((rest, query)) => (rest, format!("?{}", query)),
+                    None => (rest, String::new()),
+                };
+                let (auth, nodes) = match rest.rsplit_once('@') {
+                    Some((auth, nodes)) => (format!("{}@", auth), nodes),
+                    None => (String::new(), rest),
+                };
+                let urls = nodes
+                    .trim_end_matches('/')
+                    .split(',')
+                    .filter(|node| !node.is_empty())
+                    .map(|node| format!("{}://{}{}{}", node_scheme, auth, node, query))
+                    .collect::<Vec<_>>();
+                if urls.is_empty() {
+                    return Err(Error::UnsupportedUrl(url.to_string()));
+                }
+                Ok(ConnectionType::RedisCluster(urls))
+            }
+            #[cfg(feature = "memory")]
+            "memory" => Ok(ConnectionType::Memory(MemoryDb::shared(rest)?)),
+            #[cfg(feature = "rocksdb")]
+            "rocksdb" => match rest.split_once("?secondary=") {
+                Some((path, secondary_path)) => Ok(ConnectionType::RocksDB(
+                    RocksDb::open_secondary(path, secondary_path)?,
+                )),
+                None => Ok(ConnectionType::RocksDB(RocksDb::shared(rest)?)),
+            },
+            _ => Err(Error::UnsupportedUrl(url.to_string())),
+        }
+    }
+}
+
+pub fn open_getter(url: &str, prefix: &str) -> Result<Arc<dyn Getter + Send + Sync>> {
+    let prefix = prefix.to_string();
+    let getter: Arc<dyn Getter + Send + Sync> = match ConnectionType::from_url(url)? {
+        #[cfg(feature = "postgres")]
+        ConnectionType::Postgres(uri) => {
+            Arc::new(PgGetter::new(ConnectionType::Postgres(uri), prefix))
+        }
+        #[cfg(feature = "redis")]
+        ConnectionType::Redis(url) => {
+            Arc::new(RedisGetter::new(ConnectionType::Redis(url), prefix))
+        }
+        #[cfg(feature = "redis-cluster")]
+        ConnectionType::RedisCluster(urls) => Arc::new(RedisClusterGetter::new(
+            ConnectionType::RedisCluster(urls),
+            prefix,
+        )),
+        #[cfg(feature = "memory")]
+        ConnectionType::Memory(db) => {
+            Arc::new(MemoryGetter::new(ConnectionType::Memory(db), prefix))
+        }
+        #[cfg(feature = "rocksdb")]
+        ConnectionType::RocksDB(db) => {
+            Arc::new(RocksGetter::new(ConnectionType::RocksDB(db), prefix))
+        }
+    };
+    Ok(getter)
+}
+
+pub fn open_setter(url: &str, prefix: &str) -> Result<Arc<dyn Setter + Send + Sync>> {
+    let prefix = prefix.to_string();
+    let setter: Arc<dyn Setter + Send + Sync> = match ConnectionType::from_url(url)? {
+        #[cfg(feature = "postgres")]
+        ConnectionType::Postgres(uri) => {
+            Arc::new(PgSetter::new(ConnectionType::Postgres(uri), prefix))
+        }
+        #[cfg(feature = "redis")]
+        ConnectionType::Redis(url) => {
+            Arc::new(RedisSetter::new(ConnectionType::Redis(url), prefix))
+        }
+        #[cfg(feature = "redis-cluster")]
+        ConnectionType::RedisCluster(urls) => Arc::new(RedisClusterSetter::new(
+            ConnectionType::RedisCluster(urls),
+            prefix,
+        )),
+        #[cfg(feature = "memory")]
+        ConnectionType::Memory(db) => {
+            Arc::new(MemorySetter::new(ConnectionType::Memory(db), prefix))
+        }
+        #[cfg(feature = "rocksdb")]
+        ConnectionType::RocksDB(db) => {
+            Arc::new(RocksSetter::new(ConnectionType::RocksDB(db), prefix))
+        }
+    };
+    Ok(setter)
+}
@@ -2,7 +2,7 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[cfg(any(feature = "redis", feature = "redis-cluster"))]
+    #[cfg(feature = "redis")]
     #[error(transparent)]
     RedisError(#[from] redis::RedisError),
 
@@ -10,10 +10,28 @@ pub enum Error {
     #[error(transparent)]
     PostgresError(#[from] r2d2_postgres::postgres::Error),
 
-    #[cfg(feature = "postgres")]
+    #[cfg(any(feature = "postgres", feature = "redis"))]
     #[error(transparent)]
     R2D2PoolError(#[from] r2d2::Error),
 
+    #[cfg(feature = "async-postgres")]
+    #[error(transparent)]
+    DeadpoolError(#[from] deadpool_postgres::PoolError),
+
+    #[cfg(feature = "rocksdb")]
+    #[error(transparent)]
+    RocksDBError(#[from] rocksdb::Error),
+
+    #[error(transparent)]
+    RlpError(#[from] rlp::DecoderError),
+
+    #[cfg(feature = "zstd")]
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error("Unsupported payload encoding version {version} with flags {flags:#04x}")]
+    UnsupportedEncoding { version: u8, flags: u8 },
+
     #[error(transparent)]
     FromHexError(#[from] hex::FromHexError),
 
@@ -26,6 +44,20 @@ pub enum Error {
     #[error("Value not found")]
     ValueNotFound,
 
+    #[error("Unsupported storage url: {0}")]
+    UnsupportedUrl(String),
+
+    #[error("Unsupported key schema: {0}")]
+    UnsupportedKeySchema(String),
+
+    #[cfg(feature = "postgres")]
+    #[error("Postgres schema version {found} is older than the required {required}")]
+    SchemaTooOld { found: i64, required: i64 },
+
+    #[cfg(any(feature = "memory", feature = "rocksdb"))]
+    #[error("Store lock poisoned")]
+    LockPoisoned,
+
     #[error(transparent)]
     SerdeJsonError(#[from] serde_json::Error),
 
@@ -1,34 +1,123 @@
 use {
-    crate::{AccountBasic, Block, ConnectionType, Receipt, Result, TransactionStatus},
+    crate::{
+        decode_block, decode_receipts, decode_statuses, AccountBasic, AccountRange, AccountSummary,
+        Block, ConnectionType, IndexedLog, Receipt, Result, StateDiff, StorageRange,
+        TransactionStatus,
+    },
     primitive_types::{H160, H256, U256},
-    std::str::FromStr,
 };
 
+#[cfg(any(feature = "redis", feature = "rocksdb"))]
+use crate::keys;
+
+#[cfg(any(feature = "redis", feature = "rocksdb"))]
+use crate::code_hash;
+
+#[cfg(any(feature = "postgres", feature = "redis"))]
+use r2d2::Pool;
+
+#[cfg(feature = "memory")]
+use std::collections::BTreeMap;
+
+#[cfg(any(feature = "redis", feature = "memory", feature = "rocksdb"))]
+use std::collections::BTreeSet;
+
 #[cfg(feature = "redis")]
 use {
-    crate::keys,
-    redis::{Client as RedisClient, Commands},
-    redis_versioned_kv::VersionedKVCommand,
+    crate::Error,
+    crate::{
+        keys::{AccountKey, KeySchema, SchemaKey},
+        RedisPoolOptions,
+    },
+    redis::{Client as RedisClient, Cmd, Commands, ConnectionLike, FromRedisValue, Value},
+    redis_versioned_kv::{
+        ensure_loaded, vkv_get_cmd, with_script_reload, Codec, JsonCodec, VersionedKVCommand,
+        VersionedMap,
+    },
+    std::collections::VecDeque,
 };
 
 #[cfg(feature = "redis-cluster")]
-use redis::cluster::ClusterClient as RedisClusterClient;
+use redis::{
+    cluster::{ClusterClient as RedisClusterClient, ClusterConnection},
+    ConnectionAddr, ConnectionInfo, IntoConnectionInfo,
+};
 
 #[cfg(feature = "postgres")]
 use {
-    r2d2::Pool,
-    r2d2_postgres::{postgres::NoTls, PostgresConnectionManager},
+    crate::pg_types::{h160, h256, PgU256},
+    r2d2_postgres::{
+        postgres::{types::ToSql, NoTls, Row},
+        PostgresConnectionManager,
+    },
+    std::collections::HashMap,
 };
 
+#[cfg(feature = "memory")]
+use crate::memory::{versioned_get, MemoryDb, Versioned};
+
+#[cfg(feature = "rocksdb")]
+use {crate::RocksDb, rocksdb::IteratorMode, serde::de::DeserializeOwned};
+
 pub trait Getter {
     fn latest_height(&self) -> Result<u32>;
     fn lowest_height(&self) -> Result<u32>;
+    /// Number of rollbacks ever started on the store. Data read at heights
+    /// below the latest one stays valid while this does not change.
+    fn rollback_count(&self) -> Result<u64>;
     fn get_balance(&self, height: u32, address: H160) -> Result<U256>;
     fn get_nonce(&self, height: u32, address: H160) -> Result<U256>;
     fn get_byte_code(&self, height: u32, address: H160) -> Result<Vec<u8>>;
+    /// Keccak hash of the code of `address` at `height`, `None` when no code
+    /// was exported for it.
+    fn get_code_hash(&self, height: u32, address: H160) -> Result<Option<H256>>;
+    /// Code stored under its keccak hash, see `code_hash`.
+    fn get_code_by_hash(&self, hash: H256) -> Result<Option<Vec<u8>>>;
     fn get_account_basic(&self, height: u32, address: H160) -> Result<AccountBasic>;
     fn addr_state_exists(&self, height: u32, address: H160) -> Result<bool>;
     fn get_state(&self, height: u32, address: H160, index: H256) -> Result<H256>;
+    /// `get_balance` of each of `addresses`, in their order. The backends
+    /// served over the network read them in one round trip.
+    fn get_balances(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        addresses
+            .iter()
+            .map(|address| self.get_balance(height, *address))
+            .collect()
+    }
+    /// `get_nonce` of each of `addresses`, see `get_balances`.
+    fn get_nonces(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        addresses
+            .iter()
+            .map(|address| self.get_nonce(height, *address))
+            .collect()
+    }
+    /// `get_code_hash` of each of `addresses`, see `get_balances`.
+    fn get_code_hashes(&self, height: u32, addresses: &[H160]) -> Result<Vec<Option<H256>>> {
+        addresses
+            .iter()
+            .map(|address| self.get_code_hash(height, *address))
+            .collect()
+    }
+    /// `get_state` of each of `slots`, see `get_balances`.
+    fn get_states(&self, height: u32, slots: &[(H160, H256)]) -> Result<Vec<H256>> {
+        slots
+            .iter()
+            .map(|(address, index)| self.get_state(height, *address, *index))
+            .collect()
+    }
+    /// Storage of `address` at `height` from the slot `start` on, at most
+    /// `limit` slots of it. Slots whose value is zero are left out.
+    fn get_storage_range(
+        &self,
+        height: u32,
+        address: H160,
+        start: H256,
+        limit: usize,
+    ) -> Result<StorageRange>;
+    /// Accounts known at `height` from the address `start` on, at most `limit`
+    /// of them. An account is known once a balance, nonce or code was exported
+    /// for it.
+    fn get_account_range(&self, height: u32, start: H160, limit: usize) -> Result<AccountRange>;
     fn get_block_hash_by_height(&self, height: U256) -> Result<Option<H256>>;
     fn get_height_by_block_hash(&self, block_hash: H256) -> Result<Option<U256>>;
     fn get_block_by_hash(&self, block_hash: H256) -> Result<Option<Block>>;
@@ -47,6 +136,19 @@ pub trait Getter {
     fn get_pending_state(&self, address: H160, index: H256) -> Result<Option<H256>>;
     fn get_total_issuance(&self, height: u32) -> Result<U256>;
     fn get_allowances(&self, height: u32, owner: H160, spender: H160) -> Result<U256>;
+    /// Logs of the blocks `from..=to` matching `addresses` and `topics` (see
+    /// `IndexedLog::matches`), in chain order and at most `limit` of them.
+    fn get_logs(
+        &self,
+        from: u32,
+        to: u32,
+        addresses: &[H160],
+        topics: &[Option<Vec<H256>>],
+        limit: usize,
+    ) -> Result<Vec<IndexedLog>>;
+    /// Accounts changed by the block at `height`, `None` when no state diff
+    /// was recorded for it, see `BlockWriter::record_state_diff`.
+    fn get_state_diff(&self, height: u32) -> Result<Option<StateDiff>>;
 }
 
 #[cfg(feature = "postgres")]
@@ -63,11 +165,115 @@ impl PgGetter {
                 NoTls,
             );
             let pool = r2d2::Pool::new(manager).expect("new postgres connection pool failed");
+            crate::migrations::check_schema(
+                &mut pool.get().expect("get postgres connection failed"),
+            )
+            .expect("postgres schema is not up to date, run the exporter to migrate it");
             Self { conn: pool }
         } else {
             panic!("Invalid connection type for Postgres")
         }
     }
+
+    /// Latest row at `height` of each of `addresses` in the account table
+    /// `table`, read in one query and keyed by address.
+    fn latest_by_address(
+        &self,
+        table: &str,
+        height: u32,
+        addresses: &[H160],
+    ) -> Result<HashMap<H160, Row>> {
+        if addresses.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let addresses = addresses
+            .iter()
+            .map(|address| address.as_bytes())
+            .collect::<Vec<_>>();
+        self.conn
+            .get()?
+            .query(
+                &format!(
+                    "SELECT DISTINCT ON (address) * FROM {} WHERE address = ANY($1) AND height <= $2 ORDER BY address, height DESC",
+                    table
+                ),
+                &[&addresses, &(height as i64)],
+            )?
+            .into_iter()
+            .map(|row| Ok((h160(row.get("address"))?, row)))
+            .collect()
+    }
+}
+
+#[cfg(feature = "postgres")]
+pub(crate) fn pg_row_to_log(row: &Row) -> Result<IndexedLog> {
+    let topics = ["topic0", "topic1", "topic2", "topic3"]
+        .iter()
+        .map_while(|column| row.get::<_, Option<&[u8]>>(*column))
+        .map(h256)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(IndexedLog {
+        block_number: row.get::<_, i64>("block_number") as u32,
+        block_hash: h256(row.get("block_hash"))?,
+        transaction_hash: h256(row.get("transaction_hash"))?,
+        transaction_index: row.get::<_, i64>("transaction_index") as u32,
+        log_index: row.get::<_, i64>("log_index") as u32,
+        transaction_log_index: row.get::<_, i64>("transaction_log_index") as u32,
+        address: h160(row.get("address"))?,
+        topics,
+        data: row.get("data"),
+    })
+}
+
+/// Query of `get_logs` and its parameters, `None` when nothing can match.
+#[cfg(feature = "postgres")]
+pub(crate) fn pg_logs_query(
+    from: u32,
+    to: u32,
+    addresses: &[H160],
+    topics: &[Option<Vec<H256>>],
+    limit: usize,
+) -> Option<(String, Vec<Box<dyn ToSql + Send + Sync>>)> {
+    // Logs carry at most 4 topics, so a fifth restricted position matches nothing.
+    if topics
+        .iter()
+        .skip(4)
+        .any(|wanted| matches!(wanted, Some(w) if !w.is_empty()))
+    {
+        return None;
+    }
+
+    let mut sql =
+        String::from("SELECT * FROM logs WHERE block_number >= $1 AND block_number <= $2");
+    let mut params: Vec<Box<dyn ToSql + Send + Sync>> =
+        vec![Box::new(from as i64), Box::new(to as i64)];
+    if !addresses.is_empty() {
+        params.push(Box::new(
+            addresses
+                .iter()
+                .map(|address| address.as_bytes().to_vec())
+                .collect::<Vec<_>>(),
+        ));
+        sql.push_str(&format!(" AND address = ANY(${})", params.len()));
+    }
+    for (i, wanted) in topics.iter().enumerate() {
+        if let Some(wanted) = wanted.as_ref().filter(|w| !w.is_empty()) {
+            params.push(Box::new(
+                wanted
+                    .iter()
+                    .map(|topic| topic.as_bytes().to_vec())
+                    .collect::<Vec<_>>(),
+            ));
+            sql.push_str(&format!(" AND topic{} = ANY(${})", i, params.len()));
+        }
+    }
+    params.push(Box::new(limit as i64));
+    sql.push_str(&format!(
+        " ORDER BY block_number, log_index LIMIT ${}",
+        params.len()
+    ));
+
+    Some((sql, params))
 }
 
 #[cfg(feature = "postgres")]
@@ -86,39 +292,66 @@ impl Getter for PgGetter {
             .query_one("SELECT lowest_height FROM common", &[])?
             .get::<&str, i64>("lowest_height") as u32)
     }
+    fn rollback_count(&self) -> Result<u64> {
+        Ok(self
+            .conn
+            .get()?
+            .query_one("SELECT rollbacks FROM common", &[])?
+            .get::<&str, i64>("rollbacks") as u64)
+    }
     fn get_balance(&self, height: u32, address: H160) -> Result<U256> {
-        match self.conn.get()?.query_one(
-            "SELECT balance FROM balance WHERE address = $1 AND height <= $2 ORDER BY height DESC LIMIT 1",
-            &[&format!("{:?}", address).to_lowercase(), &(height as i64)],
-        ) {
-            Ok(row) => {
-                let b = row.try_get("balance")?;
-                Ok(U256::from_str_radix(b, 10)?)
-            }
-            _ => Ok(U256::zero()),
-        }
+        Ok(self
+            .conn
+            .get()?
+            .query_opt(
+                "SELECT balance FROM balance WHERE address = $1 AND height <= $2 ORDER BY height DESC LIMIT 1",
+                &[&address.as_bytes(), &(height as i64)],
+            )?
+            .map(|row| row.try_get::<_, PgU256>("balance"))
+            .transpose()?
+            .map(|balance| balance.0)
+            .unwrap_or_default())
     }
     fn get_nonce(&self, height: u32, address: H160) -> Result<U256> {
-        Ok(U256::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT nonce FROM nonce WHERE address = $1 AND height <= $2 ORDER BY height DESC LIMIT 1",
-                    &[&format!("{:?}", address).to_lowercase(), &(height as i64)],
-                )?
-                .get("nonce"),
-        )?)
+        Ok(self
+            .conn
+            .get()?
+            .query_opt(
+                "SELECT nonce FROM nonce WHERE address = $1 AND height <= $2 ORDER BY height DESC LIMIT 1",
+                &[&address.as_bytes(), &(height as i64)],
+            )?
+            .map(|row| row.get::<_, PgU256>("nonce").0)
+            .unwrap_or_default())
     }
     fn get_byte_code(&self, height: u32, address: H160) -> Result<Vec<u8>> {
-        Ok(hex::decode::<Vec<u8>>(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT code FROM byte_code WHERE address = $1 AND height <= $2 ORDER BY height DESC LIMIT 1",
-                    &[&format!("{:?}", address).to_lowercase(), &(height as i64)],
-                )?
-                .get("code"),
-        )?)
+        Ok(self
+            .conn
+            .get()?
+            .query_opt(
+                "SELECT code.code FROM byte_code JOIN code ON code.hash = byte_code.code_hash \
+                 WHERE byte_code.address = $1 AND byte_code.height <= $2 \
+                 ORDER BY byte_code.height DESC LIMIT 1",
+                &[&address.as_bytes(), &(height as i64)],
+            )?
+            .map(|row| row.get("code"))
+            .unwrap_or_default())
+    }
+    fn get_code_hash(&self, height: u32, address: H160) -> Result<Option<H256>> {
+        self.conn
+            .get()?
+            .query_opt(
+                "SELECT code_hash FROM byte_code WHERE address = $1 AND height <= $2 ORDER BY height DESC LIMIT 1",
+                &[&address.as_bytes(), &(height as i64)],
+            )?
+            .map(|row| h256(row.get("code_hash")))
+            .transpose()
+    }
+    fn get_code_by_hash(&self, hash: H256) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .conn
+            .get()?
+            .query_opt("SELECT code FROM code WHERE hash = $1", &[&hash.as_bytes()])?
+            .map(|row| row.get("code")))
     }
     fn get_account_basic(&self, height: u32, address: H160) -> Result<AccountBasic> {
         Ok(AccountBasic {
@@ -128,176 +361,320 @@ impl Getter for PgGetter {
         })
     }
     fn addr_state_exists(&self, height: u32, address: H160) -> Result<bool> {
-        Ok(!self
+        Ok(self
             .conn
             .get()?
-            .query_one(
+            .query_opt(
                 "SELECT 1 FROM state WHERE address = $1 AND height <= $2 ORDER BY height DESC LIMIT 1",
-                &[&format!("{:?}", address).to_lowercase(), &(height as i64)],
+                &[&address.as_bytes(), &(height as i64)],
             )?
-            .is_empty())
+            .is_some())
     }
     fn get_state(&self, height: u32, address: H160, index: H256) -> Result<H256> {
-        Ok(H256::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT value FROM state WHERE idx = $1 AND address = $2 AND height <= $3 ORDER BY height DESC LIMIT 1",
-                    &[
-                        &format!("{:?}", index),
-                        &format!("{:?}", address).to_lowercase(),
-                        &(height as i64),
-                    ],
-                )?
-                .get("value"),
-        )?)
+        self.conn
+            .get()?
+            .query_opt(
+                "SELECT value FROM state WHERE address = $1 AND idx = $2 AND height <= $3 ORDER BY height DESC LIMIT 1",
+                &[&address.as_bytes(), &index.as_bytes(), &(height as i64)],
+            )?
+            .map_or(Ok(H256::zero()), |row| h256(row.get("value")))
+    }
+    fn get_balances(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        let mut rows = self.latest_by_address("balance", height, addresses)?;
+        addresses
+            .iter()
+            .map(|address| match rows.remove(address) {
+                Some(row) => Ok(row.try_get::<_, PgU256>("balance")?.0),
+                None => Ok(U256::zero()),
+            })
+            .collect()
+    }
+    fn get_nonces(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        let mut rows = self.latest_by_address("nonce", height, addresses)?;
+        addresses
+            .iter()
+            .map(|address| match rows.remove(address) {
+                Some(row) => Ok(row.try_get::<_, PgU256>("nonce")?.0),
+                None => Ok(U256::zero()),
+            })
+            .collect()
+    }
+    fn get_code_hashes(&self, height: u32, addresses: &[H160]) -> Result<Vec<Option<H256>>> {
+        let rows = self.latest_by_address("byte_code", height, addresses)?;
+        addresses
+            .iter()
+            .map(|address| {
+                rows.get(address)
+                    .map(|row| h256(row.get("code_hash")))
+                    .transpose()
+            })
+            .collect()
+    }
+    fn get_states(&self, height: u32, slots: &[(H160, H256)]) -> Result<Vec<H256>> {
+        if slots.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (addresses, indexes): (Vec<_>, Vec<_>) = slots
+            .iter()
+            .map(|(address, index)| (address.as_bytes(), index.as_bytes()))
+            .unzip();
+        let mut values = HashMap::new();
+        for row in self.conn.get()?.query(
+            "SELECT DISTINCT ON (state.address, state.idx) state.address, state.idx, state.value \
+             FROM state JOIN unnest($1::bytea[], $2::bytea[]) AS slot(address, idx) \
+             ON state.address = slot.address AND state.idx = slot.idx \
+             WHERE state.height <= $3 ORDER BY state.address, state.idx, state.height DESC",
+            &[&addresses, &indexes, &(height as i64)],
+        )? {
+            values.insert(
+                (h160(row.get("address"))?, h256(row.get("idx"))?),
+                h256(row.get("value"))?,
+            );
+        }
+        Ok(slots
+            .iter()
+            .map(|slot| values.get(slot).copied().unwrap_or_default())
+            .collect())
+    }
+    fn get_storage_range(
+        &self,
+        height: u32,
+        address: H160,
+        start: H256,
+        limit: usize,
+    ) -> Result<StorageRange> {
+        let rows = self.conn.get()?.query(
+            "SELECT idx, value FROM (SELECT DISTINCT ON (idx) idx, value FROM state WHERE address = $1 AND idx >= $2 AND height <= $3 ORDER BY idx, height DESC) latest WHERE value <> $4 ORDER BY idx LIMIT $5",
+            &[
+                &address.as_bytes(),
+                &start.as_bytes(),
+                &(height as i64),
+                &H256::zero().as_bytes(),
+                &i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX),
+            ],
+        )?;
+        let slots = rows
+            .iter()
+            .map(|row| Ok((h256(row.get("idx"))?, h256(row.get("value"))?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(StorageRange::from_sorted(slots, limit))
+    }
+    fn get_account_range(&self, height: u32, start: H160, limit: usize) -> Result<AccountRange> {
+        let rows = self.conn.get()?.query(
+            "SELECT a.address, \
+             (SELECT balance FROM balance WHERE address = a.address AND height <= $2 ORDER BY height DESC LIMIT 1) AS balance, \
+             (SELECT nonce FROM nonce WHERE address = a.address AND height <= $2 ORDER BY height DESC LIMIT 1) AS nonce, \
+             (SELECT code_hash FROM byte_code WHERE address = a.address AND height <= $2 ORDER BY height DESC LIMIT 1) AS code_hash \
+             FROM (SELECT address FROM balance WHERE address >= $1 AND height <= $2 \
+             UNION SELECT address FROM nonce WHERE address >= $1 AND height <= $2 \
+             UNION SELECT address FROM byte_code WHERE address >= $1 AND height <= $2) a \
+             ORDER BY a.address LIMIT $3",
+            &[
+                &start.as_bytes(),
+                &(height as i64),
+                &i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX),
+            ],
+        )?;
+        let accounts = rows
+            .iter()
+            .map(|row| {
+                let summary = AccountSummary {
+                    balance: row
+                        .get::<_, Option<PgU256>>("balance")
+                        .unwrap_or(PgU256(U256::zero()))
+                        .0,
+                    nonce: row
+                        .get::<_, Option<PgU256>>("nonce")
+                        .unwrap_or(PgU256(U256::zero()))
+                        .0,
+                    code_hash: row
+                        .get::<_, Option<&[u8]>>("code_hash")
+                        .map(h256)
+                        .transpose()?,
+                };
+                Ok((h160(row.get("address"))?, summary))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(AccountRange::from_sorted(accounts, limit))
     }
     fn get_block_hash_by_height(&self, height: U256) -> Result<Option<H256>> {
-        Ok(Some(H256::from_str(
+        Ok(Some(h256(
             self.conn
                 .get()?
                 .query_one(
                     "SELECT block_hash FROM block_info WHERE block_height = $1",
-                    &[&format!("{:?}", height)],
+                    &[&(height.as_u64() as i64)],
                 )?
                 .get("block_hash"),
         )?))
     }
     fn get_height_by_block_hash(&self, block_hash: H256) -> Result<Option<U256>> {
-        Ok(Some(U256::from_str(
+        Ok(Some(U256::from(
             self.conn
                 .get()?
                 .query_one(
                     "SELECT block_height FROM block_info WHERE block_hash = $1",
-                    &[&format!("{:?}", block_hash)],
+                    &[&block_hash.as_bytes()],
                 )?
-                .get("block_height"),
-        )?))
+                .get::<_, i64>("block_height"),
+        )))
     }
     fn get_block_by_hash(&self, block_hash: H256) -> Result<Option<Block>> {
-        Ok(Some(serde_json::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT block FROM block_info WHERE block_hash = $1",
-                    &[&format!("{:?}", block_hash)],
-                )?
-                .get("block"),
-        )?))
+        let block: Vec<u8> = self
+            .conn
+            .get()?
+            .query_one(
+                "SELECT block FROM block_info WHERE block_hash = $1",
+                &[&block_hash.as_bytes()],
+            )?
+            .get("block");
+        Ok(Some(decode_block(&block)?))
     }
     fn get_transaction_receipt_by_block_hash(
         &self,
         block_hash: H256,
     ) -> Result<Option<Vec<Receipt>>> {
-        Ok(Some(serde_json::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT receipt FROM block_info WHERE block_hash = $1",
-                    &[&format!("{:?}", block_hash)],
-                )?
-                .get("receipt"),
-        )?))
+        let receipt: Vec<u8> = self
+            .conn
+            .get()?
+            .query_one(
+                "SELECT receipt FROM block_info WHERE block_hash = $1",
+                &[&block_hash.as_bytes()],
+            )?
+            .get("receipt");
+        Ok(Some(decode_receipts(&receipt)?))
     }
     fn get_transaction_status_by_block_hash(
         &self,
         block_hash: H256,
     ) -> Result<Option<Vec<TransactionStatus>>> {
-        Ok(Some(serde_json::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT statuses FROM block_info WHERE block_hash = $1",
-                    &[&format!("{:?}", block_hash)],
-                )?
-                .get("statuses"),
-        )?))
+        let statuses: Vec<u8> = self
+            .conn
+            .get()?
+            .query_one(
+                "SELECT statuses FROM block_info WHERE block_hash = $1",
+                &[&block_hash.as_bytes()],
+            )?
+            .get("statuses");
+        Ok(Some(decode_statuses(&statuses)?))
     }
     fn get_transaction_index_by_tx_hash(&self, tx_hash: H256) -> Result<Option<(H256, u32)>> {
-        Ok(Some(serde_json::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT transaction_index FROM transactions WHERE transaction_hash = $1",
-                    &[&format!("{:?}", tx_hash)],
-                )?
-                .get("transaction_index"),
-        )?))
+        let row = self.conn.get()?.query_one(
+            "SELECT block_hash, transaction_index FROM transactions WHERE transaction_hash = $1",
+            &[&tx_hash.as_bytes()],
+        )?;
+        Ok(Some((
+            h256(row.get("block_hash"))?,
+            row.get::<_, i64>("transaction_index") as u32,
+        )))
     }
     fn get_pending_balance(&self, address: H160) -> Result<Option<U256>> {
-        Ok(Some(U256::from_str(
+        Ok(Some(
             self.conn
                 .get()?
                 .query_one(
                     "SELECT pending_balance FROM pending_transactions WHERE sign_address = $1",
-                    &[&format!("{:?}", address).to_lowercase()],
+                    &[&address.as_bytes()],
                 )?
-                .get("pending_balance"),
-        )?))
+                .get::<_, PgU256>("pending_balance")
+                .0,
+        ))
     }
     fn get_pending_nonce(&self, address: H160) -> Result<Option<U256>> {
-        Ok(Some(U256::from_str(
+        Ok(Some(
             self.conn
                 .get()?
                 .query_one(
                     "SELECT pending_nonce FROM pending_transactions WHERE sign_address = $1",
-                    &[&format!("{:?}", address).to_lowercase()],
+                    &[&address.as_bytes()],
                 )?
-                .get("pending_nonce"),
-        )?))
+                .get::<_, PgU256>("pending_nonce")
+                .0,
+        ))
     }
     fn get_pending_byte_code(&self, address: H160) -> Result<Option<Vec<u8>>> {
-        Ok(Some(serde_json::from_str(
+        Ok(Some(
             self.conn
                 .get()?
                 .query_one(
                     "SELECT code FROM pending_byte_code WHERE address = $1",
-                    &[&format!("{:?}", address).to_lowercase()],
+                    &[&address.as_bytes()],
                 )?
                 .get("code"),
-        )?))
+        ))
     }
     fn get_pending_state(&self, address: H160, index: H256) -> Result<Option<H256>> {
-        Ok(Some(H256::from_str(
+        Ok(Some(h256(
             self.conn
                 .get()?
                 .query_one(
                     "SELECT value FROM pending_state WHERE address = $1 AND idx = $2",
-                    &[
-                        &format!("{:?}", address).to_lowercase(),
-                        &format!("{:?}", index),
-                    ],
+                    &[&address.as_bytes(), &index.as_bytes()],
                 )?
                 .get("value"),
         )?))
     }
     fn get_total_issuance(&self, height: u32) -> Result<U256> {
-        Ok(U256::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT value FROM issuance WHERE height <= $1 ORDER BY height DESC LIMIT 1",
-                    &[&(height as i64)],
-                )?
-                .get("value"),
-        )?)
+        Ok(self
+            .conn
+            .get()?
+            .query_one(
+                "SELECT value FROM issuance WHERE height <= $1 ORDER BY height DESC LIMIT 1",
+                &[&(height as i64)],
+            )?
+            .get::<_, PgU256>("value")
+            .0)
     }
     fn get_allowances(&self, height: u32, owner: H160, spender: H160) -> Result<U256> {
-        Ok(U256::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT value FROM allowances WHERE owner = $1 AND spender = $2 AND height <= $3 ORDER BY height DESC LIMIT 1", 
-                    &[&format!("{:?}", owner).to_lowercase(), &format!("{:?}", spender).to_lowercase(), &( height as i64 )],
-                )?
-                .get("value"),
-        )?)
+        Ok(self
+            .conn
+            .get()?
+            .query_one(
+                "SELECT value FROM allowances WHERE owner = $1 AND spender = $2 AND height <= $3 ORDER BY height DESC LIMIT 1",
+                &[&owner.as_bytes(), &spender.as_bytes(), &(height as i64)],
+            )?
+            .get::<_, PgU256>("value")
+            .0)
+    }
+    fn get_logs(
+        &self,
+        from: u32,
+        to: u32,
+        addresses: &[H160],
+        topics: &[Option<Vec<H256>>],
+        limit: usize,
+    ) -> Result<Vec<IndexedLog>> {
+        let (sql, params) = match pg_logs_query(from, to, addresses, topics, limit) {
+            Some(query) => query,
+            None => return Ok(vec![]),
+        };
+        let params = params
+            .iter()
+            .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+            .collect::<Vec<_>>();
+        self.conn
+            .get()?
+            .query(sql.as_str(), &params)?
+            .iter()
+            .map(pg_row_to_log)
+            .collect()
+    }
+    fn get_state_diff(&self, height: u32) -> Result<Option<StateDiff>> {
+        self.conn
+            .get()?
+            .query_opt(
+                "SELECT diff FROM state_diff WHERE height = $1",
+                &[&(height as i64)],
+            )?
+            .map(|row| Ok(serde_json::from_slice(row.get("diff"))?))
+            .transpose()
     }
 }
 
 #[cfg(feature = "redis")]
 pub struct RedisGetter {
-    conn: RedisClient,
+    conn: Pool<RedisClient>,
+    accounts: RedisAccounts,
     pub prefix: String,
 }
 
@@ -305,8 +682,21 @@ pub struct RedisGetter {
 impl RedisGetter {
     pub fn new(connection: ConnectionType, prefix: String) -> Self {
         if let ConnectionType::Redis(url) = connection {
+            let (url, options) = RedisPoolOptions::from_url(&url).expect("parse redis url failed");
+            let client = RedisClient::open(url).expect("Connect to Redis failed");
+            let conn = options
+                .build(client)
+                .expect("new redis connection pool failed");
+            ensure_loaded(&mut *conn.get().expect("get redis connection failed"))
+                .expect("load versioned-kv library failed");
+            let schema = redis_key_schema(
+                &mut *conn.get().expect("get redis connection failed"),
+                &prefix,
+            )
+            .expect("read redis key schema failed");
             Self {
-                conn: RedisClient::open(url).expect("Connect to Redis failed"),
+                conn,
+                accounts: RedisAccounts::new(&prefix, schema),
                 prefix,
             }
         } else {
@@ -315,11 +705,603 @@ impl RedisGetter {
     }
 }
 
+/// Blocks whose logs one `RedisLogsQuery` step reads.
+#[cfg(feature = "redis")]
+const REDIS_LOGS_PAGE: usize = 100;
+
+/// `get_logs` over the Redis logs index, driven the same way on sync and
+/// async connections: run the pipeline of `next_cmds` and hand its reply to
+/// `advance`, until that returns the logs.
+///
+/// The first step narrows the heights with the address and topic indexes,
+/// every later one filters the logs of `REDIS_LOGS_PAGE` of those heights.
+#[cfg(feature = "redis")]
+pub(crate) struct RedisLogsQuery<'a> {
+    prefix: &'a str,
+    from: u32,
+    to: u32,
+    addresses: &'a [H160],
+    topics: &'a [Option<Vec<H256>>],
+    limit: usize,
+    /// Candidate heights whose logs were not read yet, `None` until the
+    /// indexes are.
+    heights: Option<VecDeque<u32>>,
+    logs: Vec<IndexedLog>,
+}
+
+#[cfg(feature = "redis")]
+impl<'a> RedisLogsQuery<'a> {
+    pub(crate) fn new(
+        prefix: &'a str,
+        from: u32,
+        to: u32,
+        addresses: &'a [H160],
+        topics: &'a [Option<Vec<H256>>],
+        limit: usize,
+    ) -> Self {
+        Self {
+            prefix,
+            from,
+            to,
+            addresses,
+            topics,
+            limit,
+            heights: None,
+            logs: Vec::new(),
+        }
+    }
+
+    /// Index keys narrowing the heights: a height has to be in one of the
+    /// keys of every group.
+    fn filters(&self) -> Vec<Vec<String>> {
+        let mut filters = Vec::new();
+        if !self.addresses.is_empty() {
+            filters.push(
+                self.addresses
+                    .iter()
+                    .map(|address| keys::logs_address_key(self.prefix, *address))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        for (i, wanted) in self.topics.iter().enumerate() {
+            if let Some(wanted) = wanted.as_ref().filter(|w| !w.is_empty()) {
+                filters.push(
+                    wanted
+                        .iter()
+                        .map(|topic| keys::logs_topic_key(self.prefix, i, *topic))
+                        .collect(),
+                );
+            }
+        }
+        if filters.is_empty() {
+            filters.push(vec![keys::logs_heights_key(self.prefix)]);
+        }
+        filters
+    }
+
+    /// Commands of the next step, never empty.
+    pub(crate) fn next_cmds(&mut self) -> Vec<Cmd> {
+        match &mut self.heights {
+            None => self
+                .filters()
+                .into_iter()
+                .flatten()
+                .map(|key| {
+                    let mut cmd = redis::cmd("ZRANGEBYSCORE");
+                    cmd.arg(key).arg(self.from).arg(self.to);
+                    cmd
+                })
+                .collect(),
+            Some(heights) => {
+                let page = heights.len().min(REDIS_LOGS_PAGE);
+                heights
+                    .drain(..page)
+                    .map(|height| Cmd::get(keys::logs_key(self.prefix, height)))
+                    .collect()
+            }
+        }
+    }
+
+    /// Takes the reply to the pipeline of `next_cmds`, returning the logs
+    /// once the query is done.
+    pub(crate) fn advance(&mut self, reply: Vec<Value>) -> Result<Option<Vec<IndexedLog>>> {
+        let mut reply = reply.into_iter();
+        if self.heights.is_none() {
+            let mut heights: Option<BTreeSet<u32>> = None;
+            for group in self.filters() {
+                let mut matched = BTreeSet::new();
+                for _ in group {
+                    let found: Vec<u32> = match reply.next() {
+                        Some(value) => redis::from_redis_value(&value)?,
+                        None => vec![],
+                    };
+                    matched.extend(found);
+                }
+                heights = Some(match heights {
+                    Some(heights) => heights.intersection(&matched).copied().collect(),
+                    None => matched,
+                });
+            }
+            self.heights = Some(heights.unwrap_or_default().into_iter().collect());
+        } else {
+            for value in reply {
+                if let Some(value) = redis::from_redis_value::<Option<String>>(&value)? {
+                    let block_logs: Vec<IndexedLog> = serde_json::from_str(&value)?;
+                    self.logs.extend(
+                        block_logs
+                            .into_iter()
+                            .filter(|log| log.matches(self.addresses, self.topics)),
+                    );
+                }
+                if self.logs.len() >= self.limit {
+                    self.logs.truncate(self.limit);
+                    return Ok(Some(std::mem::take(&mut self.logs)));
+                }
+            }
+        }
+        match &self.heights {
+            Some(heights) if heights.is_empty() => Ok(Some(std::mem::take(&mut self.logs))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// `RedisLogsQuery` over a blocking connection.
+#[cfg(feature = "redis")]
+fn redis_get_logs<C: RedisPipelined>(
+    conn: &mut C,
+    prefix: &str,
+    from: u32,
+    to: u32,
+    addresses: &[H160],
+    topics: &[Option<Vec<H256>>],
+    limit: usize,
+) -> Result<Vec<IndexedLog>> {
+    let mut query = RedisLogsQuery::new(prefix, from, to, addresses, topics, limit);
+    loop {
+        let reply = conn.query_pipelined(query.next_cmds())?;
+        if let Some(logs) = query.advance(reply)? {
+            return Ok(logs);
+        }
+    }
+}
+
+/// Layout recorded under `key_schema_key`, `Flat` when there is none.
+#[cfg(feature = "redis")]
+pub(crate) fn redis_key_schema<C: ConnectionLike>(conn: &mut C, prefix: &str) -> Result<KeySchema> {
+    let version: Option<u32> = conn.get(keys::key_schema_key(prefix))?;
+    Ok(version
+        .map(KeySchema::from_version)
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Direct clients for every master of the cluster behind `conn`, whose node
+/// urls are `urls`. Credentials and TLS settings are taken from the first one.
+#[cfg(feature = "redis-cluster")]
+pub(crate) fn redis_cluster_masters<C: ConnectionLike>(
+    conn: &mut C,
+    urls: &[String],
+) -> Result<Vec<RedisClient>> {
+    let template = urls[0].as_str().into_connection_info()?;
+
+    let slots: redis::Value = redis::cmd("CLUSTER").arg("SLOTS").query(conn)?;
+    let mut addrs = BTreeSet::new();
+    if let redis::Value::Bulk(ranges) = slots {
+        for range in ranges {
+            if let redis::Value::Bulk(items) = range {
+                if let Some(redis::Value::Bulk(master)) = items.get(2) {
+                    if let [host, port, ..] = master.as_slice() {
+                        let host: String = redis::from_redis_value(host)?;
+                        let port: u16 = redis::from_redis_value(port)?;
+                        addrs.insert((host, port));
+                    }
+                }
+            }
+        }
+    }
+
+    addrs
+        .into_iter()
+        .map(|(host, port)| {
+            let addr = match &template.addr {
+                ConnectionAddr::TcpTls {
+                    insecure,
+                    tls_params,
+                    ..
+                } => ConnectionAddr::TcpTls {
+                    host,
+                    port,
+                    insecure: *insecure,
+                    tls_params: tls_params.clone(),
+                },
+                _ => ConnectionAddr::Tcp(host, port),
+            };
+            Ok(RedisClient::open(ConnectionInfo {
+                addr,
+                redis: template.redis.clone(),
+            })?)
+        })
+        .collect()
+}
+
+/// `ensure_loaded` on every master of the cluster behind `conn`, functions are
+/// not shared between shards.
+#[cfg(feature = "redis-cluster")]
+pub(crate) fn redis_cluster_ensure_loaded<C: ConnectionLike>(
+    conn: &mut C,
+    urls: &[String],
+) -> Result<()> {
+    for master in redis_cluster_masters(conn, urls)? {
+        ensure_loaded(&mut master.get_connection()?)?;
+    }
+    Ok(())
+}
+
+/// Versioned per-account values of the Redis backends, typed. Their keys are
+/// laid out by `schema` under the prefix.
+#[cfg(feature = "redis")]
+#[derive(Debug, Clone)]
+pub(crate) struct RedisAccounts {
+    pub schema: KeySchema,
+    pub balance: VersionedMap<SchemaKey, U256>,
+    pub nonce: VersionedMap<SchemaKey, U256>,
+    pub code_hash: VersionedMap<SchemaKey, H256>,
+    pub state: VersionedMap<SchemaKey, H256>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisAccounts {
+    pub fn new(prefix: &str, schema: KeySchema) -> Self {
+        Self {
+            schema,
+            balance: VersionedMap::new(prefix),
+            nonce: VersionedMap::new(prefix),
+            code_hash: VersionedMap::new(prefix),
+            state: VersionedMap::new(prefix),
+        }
+    }
+
+    pub fn key(&self, key: AccountKey) -> SchemaKey {
+        SchemaKey(self.schema, key)
+    }
+}
+
+#[cfg(feature = "redis")]
+fn redis_get_code_hash<C: ConnectionLike>(
+    conn: &mut C,
+    accounts: &RedisAccounts,
+    height: u32,
+    address: H160,
+) -> Result<Option<H256>> {
+    let key = accounts.key(AccountKey::CodeHash(address));
+    Ok(accounts.code_hash.get_at(conn, &key, height)?)
+}
+
+/// Values at `height` of `keys` in `map`, read in one pipeline.
+#[cfg(feature = "redis")]
+fn redis_get_many<C: RedisPipelined, V>(
+    conn: &mut C,
+    map: &VersionedMap<SchemaKey, V>,
+    height: u32,
+    keys: impl Iterator<Item = SchemaKey>,
+) -> Result<Vec<Option<V>>>
+where
+    JsonCodec: Codec<V>,
+{
+    let cmds = keys
+        .map(|key| map.get_at_cmd(&key, height))
+        .collect::<Vec<_>>();
+    if cmds.is_empty() {
+        return Ok(Vec::new());
+    }
+    let values: Vec<Option<Vec<u8>>> = conn.query_pipelined(cmds)?;
+    values
+        .into_iter()
+        .map(|value| Ok(map.decode(value)?))
+        .collect()
+}
+
+/// Members read per ZRANGEBYLEX page of an index key.
+#[cfg(feature = "redis")]
+const REDIS_INDEX_PAGE: usize = 1000;
+
+/// Connections that run a pipeline of `vkv_*` commands whose keys may be on
+/// different cluster slots, see `with_script_reload`.
+#[cfg(feature = "redis")]
+pub(crate) trait RedisPipelined: ConnectionLike + Sized {
+    fn query_pipelined<T: FromRedisValue>(&mut self, cmds: Vec<Cmd>) -> Result<T>;
+}
+
+#[cfg(feature = "redis")]
+impl RedisPipelined for redis::Connection {
+    fn query_pipelined<T: FromRedisValue>(&mut self, cmds: Vec<Cmd>) -> Result<T> {
+        let mut pipe = redis::pipe();
+        for cmd in cmds {
+            pipe.add_command(cmd);
+        }
+        Ok(with_script_reload(self, |conn| pipe.query(conn))?)
+    }
+}
+
+#[cfg(feature = "redis-cluster")]
+impl RedisPipelined for ClusterConnection {
+    fn query_pipelined<T: FromRedisValue>(&mut self, cmds: Vec<Cmd>) -> Result<T> {
+        let mut pipe = redis::cluster::cluster_pipe();
+        for cmd in cmds {
+            pipe.add_command(cmd);
+        }
+        Ok(with_script_reload(self, |conn| pipe.query(conn))?)
+    }
+}
+
+/// Visits the slots of `address` from `start` on that have a value at
+/// `height`, in slot order, while `visit` returns true. The slots are read
+/// from `keys::storage_index_key` a page at a time, with their values in one
+/// pipeline.
+#[cfg(feature = "redis")]
+fn redis_for_each_slot<C: RedisPipelined>(
+    conn: &mut C,
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    address: H160,
+    start: H256,
+    mut visit: impl FnMut(H256, H256) -> bool,
+) -> Result<()> {
+    let index_key = keys::storage_index_key(prefix, address);
+    let mut min = format!("[{}", hex::encode(start));
+    loop {
+        let page: Vec<String> =
+            conn.zrangebylex_limit(&index_key, &min, "+", 0, REDIS_INDEX_PAGE as isize)?;
+        let indexes = page
+            .iter()
+            .map(|index| keys::parse_h256(index).ok_or(Error::LengthMismatch))
+            .collect::<Result<Vec<_>>>()?;
+        if indexes.is_empty() {
+            return Ok(());
+        }
+        let cmds = indexes
+            .iter()
+            .map(|index| {
+                let key = accounts.key(AccountKey::State(address, *index));
+                accounts.state.get_at_cmd(&key, height)
+            })
+            .collect();
+        let values: Vec<Option<Vec<u8>>> = conn.query_pipelined(cmds)?;
+        for (index, value) in indexes.into_iter().zip(values) {
+            if let Some(value) = accounts.state.decode(value)? {
+                if !visit(index, value) {
+                    return Ok(());
+                }
+            }
+        }
+        match page.last() {
+            Some(last) if page.len() == REDIS_INDEX_PAGE => min = format!("({}", last),
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Storage of `address` at `height` from the slot `start` on, see
+/// `redis_for_each_slot`.
+#[cfg(feature = "redis")]
+fn redis_storage_range<C: RedisPipelined>(
+    conn: &mut C,
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    address: H160,
+    start: H256,
+    limit: usize,
+) -> Result<StorageRange> {
+    let mut slots = Vec::new();
+    redis_for_each_slot(
+        conn,
+        prefix,
+        accounts,
+        height,
+        address,
+        start,
+        |index, value| {
+            if !value.is_zero() {
+                slots.push((index, value));
+            }
+            // one slot past `limit` starts the next page
+            slots.len() <= limit
+        },
+    )?;
+    Ok(StorageRange::from_sorted(slots, limit))
+}
+
+/// Whether any slot of `address` has a value at `height`, which is what
+/// `addr_state_exists` reports.
+#[cfg(feature = "redis")]
+pub(crate) fn redis_has_storage<C: RedisPipelined>(
+    conn: &mut C,
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    address: H160,
+) -> Result<bool> {
+    let mut found = false;
+    redis_for_each_slot(
+        conn,
+        prefix,
+        accounts,
+        height,
+        address,
+        H256::zero(),
+        |_, value| {
+            // a cleared slot holds zero, as `redis_storage_range` skips it
+            found = !value.is_zero();
+            !found
+        },
+    )?;
+    Ok(found)
+}
+
+/// Accounts known at `height` from the address `start` on, through the
+/// addresses of `keys::accounts_index_key` like `redis_storage_range`.
+#[cfg(feature = "redis")]
+fn redis_account_range<C: RedisPipelined>(
+    conn: &mut C,
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    start: H160,
+    limit: usize,
+) -> Result<AccountRange> {
+    let index_key = keys::accounts_index_key(prefix);
+    let mut min = format!("[{}", hex::encode(start));
+    let mut found = Vec::new();
+    // one account past `limit` starts the next page
+    while found.len() <= limit {
+        let page: Vec<String> =
+            conn.zrangebylex_limit(&index_key, &min, "+", 0, REDIS_INDEX_PAGE as isize)?;
+        let addresses = page
+            .iter()
+            .map(|address| keys::parse_h160(address).ok_or(Error::LengthMismatch))
+            .collect::<Result<Vec<_>>>()?;
+        if addresses.is_empty() {
+            break;
+        }
+        let cmds = addresses
+            .iter()
+            .flat_map(|address| redis_account_basic_cmds(prefix, accounts, height, *address))
+            .collect();
+        let replies: Vec<Option<Vec<u8>>> = conn.query_pipelined(cmds)?;
+        for (address, reply) in addresses.into_iter().zip(replies.chunks_exact(4)) {
+            if reply.iter().all(Option::is_none) {
+                continue;
+            }
+            // code written before codes were stored by hash, a hash wins
+            let code_hash = match accounts.code_hash.decode(reply[2].clone())? {
+                Some(hash) => Some(hash),
+                None => reply[3]
+                    .as_ref()
+                    .map(hex::decode)
+                    .transpose()?
+                    .filter(|code| !code.is_empty())
+                    .map(|code| code_hash(&code)),
+            };
+            let summary = AccountSummary {
+                balance: accounts
+                    .balance
+                    .decode(reply[0].clone())?
+                    .unwrap_or_default(),
+                nonce: accounts.nonce.decode(reply[1].clone())?.unwrap_or_default(),
+                code_hash,
+            };
+            found.push((address, summary));
+        }
+        match page.last() {
+            Some(last) if page.len() == REDIS_INDEX_PAGE => min = format!("({}", last),
+            _ => break,
+        }
+    }
+    Ok(AccountRange::from_sorted(found, limit))
+}
+
+#[cfg(feature = "redis")]
+fn redis_get_state_diff<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    height: u32,
+) -> Result<Option<StateDiff>> {
+    let diff: Option<String> = conn.get(keys::state_diff_key(prefix, height))?;
+    Ok(diff.map(|diff| serde_json::from_str(&diff)).transpose()?)
+}
+
+#[cfg(feature = "redis")]
+fn redis_get_code_by_hash<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    hash: H256,
+) -> Result<Option<Vec<u8>>> {
+    let code: Option<String> = conn.get(keys::code_by_hash_key(prefix, hash))?;
+    Ok(code.map(hex::decode).transpose()?)
+}
+
+/// Resolves the code through its hash, falling back to the code versioned
+/// under `code_key` by earlier exports.
+#[cfg(feature = "redis")]
+fn redis_get_byte_code<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    address: H160,
+) -> Result<Vec<u8>> {
+    if let Some(hash) = redis_get_code_hash(conn, accounts, height, address)? {
+        return Ok(redis_get_code_by_hash(conn, prefix, hash)?.unwrap_or_default());
+    }
+    let code: Option<String> = conn.vkv_get(accounts.schema.code_key(prefix, address), height)?;
+    let code = if let Some(s) = code {
+        hex::decode(s)?
+    } else {
+        Vec::new()
+    };
+    Ok(code)
+}
+
+/// Reads of `get_account_basic`, sent as one pipeline: balance, nonce, code
+/// hash and the code versioned under `code_key` by earlier exports.
+#[cfg(feature = "redis")]
+pub(crate) fn redis_account_basic_cmds(
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    address: H160,
+) -> [Cmd; 4] {
+    [
+        accounts
+            .balance
+            .get_at_cmd(&accounts.key(AccountKey::Balance(address)), height),
+        accounts
+            .nonce
+            .get_at_cmd(&accounts.key(AccountKey::Nonce(address)), height),
+        accounts
+            .code_hash
+            .get_at_cmd(&accounts.key(AccountKey::CodeHash(address)), height),
+        vkv_get_cmd(accounts.schema.code_key(prefix, address), height),
+    ]
+}
+
+/// Replies to `redis_account_basic_cmds`.
+#[cfg(feature = "redis")]
+pub(crate) type RedisAccountBasicReplies = (
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+);
+
+/// Account from the replies to `redis_account_basic_cmds`, the code is only
+/// fetched separately when it is stored by hash.
+#[cfg(feature = "redis")]
+fn redis_account_basic<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    accounts: &RedisAccounts,
+    (balance, nonce, hash, code): RedisAccountBasicReplies,
+) -> Result<AccountBasic> {
+    let code = match accounts.code_hash.decode(hash)? {
+        Some(hash) => redis_get_code_by_hash(conn, prefix, hash)?.unwrap_or_default(),
+        None => code.map(hex::decode).transpose()?.unwrap_or_default(),
+    };
+    Ok(AccountBasic {
+        balance: accounts.balance.decode(balance)?.unwrap_or_default(),
+        code,
+        nonce: accounts.nonce.decode(nonce)?.unwrap_or_default(),
+    })
+}
+
 #[cfg(feature = "redis")]
 impl Getter for RedisGetter {
     fn latest_height(&self) -> Result<u32> {
         let height_key = keys::latest_height_key(&self.prefix);
-        let height: Option<String> = self.conn.get_connection()?.get(height_key)?;
+        let height: Option<String> = self.conn.get()?.get(height_key)?;
         match height {
             Some(str) => Ok(str.parse::<u32>()?),
             _ => Ok(0),
@@ -327,78 +1309,154 @@ impl Getter for RedisGetter {
     }
     fn lowest_height(&self) -> Result<u32> {
         let height_key = keys::lowest_height_key(&self.prefix);
-        let height: Option<String> = self.conn.get_connection()?.get(height_key)?;
+        let height: Option<String> = self.conn.get()?.get(height_key)?;
         match height {
             Some(str) => Ok(str.parse::<u32>()?),
             _ => Ok(0),
         }
     }
+    fn rollback_count(&self) -> Result<u64> {
+        let count: Option<u64> = self
+            .conn
+            .get()?
+            .get(keys::rollback_count_key(&self.prefix))?;
+        Ok(count.unwrap_or_default())
+    }
     fn get_balance(&self, height: u32, address: H160) -> Result<U256> {
-        let balance_key = keys::balance_key(&self.prefix, address);
-        let balance: Option<String> = self.conn.get_connection()?.vkv_get(balance_key, height)?;
-        let balance = if let Some(s) = balance {
-            serde_json::from_str(s.as_str())?
-        } else {
-            U256::zero()
-        };
-        Ok(balance)
+        let key = self.accounts.key(AccountKey::Balance(address));
+        let balance = self
+            .accounts
+            .balance
+            .get_at(&mut *self.conn.get()?, &key, height)?;
+        Ok(balance.unwrap_or_default())
     }
 
     fn get_nonce(&self, height: u32, address: H160) -> Result<U256> {
-        let nonce_key = keys::nonce_key(&self.prefix, address);
-        let nonce: Option<String> = self.conn.get_connection()?.vkv_get(nonce_key, height)?;
-        let nonce = if let Some(s) = nonce {
-            serde_json::from_str(s.as_str())?
-        } else {
-            U256::zero()
-        };
-        Ok(nonce)
+        let key = self.accounts.key(AccountKey::Nonce(address));
+        let nonce = self
+            .accounts
+            .nonce
+            .get_at(&mut *self.conn.get()?, &key, height)?;
+        Ok(nonce.unwrap_or_default())
     }
 
     fn get_byte_code(&self, height: u32, address: H160) -> Result<Vec<u8>> {
-        let code_key = keys::code_key(&self.prefix, address);
-        let code: Option<String> = self.conn.get_connection()?.vkv_get(code_key, height)?;
-        let code = if let Some(s) = code {
-            hex::decode(s)?
-        } else {
-            Vec::new()
-        };
-        Ok(code)
+        redis_get_byte_code(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            address,
+        )
+    }
+
+    fn get_code_hash(&self, height: u32, address: H160) -> Result<Option<H256>> {
+        redis_get_code_hash(&mut *self.conn.get()?, &self.accounts, height, address)
+    }
+
+    fn get_code_by_hash(&self, hash: H256) -> Result<Option<Vec<u8>>> {
+        redis_get_code_by_hash(&mut *self.conn.get()?, &self.prefix, hash)
     }
 
     fn get_account_basic(&self, height: u32, address: H160) -> Result<AccountBasic> {
-        Ok(AccountBasic {
-            balance: self.get_balance(height, address)?,
-            code: self.get_byte_code(height, address)?,
-            nonce: self.get_nonce(height, address)?,
-        })
+        let mut conn = self.conn.get()?;
+        let mut pipe = redis::pipe();
+        for cmd in redis_account_basic_cmds(&self.prefix, &self.accounts, height, address) {
+            pipe.add_command(cmd);
+        }
+        let replies = with_script_reload(&mut *conn, |conn| pipe.query(conn))?;
+        redis_account_basic(&mut *conn, &self.prefix, &self.accounts, replies)
     }
 
     fn addr_state_exists(&self, height: u32, address: H160) -> Result<bool> {
-        let state_addr_key = keys::state_addr_key(&self.prefix, address);
-        let value: Option<String> = self
-            .conn
-            .get_connection()?
-            .vkv_get(state_addr_key, height)?;
+        let state_addr_key = self.accounts.schema.state_addr_key(&self.prefix, address);
+        let value: Option<String> = self.conn.get()?.vkv_get(state_addr_key, height)?;
         Ok(value.is_some())
     }
 
     fn get_state(&self, height: u32, address: H160, index: H256) -> Result<H256> {
-        let state_key = keys::state_key(&self.prefix, address, index);
-        let value: Option<String> = self.conn.get_connection()?.vkv_get(state_key, height)?;
-        let val = if let Some(s) = value {
-            serde_json::from_str(s.as_str())?
-        } else {
-            H256::zero()
-        };
-        Ok(val)
+        let key = self.accounts.key(AccountKey::State(address, index));
+        let value = self
+            .accounts
+            .state
+            .get_at(&mut *self.conn.get()?, &key, height)?;
+        Ok(value.unwrap_or_default())
+    }
+
+    fn get_balances(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        let keys = addresses
+            .iter()
+            .map(|address| self.accounts.key(AccountKey::Balance(*address)));
+        let balances =
+            redis_get_many(&mut *self.conn.get()?, &self.accounts.balance, height, keys)?;
+        Ok(balances
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect())
+    }
+
+    fn get_nonces(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        let keys = addresses
+            .iter()
+            .map(|address| self.accounts.key(AccountKey::Nonce(*address)));
+        let nonces = redis_get_many(&mut *self.conn.get()?, &self.accounts.nonce, height, keys)?;
+        Ok(nonces.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn get_code_hashes(&self, height: u32, addresses: &[H160]) -> Result<Vec<Option<H256>>> {
+        let keys = addresses
+            .iter()
+            .map(|address| self.accounts.key(AccountKey::CodeHash(*address)));
+        redis_get_many(
+            &mut *self.conn.get()?,
+            &self.accounts.code_hash,
+            height,
+            keys,
+        )
+    }
+
+    fn get_states(&self, height: u32, slots: &[(H160, H256)]) -> Result<Vec<H256>> {
+        let keys = slots
+            .iter()
+            .map(|(address, index)| self.accounts.key(AccountKey::State(*address, *index)));
+        let values = redis_get_many(&mut *self.conn.get()?, &self.accounts.state, height, keys)?;
+        Ok(values.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn get_storage_range(
+        &self,
+        height: u32,
+        address: H160,
+        start: H256,
+        limit: usize,
+    ) -> Result<StorageRange> {
+        redis_storage_range(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            address,
+            start,
+            limit,
+        )
+    }
+
+    fn get_account_range(&self, height: u32, start: H160, limit: usize) -> Result<AccountRange> {
+        redis_account_range(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            start,
+            limit,
+        )
     }
 
     fn get_block_hash_by_height(&self, height: U256) -> Result<Option<H256>> {
         let block_hash_key = keys::block_hash_key(&self.prefix, height);
         let value: Option<String> = self
             .conn
-            .get_connection()?
+            .get()?
             .get::<&str, Option<String>>(&block_hash_key)?;
         if let Some(hash) = value {
             Ok(Some(serde_json::from_str(hash.as_str())?))
@@ -411,7 +1469,7 @@ impl Getter for RedisGetter {
         let block_height_key = keys::block_height_key(&self.prefix, block_hash);
         let value: Option<String> = self
             .conn
-            .get_connection()?
+            .get()?
             .get::<&str, Option<String>>(&block_height_key)?;
         if let Some(hash) = value {
             Ok(Some(serde_json::from_str(hash.as_str())?))
@@ -422,12 +1480,9 @@ impl Getter for RedisGetter {
 
     fn get_block_by_hash(&self, block_hash: H256) -> Result<Option<Block>> {
         let block_key = keys::block_key(&self.prefix, block_hash);
-        let value: Option<String> = self
-            .conn
-            .get_connection()?
-            .get::<&str, Option<String>>(&block_key)?;
+        let value: Option<Vec<u8>> = self.conn.get()?.get::<&str, Option<Vec<u8>>>(&block_key)?;
         if let Some(block) = value {
-            Ok(Some(serde_json::from_str(block.as_str())?))
+            Ok(Some(decode_block(&block)?))
         } else {
             Ok(None)
         }
@@ -438,10 +1493,10 @@ impl Getter for RedisGetter {
         block_hash: H256,
     ) -> Result<Option<Vec<Receipt>>> {
         let receipt_key = keys::receipt_key(&self.prefix, block_hash);
-        let value: Option<String> = self.conn.get_connection()?.get(receipt_key)?;
+        let value: Option<Vec<u8>> = self.conn.get()?.get(receipt_key)?;
 
         match value {
-            Some(receipts) => Ok(serde_json::from_str(receipts.as_str())?),
+            Some(receipts) => Ok(Some(decode_receipts(&receipts)?)),
             _ => Ok(None),
         }
     }
@@ -452,10 +1507,10 @@ impl Getter for RedisGetter {
     ) -> Result<Option<Vec<TransactionStatus>>> {
         let status_key = keys::status_key(&self.prefix, block_hash);
 
-        let value: Option<String> = self.conn.get_connection()?.get(status_key)?;
+        let value: Option<Vec<u8>> = self.conn.get()?.get(status_key)?;
 
         match value {
-            Some(statuses) => Ok(serde_json::from_str(statuses.as_str())?),
+            Some(statuses) => Ok(Some(decode_statuses(&statuses)?)),
             _ => Ok(None),
         }
     }
@@ -463,7 +1518,7 @@ impl Getter for RedisGetter {
     fn get_transaction_index_by_tx_hash(&self, tx_hash: H256) -> Result<Option<(H256, u32)>> {
         let transaction_index_key = keys::transaction_index_key(&self.prefix, tx_hash);
 
-        let value: Option<String> = self.conn.get_connection()?.get(transaction_index_key)?;
+        let value: Option<String> = self.conn.get()?.get(transaction_index_key)?;
 
         match value {
             Some(hash_index) => Ok(serde_json::from_str(hash_index.as_str())?),
@@ -473,7 +1528,7 @@ impl Getter for RedisGetter {
 
     fn get_pending_balance(&self, address: H160) -> Result<Option<U256>> {
         let balance_key = keys::pending_balance_key(&self.prefix, address);
-        let balance: Option<String> = self.conn.get_connection()?.get(balance_key)?;
+        let balance: Option<String> = self.conn.get()?.get(balance_key)?;
         let balance = if let Some(s) = balance {
             Some(serde_json::from_str(s.as_str())?)
         } else {
@@ -484,7 +1539,7 @@ impl Getter for RedisGetter {
 
     fn get_pending_nonce(&self, address: H160) -> Result<Option<U256>> {
         let nonce_key = keys::pending_nonce_key(&self.prefix, address);
-        let nonce: Option<String> = self.conn.get_connection()?.get(nonce_key)?;
+        let nonce: Option<String> = self.conn.get()?.get(nonce_key)?;
         let nonce = if let Some(s) = nonce {
             Some(serde_json::from_str(s.as_str())?)
         } else {
@@ -495,7 +1550,7 @@ impl Getter for RedisGetter {
 
     fn get_pending_byte_code(&self, address: H160) -> Result<Option<Vec<u8>>> {
         let code_key = keys::pending_code_key(&self.prefix, address);
-        let code: Option<String> = self.conn.get_connection()?.get(code_key)?;
+        let code: Option<String> = self.conn.get()?.get(code_key)?;
         let code = if let Some(s) = code {
             Some(hex::decode(s)?)
         } else {
@@ -506,7 +1561,7 @@ impl Getter for RedisGetter {
 
     fn get_pending_state(&self, address: H160, index: H256) -> Result<Option<H256>> {
         let state_key = keys::pending_state_key(&self.prefix, address, index);
-        let value: Option<String> = self.conn.get_connection()?.get(state_key)?;
+        let value: Option<String> = self.conn.get()?.get(state_key)?;
         let val = if let Some(s) = value {
             Some(serde_json::from_str(s.as_str())?)
         } else {
@@ -517,7 +1572,7 @@ impl Getter for RedisGetter {
 
     fn get_total_issuance(&self, height: u32) -> Result<U256> {
         let key = keys::total_issuance_key(&self.prefix);
-        let value: Option<String> = self.conn.get_connection()?.vkv_get(key, height)?;
+        let value: Option<String> = self.conn.get()?.vkv_get(key, height)?;
         let val = if let Some(s) = value {
             serde_json::from_str(s.as_str())?
         } else {
@@ -528,7 +1583,7 @@ impl Getter for RedisGetter {
 
     fn get_allowances(&self, height: u32, owner: H160, spender: H160) -> Result<U256> {
         let key = keys::allowances_key(&self.prefix, owner, spender);
-        let value: Option<String> = self.conn.get_connection()?.vkv_get(key, height)?;
+        let value: Option<String> = self.conn.get()?.vkv_get(key, height)?;
         let val = if let Some(s) = value {
             serde_json::from_str(s.as_str())?
         } else {
@@ -536,11 +1591,35 @@ impl Getter for RedisGetter {
         };
         Ok(val)
     }
+
+    fn get_logs(
+        &self,
+        from: u32,
+        to: u32,
+        addresses: &[H160],
+        topics: &[Option<Vec<H256>>],
+        limit: usize,
+    ) -> Result<Vec<IndexedLog>> {
+        redis_get_logs(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            from,
+            to,
+            addresses,
+            topics,
+            limit,
+        )
+    }
+
+    fn get_state_diff(&self, height: u32) -> Result<Option<StateDiff>> {
+        redis_get_state_diff(&mut *self.conn.get()?, &self.prefix, height)
+    }
 }
 
 #[cfg(feature = "redis-cluster")]
 pub struct RedisClusterGetter {
-    conn: RedisClusterClient,
+    conn: Pool<RedisClusterClient>,
+    accounts: RedisAccounts,
     pub prefix: String,
 }
 
@@ -548,9 +1627,26 @@ pub struct RedisClusterGetter {
 impl RedisClusterGetter {
     pub fn new(connection: ConnectionType, prefix: String) -> Self {
         if let ConnectionType::RedisCluster(urls) = connection {
+            let (urls, options) =
+                RedisPoolOptions::from_cluster_urls(&urls).expect("parse redis url failed");
+            let client =
+                RedisClusterClient::new(urls.to_vec()).expect("Connect to Redis Cluster failed");
+            let conn = options
+                .build(client)
+                .expect("new redis connection pool failed");
+            redis_cluster_ensure_loaded(
+                &mut *conn.get().expect("get redis connection failed"),
+                &urls,
+            )
+            .expect("load versioned-kv library failed");
+            let schema = redis_key_schema(
+                &mut *conn.get().expect("get redis connection failed"),
+                &prefix,
+            )
+            .expect("read redis key schema failed");
             Self {
-                conn: RedisClusterClient::new(urls.to_vec())
-                    .expect("Connect to Redis Cluster failed"),
+                conn,
+                accounts: RedisAccounts::new(&prefix, schema),
                 prefix,
             }
         } else {
@@ -563,7 +1659,7 @@ impl RedisClusterGetter {
 impl Getter for RedisClusterGetter {
     fn latest_height(&self) -> Result<u32> {
         let height_key = keys::latest_height_key(&self.prefix);
-        let height: Option<String> = self.conn.get_connection()?.get(height_key)?;
+        let height: Option<String> = self.conn.get()?.get(height_key)?;
         match height {
             Some(str) => Ok(str.parse::<u32>()?),
             _ => Ok(0),
@@ -571,78 +1667,154 @@ impl Getter for RedisClusterGetter {
     }
     fn lowest_height(&self) -> Result<u32> {
         let height_key = keys::lowest_height_key(&self.prefix);
-        let height: Option<String> = self.conn.get_connection()?.get(height_key)?;
+        let height: Option<String> = self.conn.get()?.get(height_key)?;
         match height {
             Some(str) => Ok(str.parse::<u32>()?),
             _ => Ok(0),
         }
     }
+    fn rollback_count(&self) -> Result<u64> {
+        let count: Option<u64> = self
+            .conn
+            .get()?
+            .get(keys::rollback_count_key(&self.prefix))?;
+        Ok(count.unwrap_or_default())
+    }
     fn get_balance(&self, height: u32, address: H160) -> Result<U256> {
-        let balance_key = keys::balance_key(&self.prefix, address);
-        let balance: Option<String> = self.conn.get_connection()?.vkv_get(balance_key, height)?;
-        let balance = if let Some(s) = balance {
-            serde_json::from_str(s.as_str())?
-        } else {
-            U256::zero()
-        };
-        Ok(balance)
+        let key = self.accounts.key(AccountKey::Balance(address));
+        let balance = self
+            .accounts
+            .balance
+            .get_at(&mut *self.conn.get()?, &key, height)?;
+        Ok(balance.unwrap_or_default())
     }
 
     fn get_nonce(&self, height: u32, address: H160) -> Result<U256> {
-        let nonce_key = keys::nonce_key(&self.prefix, address);
-        let nonce: Option<String> = self.conn.get_connection()?.vkv_get(nonce_key, height)?;
-        let nonce = if let Some(s) = nonce {
-            serde_json::from_str(s.as_str())?
-        } else {
-            U256::zero()
-        };
-        Ok(nonce)
+        let key = self.accounts.key(AccountKey::Nonce(address));
+        let nonce = self
+            .accounts
+            .nonce
+            .get_at(&mut *self.conn.get()?, &key, height)?;
+        Ok(nonce.unwrap_or_default())
     }
 
     fn get_byte_code(&self, height: u32, address: H160) -> Result<Vec<u8>> {
-        let code_key = keys::code_key(&self.prefix, address);
-        let code: Option<String> = self.conn.get_connection()?.vkv_get(code_key, height)?;
-        let code = if let Some(s) = code {
-            hex::decode(s)?
-        } else {
-            Vec::new()
-        };
-        Ok(code)
+        redis_get_byte_code(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            address,
+        )
+    }
+
+    fn get_code_hash(&self, height: u32, address: H160) -> Result<Option<H256>> {
+        redis_get_code_hash(&mut *self.conn.get()?, &self.accounts, height, address)
+    }
+
+    fn get_code_by_hash(&self, hash: H256) -> Result<Option<Vec<u8>>> {
+        redis_get_code_by_hash(&mut *self.conn.get()?, &self.prefix, hash)
     }
 
     fn get_account_basic(&self, height: u32, address: H160) -> Result<AccountBasic> {
-        Ok(AccountBasic {
-            balance: self.get_balance(height, address)?,
-            code: self.get_byte_code(height, address)?,
-            nonce: self.get_nonce(height, address)?,
-        })
+        let mut conn = self.conn.get()?;
+        let mut pipe = redis::cluster::cluster_pipe();
+        for cmd in redis_account_basic_cmds(&self.prefix, &self.accounts, height, address) {
+            pipe.add_command(cmd);
+        }
+        let replies = with_script_reload(&mut *conn, |conn| pipe.query(conn))?;
+        redis_account_basic(&mut *conn, &self.prefix, &self.accounts, replies)
     }
 
     fn addr_state_exists(&self, height: u32, address: H160) -> Result<bool> {
-        let state_addr_key = keys::state_addr_key(&self.prefix, address);
-        let value: Option<String> = self
-            .conn
-            .get_connection()?
-            .vkv_get(state_addr_key, height)?;
+        let state_addr_key = self.accounts.schema.state_addr_key(&self.prefix, address);
+        let value: Option<String> = self.conn.get()?.vkv_get(state_addr_key, height)?;
         Ok(value.is_some())
     }
 
     fn get_state(&self, height: u32, address: H160, index: H256) -> Result<H256> {
-        let state_key = keys::state_key(&self.prefix, address, index);
-        let value: Option<String> = self.conn.get_connection()?.vkv_get(state_key, height)?;
-        let val = if let Some(s) = value {
-            serde_json::from_str(s.as_str())?
-        } else {
-            H256::zero()
-        };
-        Ok(val)
+        let key = self.accounts.key(AccountKey::State(address, index));
+        let value = self
+            .accounts
+            .state
+            .get_at(&mut *self.conn.get()?, &key, height)?;
+        Ok(value.unwrap_or_default())
+    }
+
+    fn get_balances(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        let keys = addresses
+            .iter()
+            .map(|address| self.accounts.key(AccountKey::Balance(*address)));
+        let balances =
+            redis_get_many(&mut *self.conn.get()?, &self.accounts.balance, height, keys)?;
+        Ok(balances
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect())
+    }
+
+    fn get_nonces(&self, height: u32, addresses: &[H160]) -> Result<Vec<U256>> {
+        let keys = addresses
+            .iter()
+            .map(|address| self.accounts.key(AccountKey::Nonce(*address)));
+        let nonces = redis_get_many(&mut *self.conn.get()?, &self.accounts.nonce, height, keys)?;
+        Ok(nonces.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn get_code_hashes(&self, height: u32, addresses: &[H160]) -> Result<Vec<Option<H256>>> {
+        let keys = addresses
+            .iter()
+            .map(|address| self.accounts.key(AccountKey::CodeHash(*address)));
+        redis_get_many(
+            &mut *self.conn.get()?,
+            &self.accounts.code_hash,
+            height,
+            keys,
+        )
+    }
+
+    fn get_states(&self, height: u32, slots: &[(H160, H256)]) -> Result<Vec<H256>> {
+        let keys = slots
+            .iter()
+            .map(|(address, index)| self.accounts.key(AccountKey::State(*address, *index)));
+        let values = redis_get_many(&mut *self.conn.get()?, &self.accounts.state, height, keys)?;
+        Ok(values.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn get_storage_range(
+        &self,
+        height: u32,
+        address: H160,
+        start: H256,
+        limit: usize,
+    ) -> Result<StorageRange> {
+        redis_storage_range(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            address,
+            start,
+            limit,
+        )
+    }
+
+    fn get_account_range(&self, height: u32, start: H160, limit: usize) -> Result<AccountRange> {
+        redis_account_range(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            start,
+            limit,
+        )
     }
 
     fn get_block_hash_by_height(&self, height: U256) -> Result<Option<H256>> {
         let block_hash_key = keys::block_hash_key(&self.prefix, height);
         let value: Option<String> = self
             .conn
-            .get_connection()?
+            .get()?
             .get::<&str, Option<String>>(&block_hash_key)?;
         if let Some(hash) = value {
             Ok(Some(serde_json::from_str(hash.as_str())?))
@@ -655,7 +1827,7 @@ impl Getter for RedisClusterGetter {
         let block_height_key = keys::block_height_key(&self.prefix, block_hash);
         let value: Option<String> = self
             .conn
-            .get_connection()?
+            .get()?
             .get::<&str, Option<String>>(&block_height_key)?;
         if let Some(hash) = value {
             Ok(Some(serde_json::from_str(hash.as_str())?))
@@ -666,12 +1838,9 @@ impl Getter for RedisClusterGetter {
 
     fn get_block_by_hash(&self, block_hash: H256) -> Result<Option<Block>> {
         let block_key = keys::block_key(&self.prefix, block_hash);
-        let value: Option<String> = self
-            .conn
-            .get_connection()?
-            .get::<&str, Option<String>>(&block_key)?;
+        let value: Option<Vec<u8>> = self.conn.get()?.get::<&str, Option<Vec<u8>>>(&block_key)?;
         if let Some(block) = value {
-            Ok(Some(serde_json::from_str(block.as_str())?))
+            Ok(Some(decode_block(&block)?))
         } else {
             Ok(None)
         }
@@ -682,10 +1851,10 @@ impl Getter for RedisClusterGetter {
         block_hash: H256,
     ) -> Result<Option<Vec<Receipt>>> {
         let receipt_key = keys::receipt_key(&self.prefix, block_hash);
-        let value: Option<String> = self.conn.get_connection()?.get(receipt_key)?;
+        let value: Option<Vec<u8>> = self.conn.get()?.get(receipt_key)?;
 
         match value {
-            Some(receipts) => Ok(serde_json::from_str(receipts.as_str())?),
+            Some(receipts) => Ok(Some(decode_receipts(&receipts)?)),
             _ => Ok(None),
         }
     }
@@ -696,10 +1865,10 @@ impl Getter for RedisClusterGetter {
     ) -> Result<Option<Vec<TransactionStatus>>> {
         let status_key = keys::status_key(&self.prefix, block_hash);
 
-        let value: Option<String> = self.conn.get_connection()?.get(status_key)?;
+        let value: Option<Vec<u8>> = self.conn.get()?.get(status_key)?;
 
         match value {
-            Some(statuses) => Ok(serde_json::from_str(statuses.as_str())?),
+            Some(statuses) => Ok(Some(decode_statuses(&statuses)?)),
             _ => Ok(None),
         }
     }
@@ -707,7 +1876,7 @@ impl Getter for RedisClusterGetter {
     fn get_transaction_index_by_tx_hash(&self, tx_hash: H256) -> Result<Option<(H256, u32)>> {
         let transaction_index_key = keys::transaction_index_key(&self.prefix, tx_hash);
 
-        let value: Option<String> = self.conn.get_connection()?.get(transaction_index_key)?;
+        let value: Option<String> = self.conn.get()?.get(transaction_index_key)?;
 
         match value {
             Some(hash_index) => Ok(serde_json::from_str(hash_index.as_str())?),
@@ -717,7 +1886,7 @@ impl Getter for RedisClusterGetter {
 
     fn get_pending_balance(&self, address: H160) -> Result<Option<U256>> {
         let balance_key = keys::pending_balance_key(&self.prefix, address);
-        let balance: Option<String> = self.conn.get_connection()?.get(balance_key)?;
+        let balance: Option<String> = self.conn.get()?.get(balance_key)?;
         let balance = if let Some(s) = balance {
             Some(serde_json::from_str(s.as_str())?)
         } else {
@@ -728,7 +1897,7 @@ impl Getter for RedisClusterGetter {
 
     fn get_pending_nonce(&self, address: H160) -> Result<Option<U256>> {
         let nonce_key = keys::pending_nonce_key(&self.prefix, address);
-        let nonce: Option<String> = self.conn.get_connection()?.get(nonce_key)?;
+        let nonce: Option<String> = self.conn.get()?.get(nonce_key)?;
         let nonce = if let Some(s) = nonce {
             Some(serde_json::from_str(s.as_str())?)
         } else {
@@ -739,7 +1908,7 @@ impl Getter for RedisClusterGetter {
 
     fn get_pending_byte_code(&self, address: H160) -> Result<Option<Vec<u8>>> {
         let code_key = keys::pending_code_key(&self.prefix, address);
-        let code: Option<String> = self.conn.get_connection()?.get(code_key)?;
+        let code: Option<String> = self.conn.get()?.get(code_key)?;
         let code = if let Some(s) = code {
             Some(hex::decode(s)?)
         } else {
@@ -750,7 +1919,7 @@ impl Getter for RedisClusterGetter {
 
     fn get_pending_state(&self, address: H160, index: H256) -> Result<Option<H256>> {
         let state_key = keys::pending_state_key(&self.prefix, address, index);
-        let value: Option<String> = self.conn.get_connection()?.get(state_key)?;
+        let value: Option<String> = self.conn.get()?.get(state_key)?;
         let val = if let Some(s) = value {
             Some(serde_json::from_str(s.as_str())?)
         } else {
@@ -761,7 +1930,7 @@ impl Getter for RedisClusterGetter {
 
     fn get_total_issuance(&self, height: u32) -> Result<U256> {
         let key = keys::total_issuance_key(&self.prefix);
-        let value: Option<String> = self.conn.get_connection()?.vkv_get(key, height)?;
+        let value: Option<String> = self.conn.get()?.vkv_get(key, height)?;
         let val = if let Some(s) = value {
             serde_json::from_str(s.as_str())?
         } else {
@@ -772,7 +1941,7 @@ impl Getter for RedisClusterGetter {
 
     fn get_allowances(&self, height: u32, owner: H160, spender: H160) -> Result<U256> {
         let key = keys::allowances_key(&self.prefix, owner, spender);
-        let value: Option<String> = self.conn.get_connection()?.vkv_get(key, height)?;
+        let value: Option<String> = self.conn.get()?.vkv_get(key, height)?;
         let val = if let Some(s) = value {
             serde_json::from_str(s.as_str())?
         } else {
@@ -780,4 +1949,551 @@ impl Getter for RedisClusterGetter {
         };
         Ok(val)
     }
+
+    fn get_logs(
+        &self,
+        from: u32,
+        to: u32,
+        addresses: &[H160],
+        topics: &[Option<Vec<H256>>],
+        limit: usize,
+    ) -> Result<Vec<IndexedLog>> {
+        redis_get_logs(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            from,
+            to,
+            addresses,
+            topics,
+            limit,
+        )
+    }
+
+    fn get_state_diff(&self, height: u32) -> Result<Option<StateDiff>> {
+        redis_get_state_diff(&mut *self.conn.get()?, &self.prefix, height)
+    }
+}
+
+#[cfg(feature = "memory")]
+pub struct MemoryGetter {
+    db: MemoryDb,
+}
+
+#[cfg(feature = "memory")]
+impl MemoryGetter {
+    pub fn new(connection: ConnectionType, _something: String) -> Self {
+        if let ConnectionType::Memory(db) = connection {
+            Self { db }
+        } else {
+            panic!("Invalid connection type for Memory")
+        }
+    }
+}
+
+#[cfg(feature = "memory")]
+impl Getter for MemoryGetter {
+    fn latest_height(&self) -> Result<u32> {
+        Ok(self.db.read()?.latest_height)
+    }
+    fn lowest_height(&self) -> Result<u32> {
+        Ok(self.db.read()?.lowest_height)
+    }
+    fn rollback_count(&self) -> Result<u64> {
+        Ok(self.db.read()?.rollbacks)
+    }
+    fn get_balance(&self, height: u32, address: H160) -> Result<U256> {
+        let data = self.db.read()?;
+        Ok(versioned_get(data.balance.get(&address), height)
+            .copied()
+            .unwrap_or_default())
+    }
+    fn get_nonce(&self, height: u32, address: H160) -> Result<U256> {
+        let data = self.db.read()?;
+        Ok(versioned_get(data.nonce.get(&address), height)
+            .copied()
+            .unwrap_or_default())
+    }
+    fn get_byte_code(&self, height: u32, address: H160) -> Result<Vec<u8>> {
+        let data = self.db.read()?;
+        Ok(versioned_get(data.code_hash.get(&address), height)
+            .and_then(|hash| data.code.get(hash))
+            .cloned()
+            .unwrap_or_default())
+    }
+    fn get_code_hash(&self, height: u32, address: H160) -> Result<Option<H256>> {
+        let data = self.db.read()?;
+        Ok(versioned_get(data.code_hash.get(&address), height).copied())
+    }
+    fn get_code_by_hash(&self, hash: H256) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.read()?.code.get(&hash).cloned())
+    }
+    fn get_account_basic(&self, height: u32, address: H160) -> Result<AccountBasic> {
+        Ok(AccountBasic {
+            balance: self.get_balance(height, address)?,
+            code: self.get_byte_code(height, address)?,
+            nonce: self.get_nonce(height, address)?,
+        })
+    }
+    fn addr_state_exists(&self, height: u32, address: H160) -> Result<bool> {
+        let data = self.db.read()?;
+        Ok(data
+            .state
+            .range((address, H256::zero())..=(address, H256::repeat_byte(0xff)))
+            .any(|(_, versions)| versioned_get(Some(versions), height).is_some()))
+    }
+    fn get_state(&self, height: u32, address: H160, index: H256) -> Result<H256> {
+        let data = self.db.read()?;
+        Ok(versioned_get(data.state.get(&(address, index)), height)
+            .copied()
+            .unwrap_or_default())
+    }
+    fn get_storage_range(
+        &self,
+        height: u32,
+        address: H160,
+        start: H256,
+        limit: usize,
+    ) -> Result<StorageRange> {
+        let data = self.db.read()?;
+        let slots = data
+            .state
+            .range((address, start)..=(address, H256::repeat_byte(0xff)))
+            .filter_map(|((_, index), versions)| {
+                versioned_get(Some(versions), height).map(|value| (*index, *value))
+            });
+        Ok(StorageRange::from_sorted(slots, limit))
+    }
+    fn get_account_range(&self, height: u32, start: H160, limit: usize) -> Result<AccountRange> {
+        fn known<V>(
+            versions: &BTreeMap<H160, Versioned<V>>,
+            height: u32,
+            start: H160,
+            limit: usize,
+        ) -> impl Iterator<Item = H160> + '_ {
+            versions
+                .range(start..)
+                .filter(move |(_, versions)| versioned_get(Some(*versions), height).is_some())
+                .map(|(address, _)| *address)
+                .take(limit.saturating_add(1))
+        }
+
+        let data = self.db.read()?;
+        let mut addresses = BTreeSet::new();
+        addresses.extend(known(&data.balance, height, start, limit));
+        addresses.extend(known(&data.nonce, height, start, limit));
+        addresses.extend(known(&data.code_hash, height, start, limit));
+        let accounts = addresses.into_iter().map(|address| {
+            let summary = AccountSummary {
+                balance: versioned_get(data.balance.get(&address), height)
+                    .copied()
+                    .unwrap_or_default(),
+                nonce: versioned_get(data.nonce.get(&address), height)
+                    .copied()
+                    .unwrap_or_default(),
+                code_hash: versioned_get(data.code_hash.get(&address), height).copied(),
+            };
+            (address, summary)
+        });
+        Ok(AccountRange::from_sorted(accounts, limit))
+    }
+    fn get_block_hash_by_height(&self, height: U256) -> Result<Option<H256>> {
+        Ok(self.db.read()?.block_hash.get(&height).copied())
+    }
+    fn get_height_by_block_hash(&self, block_hash: H256) -> Result<Option<U256>> {
+        Ok(self.db.read()?.block_height.get(&block_hash).copied())
+    }
+    fn get_block_by_hash(&self, block_hash: H256) -> Result<Option<Block>> {
+        Ok(self.db.read()?.block.get(&block_hash).cloned())
+    }
+    fn get_transaction_receipt_by_block_hash(
+        &self,
+        block_hash: H256,
+    ) -> Result<Option<Vec<Receipt>>> {
+        Ok(self.db.read()?.receipts.get(&block_hash).cloned())
+    }
+    fn get_transaction_status_by_block_hash(
+        &self,
+        block_hash: H256,
+    ) -> Result<Option<Vec<TransactionStatus>>> {
+        Ok(self.db.read()?.statuses.get(&block_hash).cloned())
+    }
+    fn get_transaction_index_by_tx_hash(&self, tx_hash: H256) -> Result<Option<(H256, u32)>> {
+        Ok(self.db.read()?.transaction_index.get(&tx_hash).copied())
+    }
+    fn get_pending_balance(&self, address: H160) -> Result<Option<U256>> {
+        Ok(self.db.read()?.pending_balance.get(&address).copied())
+    }
+    fn get_pending_nonce(&self, address: H160) -> Result<Option<U256>> {
+        Ok(self.db.read()?.pending_nonce.get(&address).copied())
+    }
+    fn get_pending_byte_code(&self, address: H160) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.read()?.pending_code.get(&address).cloned())
+    }
+    fn get_pending_state(&self, address: H160, index: H256) -> Result<Option<H256>> {
+        Ok(self
+            .db
+            .read()?
+            .pending_state
+            .get(&(address, index))
+            .copied())
+    }
+    fn get_total_issuance(&self, height: u32) -> Result<U256> {
+        let data = self.db.read()?;
+        Ok(versioned_get(Some(&data.total_issuance), height)
+            .copied()
+            .unwrap_or_default())
+    }
+    fn get_allowances(&self, height: u32, owner: H160, spender: H160) -> Result<U256> {
+        let data = self.db.read()?;
+        Ok(
+            versioned_get(data.allowances.get(&(owner, spender)), height)
+                .copied()
+                .unwrap_or_default(),
+        )
+    }
+    fn get_logs(
+        &self,
+        from: u32,
+        to: u32,
+        addresses: &[H160],
+        topics: &[Option<Vec<H256>>],
+        limit: usize,
+    ) -> Result<Vec<IndexedLog>> {
+        if from > to {
+            return Ok(vec![]);
+        }
+        Ok(self
+            .db
+            .read()?
+            .logs
+            .range(from..=to)
+            .flat_map(|(_, logs)| logs.iter())
+            .filter(|log| log.matches(addresses, topics))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+    fn get_state_diff(&self, height: u32) -> Result<Option<StateDiff>> {
+        Ok(self.db.read()?.state_diffs.get(&height).cloned())
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+pub struct RocksGetter {
+    db: RocksDb,
+    pub prefix: String,
+}
+
+#[cfg(feature = "rocksdb")]
+impl RocksGetter {
+    pub fn new(connection: ConnectionType, prefix: String) -> Self {
+        if let ConnectionType::RocksDB(db) = connection {
+            Self { db, prefix }
+        } else {
+            panic!("Invalid connection type for RocksDB")
+        }
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.db.get(key)? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_payload<T>(&self, key: &str, decode: fn(&[u8]) -> Result<T>) -> Result<Option<T>> {
+        self.db
+            .get_bytes(key)?
+            .map(|value| decode(&value))
+            .transpose()
+    }
+
+    fn versioned_json<T: DeserializeOwned + Default>(&self, key: &str, height: u32) -> Result<T> {
+        match self.db.versioned_get(key, height)? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(T::default()),
+        }
+    }
+
+    fn get_height(&self, key: &str) -> Result<u32> {
+        match self.db.get(key)? {
+            Some(str) => Ok(str.parse::<u32>()?),
+            _ => Ok(0),
+        }
+    }
+}
+
+/// Whether any slot of `address` has a non-zero value at `height`, the
+/// counterpart of `redis_has_storage`.
+#[cfg(feature = "rocksdb")]
+pub(crate) fn rocks_has_storage(
+    db: &RocksDb,
+    prefix: &str,
+    height: u32,
+    address: H160,
+) -> Result<bool> {
+    let mut found = false;
+    db.scan_versioned(
+        &keys::state_index_prefix(prefix, address),
+        "",
+        height,
+        |_, value| {
+            found = !serde_json::from_slice::<H256>(value)?.is_zero();
+            Ok(!found)
+        },
+    )?;
+    Ok(found)
+}
+
+#[cfg(feature = "rocksdb")]
+impl Getter for RocksGetter {
+    /// Also where a secondary handle catches up with the exporter.
+    fn latest_height(&self) -> Result<u32> {
+        self.db.catch_up()?;
+        self.get_height(&keys::latest_height_key(&self.prefix))
+    }
+    fn lowest_height(&self) -> Result<u32> {
+        self.get_height(&keys::lowest_height_key(&self.prefix))
+    }
+    fn rollback_count(&self) -> Result<u64> {
+        match self.db.get(&keys::rollback_count_key(&self.prefix))? {
+            Some(count) => Ok(count.parse::<u64>()?),
+            None => Ok(0),
+        }
+    }
+    fn get_balance(&self, height: u32, address: H160) -> Result<U256> {
+        self.versioned_json(&keys::balance_key(&self.prefix, address), height)
+    }
+    fn get_nonce(&self, height: u32, address: H160) -> Result<U256> {
+        self.versioned_json(&keys::nonce_key(&self.prefix, address), height)
+    }
+    /// Falls back to the code versioned under `code_key` by earlier exports.
+    fn get_byte_code(&self, height: u32, address: H160) -> Result<Vec<u8>> {
+        if let Some(hash) = self.get_code_hash(height, address)? {
+            return Ok(self.get_code_by_hash(hash)?.unwrap_or_default());
+        }
+        let code_key = keys::code_key(&self.prefix, address);
+        match self.db.versioned_get(&code_key, height)? {
+            Some(s) => Ok(hex::decode(s)?),
+            None => Ok(Vec::new()),
+        }
+    }
+    fn get_code_hash(&self, height: u32, address: H160) -> Result<Option<H256>> {
+        self.versioned_json(&keys::code_hash_key(&self.prefix, address), height)
+    }
+    fn get_code_by_hash(&self, hash: H256) -> Result<Option<Vec<u8>>> {
+        match self.db.get(&keys::code_by_hash_key(&self.prefix, hash))? {
+            Some(s) => Ok(Some(hex::decode(s)?)),
+            None => Ok(None),
+        }
+    }
+    fn get_account_basic(&self, height: u32, address: H160) -> Result<AccountBasic> {
+        Ok(AccountBasic {
+            balance: self.get_balance(height, address)?,
+            code: self.get_byte_code(height, address)?,
+            nonce: self.get_nonce(height, address)?,
+        })
+    }
+    fn addr_state_exists(&self, height: u32, address: H160) -> Result<bool> {
+        let state_addr_key = keys::state_addr_key(&self.prefix, address);
+        Ok(self.db.versioned_get(&state_addr_key, height)?.is_some())
+    }
+    fn get_state(&self, height: u32, address: H160, index: H256) -> Result<H256> {
+        self.versioned_json(&keys::state_key(&self.prefix, address, index), height)
+    }
+    fn get_storage_range(
+        &self,
+        height: u32,
+        address: H160,
+        start: H256,
+        limit: usize,
+    ) -> Result<StorageRange> {
+        let state_prefix = keys::state_index_prefix(&self.prefix, address);
+        let mut slots = Vec::new();
+        self.db.scan_versioned(
+            &state_prefix,
+            &hex::encode(start),
+            height,
+            |index, value| {
+                let index = match hex::decode(index) {
+                    Ok(index) if index.len() == H256::len_bytes() => H256::from_slice(&index),
+                    _ => return Ok(true),
+                };
+                let value: H256 = serde_json::from_slice(value)?;
+                if !value.is_zero() {
+                    slots.push((index, value));
+                }
+                Ok(slots.len() <= limit)
+            },
+        )?;
+        Ok(StorageRange::from_sorted(slots, limit))
+    }
+    fn get_account_range(&self, height: u32, start: H160, limit: usize) -> Result<AccountRange> {
+        // the first `limit` + 1 addresses of each kind of key hold the first
+        // `limit` + 1 of all
+        let mut addresses = BTreeSet::new();
+        for account_prefix in keys::KeySchema::Flat.account_prefixes(&self.prefix) {
+            let mut found = 0;
+            self.db.scan_versioned(
+                &account_prefix,
+                &hex::encode(start),
+                height,
+                |address, _| {
+                    if let Ok(address) = hex::decode(address) {
+                        if address.len() == H160::len_bytes() {
+                            addresses.insert(H160::from_slice(&address));
+                            found += 1;
+                        }
+                    }
+                    Ok(found <= limit)
+                },
+            )?;
+        }
+
+        let mut accounts = Vec::new();
+        for address in addresses.into_iter().take(limit.saturating_add(1)) {
+            let code_hash = match self.get_code_hash(height, address)? {
+                Some(hash) => Some(hash),
+                None => {
+                    let code_key = keys::code_key(&self.prefix, address);
+                    match self.db.versioned_get(&code_key, height)? {
+                        Some(code) if !code.is_empty() => Some(code_hash(&hex::decode(code)?)),
+                        _ => None,
+                    }
+                }
+            };
+            let summary = AccountSummary {
+                balance: self.get_balance(height, address)?,
+                nonce: self.get_nonce(height, address)?,
+                code_hash,
+            };
+            accounts.push((address, summary));
+        }
+        Ok(AccountRange::from_sorted(accounts, limit))
+    }
+    fn get_block_hash_by_height(&self, height: U256) -> Result<Option<H256>> {
+        self.get_json(&keys::block_hash_key(&self.prefix, height))
+    }
+    fn get_height_by_block_hash(&self, block_hash: H256) -> Result<Option<U256>> {
+        self.get_json(&keys::block_height_key(&self.prefix, block_hash))
+    }
+    fn get_block_by_hash(&self, block_hash: H256) -> Result<Option<Block>> {
+        self.get_payload(&keys::block_key(&self.prefix, block_hash), decode_block)
+    }
+    fn get_transaction_receipt_by_block_hash(
+        &self,
+        block_hash: H256,
+    ) -> Result<Option<Vec<Receipt>>> {
+        self.get_payload(
+            &keys::receipt_key(&self.prefix, block_hash),
+            decode_receipts,
+        )
+    }
+    fn get_transaction_status_by_block_hash(
+        &self,
+        block_hash: H256,
+    ) -> Result<Option<Vec<TransactionStatus>>> {
+        self.get_payload(&keys::status_key(&self.prefix, block_hash), decode_statuses)
+    }
+    fn get_transaction_index_by_tx_hash(&self, tx_hash: H256) -> Result<Option<(H256, u32)>> {
+        self.get_json(&keys::transaction_index_key(&self.prefix, tx_hash))
+    }
+    fn get_pending_balance(&self, address: H160) -> Result<Option<U256>> {
+        self.get_json(&keys::pending_balance_key(&self.prefix, address))
+    }
+    fn get_pending_nonce(&self, address: H160) -> Result<Option<U256>> {
+        self.get_json(&keys::pending_nonce_key(&self.prefix, address))
+    }
+    fn get_pending_byte_code(&self, address: H160) -> Result<Option<Vec<u8>>> {
+        match self
+            .db
+            .get(&keys::pending_code_key(&self.prefix, address))?
+        {
+            Some(s) => Ok(Some(hex::decode(s)?)),
+            None => Ok(None),
+        }
+    }
+    fn get_pending_state(&self, address: H160, index: H256) -> Result<Option<H256>> {
+        self.get_json(&keys::pending_state_key(&self.prefix, address, index))
+    }
+    fn get_total_issuance(&self, height: u32) -> Result<U256> {
+        self.versioned_json(&keys::total_issuance_key(&self.prefix), height)
+    }
+    fn get_allowances(&self, height: u32, owner: H160, spender: H160) -> Result<U256> {
+        self.versioned_json(&keys::allowances_key(&self.prefix, owner, spender), height)
+    }
+    fn get_logs(
+        &self,
+        from: u32,
+        to: u32,
+        addresses: &[H160],
+        topics: &[Option<Vec<H256>>],
+        limit: usize,
+    ) -> Result<Vec<IndexedLog>> {
+        if from > to {
+            return Ok(vec![]);
+        }
+        let logs_prefix = keys::logs_index_prefix(&self.prefix);
+        let lower = keys::versioned_key(&logs_prefix, from).into_bytes();
+        let mut upper = keys::versioned_key(&logs_prefix, to).into_bytes();
+        upper.push(0);
+
+        let mut logs = Vec::new();
+        for (_, value) in self.db.range(lower, upper, IteratorMode::Start) {
+            let block_logs: Vec<IndexedLog> = serde_json::from_slice(&value)?;
+            logs.extend(
+                block_logs
+                    .into_iter()
+                    .filter(|log| log.matches(addresses, topics)),
+            );
+            if logs.len() >= limit {
+                logs.truncate(limit);
+                break;
+            }
+        }
+        Ok(logs)
+    }
+    fn get_state_diff(&self, height: u32) -> Result<Option<StateDiff>> {
+        self.get_json(&keys::state_diff_key(&self.prefix, height))
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use {
+        super::pg_logs_query,
+        primitive_types::{H160, H256},
+    };
+
+    #[test]
+    fn test_pg_logs_query() {
+        let (sql, params) = pg_logs_query(1, 9, &[], &[], 10).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM logs WHERE block_number >= $1 AND block_number <= $2 \
+             ORDER BY block_number, log_index LIMIT $3"
+        );
+        assert_eq!(params.len(), 3);
+
+        let (sql, params) = pg_logs_query(
+            1,
+            9,
+            &[H160::repeat_byte(1)],
+            &[None, Some(vec![H256::repeat_byte(2)]), Some(vec![])],
+            10,
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM logs WHERE block_number >= $1 AND block_number <= $2 \
+             AND address = ANY($3) AND topic1 = ANY($4) \
+             ORDER BY block_number, log_index LIMIT $5"
+        );
+        assert_eq!(params.len(), 5);
+
+        // Logs carry at most 4 topics.
+        let fifth = |wanted| vec![None, None, None, None, Some(wanted)];
+        assert!(pg_logs_query(1, 9, &[], &fifth(vec![H256::zero()]), 10).is_none());
+        assert!(pg_logs_query(1, 9, &[], &fifth(vec![]), 10).is_some());
+    }
 }
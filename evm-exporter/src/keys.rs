@@ -1,13 +1,29 @@
-use primitive_types::{H160, H256, U256};
+use {
+    crate::error::{Error, Result},
+    primitive_types::{H160, H256, U256},
+    std::str::FromStr,
+};
 
 pub fn balance_key(prefix: &str, addr: H160) -> String {
     format!("{}:balance:addr.{}", prefix, hex::encode(addr))
 }
 
+/// Versioned code of `addr` as written before codes were stored by hash, only
+/// read as a fallback now.
 pub fn code_key(prefix: &str, addr: H160) -> String {
     format!("{}:code:addr.{}", prefix, hex::encode(addr))
 }
 
+/// Versioned key holding the hash of the code of `addr`.
+pub fn code_hash_key(prefix: &str, addr: H160) -> String {
+    format!("{}:code_hash:addr.{}", prefix, hex::encode(addr))
+}
+
+/// Key holding a code under its keccak hash, written once per distinct code.
+pub fn code_by_hash_key(prefix: &str, hash: H256) -> String {
+    format!("{}:code_by_hash:hash.{}", prefix, hex::encode(hash))
+}
+
 pub fn nonce_key(prefix: &str, addr: H160) -> String {
     format!("{}:nonce:addr.{}", prefix, hex::encode(addr))
 }
@@ -26,6 +42,195 @@ pub fn state_key(prefix: &str, addr: H160, index: H256) -> String {
         hex::encode(index)
     )
 }
+/// Common prefix of the `state_key`s of `addr`, followed by the index.
+pub fn state_index_prefix(prefix: &str, addr: H160) -> String {
+    format!("{}:state:addr.{}:index:u256.", prefix, hex::encode(addr))
+}
+
+/// Layout of the versioned per-account keys of the Redis backends, recorded
+/// under `key_schema_key`. The other keys are the same in both.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeySchema {
+    /// `<prefix>:balance:addr.<addr>`, as written by earlier versions. The keys
+    /// of one account land on unrelated cluster slots.
+    #[default]
+    Flat,
+    /// `<prefix>:{<addr>}:balance`, every key of an account and its versions
+    /// share the `{<addr>}` hash tag and so one cluster slot.
+    HashTagged,
+}
+
+/// A versioned per-account key, independent of its layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountKey {
+    Balance(H160),
+    Nonce(H160),
+    /// Code written before codes were stored by hash.
+    Code(H160),
+    CodeHash(H160),
+    StateAddr(H160),
+    State(H160, H256),
+}
+
+impl KeySchema {
+    /// Value stored under `key_schema_key`, a missing marker means `Flat`.
+    pub fn version(self) -> u32 {
+        match self {
+            KeySchema::Flat => 1,
+            KeySchema::HashTagged => 2,
+        }
+    }
+
+    pub fn from_version(version: u32) -> Result<Self> {
+        match version {
+            1 => Ok(KeySchema::Flat),
+            2 => Ok(KeySchema::HashTagged),
+            _ => Err(Error::UnsupportedKeySchema(version.to_string())),
+        }
+    }
+
+    pub fn account_key(self, prefix: &str, key: AccountKey) -> String {
+        match self {
+            KeySchema::Flat => match key {
+                AccountKey::Balance(addr) => balance_key(prefix, addr),
+                AccountKey::Nonce(addr) => nonce_key(prefix, addr),
+                AccountKey::Code(addr) => code_key(prefix, addr),
+                AccountKey::CodeHash(addr) => code_hash_key(prefix, addr),
+                AccountKey::StateAddr(addr) => state_addr_key(prefix, addr),
+                AccountKey::State(addr, index) => state_key(prefix, addr, index),
+            },
+            KeySchema::HashTagged => {
+                let (addr, kind) = match key {
+                    AccountKey::Balance(addr) => (addr, "balance".to_string()),
+                    AccountKey::Nonce(addr) => (addr, "nonce".to_string()),
+                    AccountKey::Code(addr) => (addr, "code".to_string()),
+                    AccountKey::CodeHash(addr) => (addr, "code_hash".to_string()),
+                    AccountKey::StateAddr(addr) => (addr, "state".to_string()),
+                    AccountKey::State(addr, index) => {
+                        (addr, format!("state:index.{}", hex::encode(index)))
+                    }
+                };
+                format!("{}:{{{}}}:{}", prefix, hex::encode(addr), kind)
+            }
+        }
+    }
+
+    /// Inverse of `account_key`, `None` for keys that are not per-account.
+    pub fn parse_account_key(self, prefix: &str, key: &str) -> Option<AccountKey> {
+        let rest = key.strip_prefix(prefix)?.strip_prefix(':')?;
+        let (kind, addr, index) = match self {
+            KeySchema::Flat => {
+                let (kind, rest) = rest.split_once(":addr.")?;
+                match rest.split_once(":index:u256.") {
+                    Some((addr, index)) => (kind, addr, Some(index)),
+                    None => (kind, rest, None),
+                }
+            }
+            KeySchema::HashTagged => {
+                let (addr, rest) = rest.strip_prefix('{')?.split_once("}:")?;
+                match rest.split_once(":index.") {
+                    Some((kind, index)) => (kind, addr, Some(index)),
+                    None => (rest, addr, None),
+                }
+            }
+        };
+
+        let addr = decode_hash::<20>(addr).map(H160)?;
+        Some(match (kind, index) {
+            ("balance", None) => AccountKey::Balance(addr),
+            ("nonce", None) => AccountKey::Nonce(addr),
+            ("code", None) => AccountKey::Code(addr),
+            ("code_hash", None) => AccountKey::CodeHash(addr),
+            ("state", None) => AccountKey::StateAddr(addr),
+            ("state", Some(index)) => AccountKey::State(addr, decode_hash::<32>(index).map(H256)?),
+            _ => return None,
+        })
+    }
+
+    pub fn balance_key(self, prefix: &str, addr: H160) -> String {
+        self.account_key(prefix, AccountKey::Balance(addr))
+    }
+    pub fn nonce_key(self, prefix: &str, addr: H160) -> String {
+        self.account_key(prefix, AccountKey::Nonce(addr))
+    }
+    pub fn code_key(self, prefix: &str, addr: H160) -> String {
+        self.account_key(prefix, AccountKey::Code(addr))
+    }
+    pub fn code_hash_key(self, prefix: &str, addr: H160) -> String {
+        self.account_key(prefix, AccountKey::CodeHash(addr))
+    }
+    pub fn state_addr_key(self, prefix: &str, addr: H160) -> String {
+        self.account_key(prefix, AccountKey::StateAddr(addr))
+    }
+    pub fn state_key(self, prefix: &str, addr: H160, index: H256) -> String {
+        self.account_key(prefix, AccountKey::State(addr, index))
+    }
+    /// Prefixes that the balance, nonce, code and code hash keys of every
+    /// account start with, the address follows them with `Flat`.
+    pub fn account_prefixes(self, prefix: &str) -> Vec<String> {
+        match self {
+            KeySchema::Flat => ["balance", "nonce", "code", "code_hash"]
+                .iter()
+                .map(|kind| format!("{}:{}:addr.", prefix, kind))
+                .collect(),
+            KeySchema::HashTagged => vec![format!("{}:{{", prefix)],
+        }
+    }
+}
+
+/// `AccountKey` laid out by a `KeySchema`, the key of the per-account
+/// `VersionedMap`s of the Redis backends, whose namespace is the prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemaKey(pub KeySchema, pub AccountKey);
+
+#[cfg(feature = "redis")]
+impl redis_versioned_kv::VersionedKey for SchemaKey {
+    fn versioned_key(&self, namespace: &str) -> String {
+        self.0.account_key(namespace, self.1)
+    }
+}
+
+impl FromStr for KeySchema {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "flat" => Ok(KeySchema::Flat),
+            "hash_tagged" => Ok(KeySchema::HashTagged),
+            _ => Err(Error::UnsupportedKeySchema(s.to_string())),
+        }
+    }
+}
+
+fn decode_hash<const N: usize>(s: &str) -> Option<[u8; N]> {
+    hex::decode(s).ok()?.try_into().ok()
+}
+
+/// Inverse of `hex::encode` of a `H160`, as in the members of the index keys.
+pub fn parse_h160(s: &str) -> Option<H160> {
+    decode_hash::<20>(s).map(H160)
+}
+
+/// Inverse of `hex::encode` of a `H256`, as in the members of the index keys.
+pub fn parse_h256(s: &str) -> Option<H256> {
+    decode_hash::<32>(s).map(H256)
+}
+
+pub fn key_schema_key(prefix: &str) -> String {
+    format!("{}:key_schema", prefix)
+}
+
+/// Version of the data derived from exported blocks, such as indexes, that a
+/// Redis or RocksDB store is up to date with.
+pub fn data_version_key(prefix: &str) -> String {
+    format!("{}:data_version", prefix)
+}
+
+/// Key holding the version of `key` written at `height`, used by the RocksDB
+/// backend. Versions of one key sort together, in height order.
+pub fn versioned_key(key: &str, height: u32) -> String {
+    format!("{}@{:08x}", key, height)
+}
 
 pub fn latest_height_key(prefix: &str) -> String {
     format!("{}:height", prefix)
@@ -33,6 +238,14 @@ pub fn latest_height_key(prefix: &str) -> String {
 pub fn lowest_height_key(prefix: &str) -> String {
     format!("{}:lowest_height", prefix)
 }
+/// The latest height a rollback in progress started from.
+pub fn rollback_from_key(prefix: &str) -> String {
+    format!("{}:rollback_from", prefix)
+}
+/// Number of rollbacks ever started, see `Getter::rollback_count`.
+pub fn rollback_count_key(prefix: &str) -> String {
+    format!("{}:rollbacks", prefix)
+}
 pub fn tx_state_key(prefix: &str, tx_hash: H256) -> String {
     format!("{}:tx_state:hash.{}", prefix, hex::encode(tx_hash))
 }
@@ -93,3 +306,99 @@ pub fn allowances_key(prefix: &str, owner: H160, spender: H160) -> String {
 pub fn total_issuance_key(prefix: &str) -> String {
     format!("{}:total_issuance", prefix)
 }
+
+/// Common prefix of the logs index keys, which are not versioned.
+pub fn logs_index_prefix(prefix: &str) -> String {
+    format!("{}:logs", prefix)
+}
+pub fn logs_key(prefix: &str, height: u32) -> String {
+    format!("{}:logs:height.{}", prefix, height)
+}
+pub fn logs_heights_key(prefix: &str) -> String {
+    format!("{}:logs_heights", prefix)
+}
+pub fn logs_address_key(prefix: &str, addr: H160) -> String {
+    format!("{}:logs_address:addr.{}", prefix, hex::encode(addr))
+}
+pub fn logs_topic_key(prefix: &str, position: usize, topic: H256) -> String {
+    format!(
+        "{}:logs_topic:pos.{}:topic.{}",
+        prefix,
+        position,
+        hex::encode(topic)
+    )
+}
+
+/// Common prefix of the index keys of the Redis backends, which are not
+/// versioned.
+pub fn index_prefix(prefix: &str) -> String {
+    format!("{}:index", prefix)
+}
+/// Sorted set of the hex encoded indexes of every storage slot of `addr` ever
+/// written, all scored 0 so that ZRANGEBYLEX pages them in slot order. The
+/// `{<addr>}` hash tag keeps it on the cluster slot of the `HashTagged` keys
+/// of `addr`.
+pub fn storage_index_key(prefix: &str, addr: H160) -> String {
+    format!("{}:index:storage:{{{}}}", prefix, hex::encode(addr))
+}
+/// Sorted set of the hex encoded addresses of every account whose balance,
+/// nonce or code was ever written, laid out like `storage_index_key`.
+pub fn accounts_index_key(prefix: &str) -> String {
+    format!("{}:index:accounts", prefix)
+}
+
+/// Key holding the `StateDiff` of `height`, which is not versioned.
+pub fn state_diff_key(prefix: &str, height: u32) -> String {
+    format!("{}:state_diff:height.{}", prefix, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{AccountKey, KeySchema},
+        primitive_types::{H160, H256},
+    };
+
+    #[test]
+    fn test_account_key_round_trip() {
+        let addr = H160::repeat_byte(0xab);
+        let index = H256::repeat_byte(0x01);
+        for schema in [KeySchema::Flat, KeySchema::HashTagged] {
+            for key in [
+                AccountKey::Balance(addr),
+                AccountKey::Nonce(addr),
+                AccountKey::Code(addr),
+                AccountKey::CodeHash(addr),
+                AccountKey::StateAddr(addr),
+                AccountKey::State(addr, index),
+            ] {
+                let formatted = schema.account_key("evm", key);
+                assert_eq!(schema.parse_account_key("evm", &formatted), Some(key));
+            }
+            let balance_key = schema.balance_key("evm", addr);
+            assert!(schema
+                .account_prefixes("evm")
+                .iter()
+                .any(|prefix| balance_key.starts_with(prefix)));
+        }
+
+        assert_eq!(
+            KeySchema::HashTagged.balance_key("evm", addr),
+            format!("evm:{{{}}}:balance", hex::encode(addr))
+        );
+        assert_eq!(
+            KeySchema::Flat.parse_account_key("evm", &super::latest_height_key("evm")),
+            None
+        );
+        assert_eq!(
+            KeySchema::Flat.parse_account_key("evm", &super::pending_balance_key("evm", addr)),
+            None
+        );
+        for schema in [KeySchema::Flat, KeySchema::HashTagged] {
+            let index_key = super::storage_index_key("evm", addr);
+            assert_eq!(schema.parse_account_key("evm", &index_key), None);
+        }
+        assert_eq!(super::parse_h160(&hex::encode(addr)), Some(addr));
+        assert_eq!(super::parse_h256(&hex::encode(index)), Some(index));
+    }
+}
@@ -1,25 +1,54 @@
 mod error;
 pub use error::*;
 
+mod connection;
+pub use connection::*;
+
 mod types;
 pub use types::*;
 
 pub mod keys;
 
+mod codec;
+pub use codec::*;
+
+#[cfg(feature = "postgres")]
+pub mod migrations;
+
+#[cfg(feature = "postgres")]
+mod pg_types;
+
+#[cfg(feature = "redis")]
+mod redis_pool;
+#[cfg(feature = "redis")]
+pub use redis_pool::RedisPoolOptions;
+
 mod getter;
 pub use getter::*;
 
 mod setter;
 pub use setter::*;
 
+#[cfg(any(feature = "async-redis", feature = "async-postgres"))]
+mod async_getter;
+#[cfg(any(feature = "async-redis", feature = "async-postgres"))]
+pub use async_getter::*;
+
+mod block_writer;
+pub use block_writer::*;
+
+mod caching;
+pub use caching::*;
+
 mod utils;
 pub use utils::*;
 
-pub enum ConnectionType {
-    #[cfg(feature = "redis")]
-    Redis(String),
-    #[cfg(feature = "redis-cluster")]
-    RedisCluster(Vec<String>),
-    #[cfg(feature = "postgres")]
-    Postgres(String),
-}
+#[cfg(feature = "memory")]
+mod memory;
+#[cfg(feature = "memory")]
+pub use memory::MemoryDb;
+
+#[cfg(feature = "rocksdb")]
+mod rocks;
+#[cfg(feature = "rocksdb")]
+pub use rocks::RocksDb;
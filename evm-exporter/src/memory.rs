@@ -0,0 +1,548 @@
+use {
+    crate::{
+        error::{Error, Result},
+        utils::{block_logs, code_hash},
+        Block, IndexedLog, Receipt, StateDiff, TransactionStatus,
+    },
+    primitive_types::{H160, H256, U256},
+    std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    },
+};
+
+pub(crate) type Versioned<V> = BTreeMap<u32, V>;
+
+#[derive(Default)]
+pub(crate) struct MemoryData {
+    pub latest_height: u32,
+    pub lowest_height: u32,
+    pub rollbacks: u64,
+    pub balance: BTreeMap<H160, Versioned<U256>>,
+    pub nonce: BTreeMap<H160, Versioned<U256>>,
+    pub code_hash: BTreeMap<H160, Versioned<H256>>,
+    pub code: BTreeMap<H256, Vec<u8>>,
+    pub state: BTreeMap<(H160, H256), Versioned<H256>>,
+    pub block_hash: BTreeMap<U256, H256>,
+    pub block_height: BTreeMap<H256, U256>,
+    pub block: BTreeMap<H256, Block>,
+    pub receipts: BTreeMap<H256, Vec<Receipt>>,
+    pub statuses: BTreeMap<H256, Vec<TransactionStatus>>,
+    pub transaction_index: BTreeMap<H256, (H256, u32)>,
+    pub logs: BTreeMap<u32, Vec<IndexedLog>>,
+    pub state_diffs: BTreeMap<u32, StateDiff>,
+    pub pending_balance: BTreeMap<H160, U256>,
+    pub pending_nonce: BTreeMap<H160, U256>,
+    pub pending_code: BTreeMap<H160, Vec<u8>>,
+    pub pending_state: BTreeMap<(H160, H256), H256>,
+    pub total_issuance: Versioned<U256>,
+    pub allowances: BTreeMap<(H160, H160), Versioned<U256>>,
+}
+
+/// Shared in-process store backing `MemoryGetter` and `MemorySetter`.
+///
+/// Cloning is cheap and every clone sees the same data, so a getter and a
+/// setter built from clones of one `MemoryDb` behave like two connections to
+/// the same database.
+#[derive(Clone, Default)]
+pub struct MemoryDb(Arc<RwLock<MemoryData>>);
+
+impl MemoryDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process-wide store registered under `name`, created on first use.
+    pub fn shared(name: &str) -> Result<Self> {
+        static SHARED: OnceLock<Mutex<BTreeMap<String, MemoryDb>>> = OnceLock::new();
+
+        let mut shared = SHARED
+            .get_or_init(Default::default)
+            .lock()
+            .map_err(|_| Error::LockPoisoned)?;
+        Ok(shared.entry(name.to_string()).or_default().clone())
+    }
+
+    pub(crate) fn read(&self) -> Result<RwLockReadGuard<'_, MemoryData>> {
+        self.0.read().map_err(|_| Error::LockPoisoned)
+    }
+
+    pub(crate) fn write(&self) -> Result<RwLockWriteGuard<'_, MemoryData>> {
+        self.0.write().map_err(|_| Error::LockPoisoned)
+    }
+}
+
+impl MemoryData {
+    pub(crate) fn insert_byte_code(&mut self, height: u32, address: H160, code: Vec<u8>) {
+        let hash = code_hash(&code);
+        self.code_hash
+            .entry(address)
+            .or_default()
+            .insert(height, hash);
+        self.code.entry(hash).or_insert(code);
+    }
+
+    pub(crate) fn insert_block_info(
+        &mut self,
+        block: Block,
+        receipts: Vec<Receipt>,
+        statuses: Vec<TransactionStatus>,
+    ) {
+        let block_hash = block.header.hash();
+        let height = block.header.number;
+
+        let logs = block_logs(&block, &statuses);
+        if !logs.is_empty() {
+            self.logs.insert(height.as_u32(), logs);
+        }
+        self.block_hash.insert(height, block_hash);
+        self.block_height.insert(block_hash, height);
+        for (i, tx) in statuses.iter().enumerate() {
+            self.transaction_index
+                .insert(tx.transaction_hash, (block_hash, i as u32));
+        }
+        self.block.insert(block_hash, block);
+        self.receipts.insert(block_hash, receipts);
+        self.statuses.insert(block_hash, statuses);
+    }
+}
+
+/// Value with the greatest height <= `height`.
+pub(crate) fn versioned_get<V>(versions: Option<&Versioned<V>>, height: u32) -> Option<&V> {
+    versions.and_then(|v| v.range(..=height).next_back().map(|(_, value)| value))
+}
+
+/// Drops the versions shadowed at `height`, keeping the newest one at or below it.
+pub(crate) fn prune_versions<V>(versions: &mut Versioned<V>, height: u32) {
+    if let Some(newest) = versions.range(..=height).next_back().map(|(h, _)| *h) {
+        *versions = versions.split_off(&newest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::MemoryDb,
+        crate::{
+            code_hash, AccountSummary, Change, ConnectionType, Getter, IndexedLog, MemoryGetter,
+            MemorySetter, Setter,
+        },
+        primitive_types::{H160, H256, U256},
+    };
+
+    fn open() -> (MemoryGetter, MemorySetter) {
+        let db = MemoryDb::new();
+        (
+            MemoryGetter::new(ConnectionType::Memory(db.clone()), String::new()),
+            MemorySetter::new(ConnectionType::Memory(db), String::new()),
+        )
+    }
+
+    #[test]
+    fn test_versioned_balance() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(1);
+
+        setter.set_balance(4, addr, U256::from(10)).unwrap();
+        setter.set_balance(9, addr, U256::from(20)).unwrap();
+
+        for i in 0..4 {
+            assert_eq!(getter.get_balance(i, addr).unwrap(), U256::zero());
+        }
+        for i in 4..9 {
+            assert_eq!(getter.get_balance(i, addr).unwrap(), U256::from(10));
+        }
+        for i in 9..12 {
+            assert_eq!(getter.get_balance(i, addr).unwrap(), U256::from(20));
+        }
+
+        setter.remove_balance(9, addr).unwrap();
+        assert_eq!(getter.get_balance(11, addr).unwrap(), U256::from(10));
+    }
+
+    #[test]
+    fn test_state_and_heights() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(2);
+        let index = H256::repeat_byte(3);
+
+        assert!(!getter.addr_state_exists(5, addr).unwrap());
+        setter
+            .set_state(5, addr, index, H256::repeat_byte(4))
+            .unwrap();
+        assert!(!getter.addr_state_exists(4, addr).unwrap());
+        assert!(getter.addr_state_exists(5, addr).unwrap());
+        assert_eq!(getter.get_state(4, addr, index).unwrap(), H256::zero());
+        assert_eq!(
+            getter.get_state(6, addr, index).unwrap(),
+            H256::repeat_byte(4)
+        );
+
+        setter.set_height(6).unwrap();
+        setter.set_lowest_height(1).unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 6);
+        assert_eq!(getter.lowest_height().unwrap(), 1);
+
+        setter.clear().unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 0);
+        assert!(!getter.addr_state_exists(6, addr).unwrap());
+    }
+
+    #[test]
+    fn test_storage_range() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(2);
+        for i in 1..=4 {
+            setter
+                .set_state(5, addr, H256::repeat_byte(i), H256::repeat_byte(i))
+                .unwrap();
+        }
+        setter
+            .set_state(6, addr, H256::repeat_byte(2), H256::zero())
+            .unwrap();
+
+        let range = getter
+            .get_storage_range(6, addr, H256::repeat_byte(1), 2)
+            .unwrap();
+        assert_eq!(
+            range.slots,
+            vec![
+                (H256::repeat_byte(1), H256::repeat_byte(1)),
+                (H256::repeat_byte(3), H256::repeat_byte(3)),
+            ]
+        );
+        assert_eq!(range.next, Some(H256::repeat_byte(4)));
+
+        let range = getter
+            .get_storage_range(5, addr, H256::repeat_byte(2), 3)
+            .unwrap();
+        assert_eq!(range.slots.len(), 3);
+        assert_eq!(range.next, None);
+    }
+
+    #[test]
+    fn test_account_range() {
+        let (getter, setter) = open();
+        setter
+            .set_balance(3, H160::repeat_byte(1), U256::one())
+            .unwrap();
+        setter
+            .set_nonce(3, H160::repeat_byte(2), U256::one())
+            .unwrap();
+        setter
+            .set_balance(4, H160::repeat_byte(2), U256::from(2))
+            .unwrap();
+        setter
+            .set_byte_code(5, H160::repeat_byte(3), vec![0x60])
+            .unwrap();
+
+        let range = getter.get_account_range(4, H160::zero(), 1).unwrap();
+        assert_eq!(range.accounts.len(), 1);
+        assert_eq!(range.accounts[0].0, H160::repeat_byte(1));
+        assert_eq!(range.next, Some(H160::repeat_byte(2)));
+
+        let range = getter
+            .get_account_range(5, H160::repeat_byte(2), 10)
+            .unwrap();
+        assert_eq!(
+            range.accounts,
+            vec![
+                (
+                    H160::repeat_byte(2),
+                    AccountSummary {
+                        balance: U256::from(2),
+                        nonce: U256::one(),
+                        code_hash: None,
+                    }
+                ),
+                (
+                    H160::repeat_byte(3),
+                    AccountSummary {
+                        code_hash: Some(code_hash(&[0x60])),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(range.next, None);
+    }
+
+    #[test]
+    fn test_commit_block() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(5);
+
+        let mut writer = setter.begin_block(3);
+        writer.set_balance(addr, U256::from(7));
+        writer.set_nonce(addr, U256::one());
+        drop(writer);
+        assert_eq!(getter.latest_height().unwrap(), 0);
+        assert_eq!(getter.get_balance(3, addr).unwrap(), U256::zero());
+
+        let mut writer = setter.begin_block(3);
+        writer.set_balance(addr, U256::from(7));
+        writer.set_nonce(addr, U256::one());
+        writer.commit().unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 3);
+        assert_eq!(getter.get_balance(3, addr).unwrap(), U256::from(7));
+        assert_eq!(getter.get_nonce(3, addr).unwrap(), U256::one());
+    }
+
+    #[test]
+    fn test_bulk_setters() {
+        let (single, single_setter) = open();
+        let (bulk, bulk_setter) = open();
+        let addrs = [H160::repeat_byte(1), H160::repeat_byte(2)];
+        let index = H256::repeat_byte(3);
+
+        for (i, addr) in addrs.iter().enumerate() {
+            let i = i as u8 + 1;
+            single_setter.set_balance(4, *addr, U256::from(i)).unwrap();
+            single_setter.set_nonce(4, *addr, U256::from(i)).unwrap();
+            single_setter
+                .set_byte_code(4, *addr, vec![0x60, i])
+                .unwrap();
+            single_setter
+                .set_state(4, *addr, index, H256::repeat_byte(i))
+                .unwrap();
+        }
+        bulk_setter
+            .set_balances(
+                4,
+                vec![(addrs[0], U256::from(1)), (addrs[1], U256::from(2))],
+            )
+            .unwrap();
+        bulk_setter
+            .set_nonces(
+                4,
+                vec![(addrs[0], U256::from(1)), (addrs[1], U256::from(2))],
+            )
+            .unwrap();
+        bulk_setter
+            .set_byte_codes(
+                4,
+                vec![(addrs[0], vec![0x60, 1]), (addrs[1], vec![0x60, 2])],
+            )
+            .unwrap();
+        bulk_setter
+            .set_states(
+                4,
+                vec![
+                    (addrs[0], index, H256::repeat_byte(1)),
+                    (addrs[1], index, H256::repeat_byte(2)),
+                ],
+            )
+            .unwrap();
+
+        for height in [3, 4] {
+            for addr in addrs {
+                assert_eq!(
+                    bulk.get_balance(height, addr).unwrap(),
+                    single.get_balance(height, addr).unwrap()
+                );
+                assert_eq!(
+                    bulk.get_nonce(height, addr).unwrap(),
+                    single.get_nonce(height, addr).unwrap()
+                );
+                assert_eq!(
+                    bulk.get_byte_code(height, addr).unwrap(),
+                    single.get_byte_code(height, addr).unwrap()
+                );
+                assert_eq!(
+                    bulk.get_state(height, addr, index).unwrap(),
+                    single.get_state(height, addr, index).unwrap()
+                );
+                assert_eq!(
+                    bulk.addr_state_exists(height, addr).unwrap(),
+                    single.addr_state_exists(height, addr).unwrap()
+                );
+            }
+        }
+        assert_eq!(bulk.get_balance(4, addrs[1]).unwrap(), U256::from(2));
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(5);
+        let index = H256::repeat_byte(1);
+        setter.set_balance(2, addr, U256::from(7)).unwrap();
+
+        let mut writer = setter.begin_block(3);
+        writer.set_balance(addr, U256::from(7));
+        writer.set_nonce(addr, U256::one());
+        writer.set_state(addr, index, H256::repeat_byte(2));
+        writer.record_state_diff(&getter).unwrap();
+        writer.commit().unwrap();
+
+        let diff = getter.get_state_diff(3).unwrap().unwrap();
+        let account = &diff[&addr];
+        assert_eq!(account.balance, None);
+        assert_eq!(
+            account.nonce,
+            Some(Change {
+                from: U256::zero(),
+                to: U256::one(),
+            })
+        );
+        assert_eq!(
+            account.storage[&index],
+            Change {
+                from: H256::zero(),
+                to: H256::repeat_byte(2),
+            }
+        );
+        assert_eq!(getter.get_state_diff(2).unwrap(), None);
+
+        setter.rollback_to(2).unwrap();
+        assert_eq!(getter.get_state_diff(3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_logs() {
+        let db = MemoryDb::new();
+        let getter = MemoryGetter::new(ConnectionType::Memory(db.clone()), String::new());
+        let log = |block_number, address, topic| IndexedLog {
+            block_number,
+            block_hash: H256::zero(),
+            transaction_hash: H256::zero(),
+            transaction_index: 0,
+            log_index: 0,
+            transaction_log_index: 0,
+            address: H160::repeat_byte(address),
+            topics: vec![H256::repeat_byte(topic)],
+            data: vec![],
+        };
+        {
+            let mut data = db.write().unwrap();
+            data.logs.insert(2, vec![log(2, 1, 1), log(2, 2, 1)]);
+            data.logs.insert(3, vec![log(3, 1, 2)]);
+            data.logs.insert(5, vec![log(5, 1, 1)]);
+        }
+
+        let logs = getter.get_logs(0, 9, &[], &[], 10).unwrap();
+        assert_eq!(logs.len(), 4);
+        let logs = getter.get_logs(3, 4, &[], &[], 10).unwrap();
+        assert_eq!(logs, vec![log(3, 1, 2)]);
+
+        let logs = getter
+            .get_logs(0, 9, &[H160::repeat_byte(2)], &[], 10)
+            .unwrap();
+        assert_eq!(logs, vec![log(2, 2, 1)]);
+        let logs = getter
+            .get_logs(
+                0,
+                9,
+                &[H160::repeat_byte(1)],
+                &[Some(vec![H256::repeat_byte(1)])],
+                10,
+            )
+            .unwrap();
+        assert_eq!(logs, vec![log(2, 1, 1), log(5, 1, 1)]);
+
+        let logs = getter
+            .get_logs(0, 9, &[], &[Some(vec![H256::repeat_byte(1)])], 2)
+            .unwrap();
+        assert_eq!(logs, vec![log(2, 1, 1), log(2, 2, 1)]);
+        assert!(getter.get_logs(5, 2, &[], &[], 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_code_by_hash() {
+        let db = MemoryDb::new();
+        let getter = MemoryGetter::new(ConnectionType::Memory(db.clone()), String::new());
+        let setter = MemorySetter::new(ConnectionType::Memory(db.clone()), String::new());
+        let code = vec![0x60, 0x80, 0x60, 0x40];
+        let hash = crate::code_hash(&code);
+
+        setter
+            .set_byte_code(2, H160::repeat_byte(1), code.clone())
+            .unwrap();
+        setter
+            .set_byte_code(3, H160::repeat_byte(2), code.clone())
+            .unwrap();
+        assert_eq!(db.read().unwrap().code.len(), 1);
+
+        assert_eq!(getter.get_code_hash(1, H160::repeat_byte(1)).unwrap(), None);
+        assert_eq!(
+            getter.get_code_hash(3, H160::repeat_byte(1)).unwrap(),
+            Some(hash)
+        );
+        assert_eq!(getter.get_code_by_hash(hash).unwrap(), Some(code.clone()));
+        assert_eq!(getter.get_byte_code(3, H160::repeat_byte(2)).unwrap(), code);
+        assert!(getter
+            .get_byte_code(2, H160::repeat_byte(2))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_prune_below() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(6);
+
+        setter.set_nonce(2, addr, U256::from(1)).unwrap();
+        setter.set_nonce(5, addr, U256::from(2)).unwrap();
+        setter.set_nonce(8, addr, U256::from(3)).unwrap();
+        setter.set_height(8).unwrap();
+        setter.prune_below(6).unwrap();
+
+        assert_eq!(getter.lowest_height().unwrap(), 6);
+        assert_eq!(getter.get_nonce(4, addr).unwrap(), U256::zero());
+        assert_eq!(getter.get_nonce(6, addr).unwrap(), U256::from(2));
+        assert_eq!(getter.get_nonce(9, addr).unwrap(), U256::from(3));
+
+        // A height above the latest one is clamped to it.
+        setter.prune_below(20).unwrap();
+        assert_eq!(getter.lowest_height().unwrap(), 8);
+        assert_eq!(getter.get_nonce(8, addr).unwrap(), U256::from(3));
+    }
+
+    #[test]
+    fn test_rollback_to() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(7);
+
+        setter.set_balance(2, addr, U256::from(1)).unwrap();
+        setter.set_balance(5, addr, U256::from(2)).unwrap();
+        setter.set_total_issuance(5, U256::from(9)).unwrap();
+        setter.set_height(5).unwrap();
+        setter.rollback_to(3).unwrap();
+
+        assert_eq!(getter.latest_height().unwrap(), 3);
+        assert_eq!(getter.get_balance(5, addr).unwrap(), U256::from(1));
+        assert_eq!(getter.get_total_issuance(5).unwrap(), U256::zero());
+    }
+
+    #[test]
+    fn test_rollback_to_rerun() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(7);
+
+        setter.set_balance(2, addr, U256::from(1)).unwrap();
+        setter.set_balance(5, addr, U256::from(2)).unwrap();
+        setter.set_height(5).unwrap();
+        setter.set_lowest_height(4).unwrap();
+        setter.rollback_to(3).unwrap();
+        setter.rollback_to(3).unwrap();
+
+        assert_eq!(getter.latest_height().unwrap(), 3);
+        assert_eq!(getter.lowest_height().unwrap(), 3);
+        assert_eq!(getter.get_balance(5, addr).unwrap(), U256::from(1));
+    }
+
+    #[test]
+    fn test_rollback_to_above_latest() {
+        let (getter, setter) = open();
+        let addr = H160::repeat_byte(7);
+
+        setter.set_balance(2, addr, U256::from(1)).unwrap();
+        setter.set_height(3).unwrap();
+        setter.set_lowest_height(1).unwrap();
+        setter.rollback_to(8).unwrap();
+
+        assert_eq!(getter.latest_height().unwrap(), 3);
+        assert_eq!(getter.lowest_height().unwrap(), 1);
+        assert_eq!(getter.get_balance(3, addr).unwrap(), U256::from(1));
+    }
+}
@@ -0,0 +1,168 @@
+//! Conversions between the Postgres column types and the EVM types.
+
+use {
+    crate::error::{Error, Result},
+    bytes::{BufMut, BytesMut},
+    primitive_types::{H160, H256, U256},
+    r2d2_postgres::postgres::types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type},
+    std::error::Error as StdError,
+};
+
+type SqlResult<T> = std::result::Result<T, Box<dyn StdError + Sync + Send>>;
+
+const NUMERIC_POS: u16 = 0x0000;
+const NBASE: u64 = 10_000;
+
+/// `U256` stored as a `NUMERIC(78)`, which holds every 256 bit value.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PgU256(pub U256);
+
+impl ToSql for PgU256 {
+    /// Binary NUMERIC: digit count, weight, sign and scale, then base 10000
+    /// digits, most significant first.
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> SqlResult<IsNull> {
+        let mut digits = Vec::new();
+        let mut value = self.0;
+        while !value.is_zero() {
+            let (rest, digit) = value.div_mod(U256::from(NBASE));
+            digits.push(digit.as_u64() as i16);
+            value = rest;
+        }
+        let weight = digits.len().saturating_sub(1) as i16;
+        // Trailing zero digits are implied by the weight.
+        let skip = digits.iter().take_while(|digit| **digit == 0).count();
+
+        out.put_i16((digits.len() - skip) as i16);
+        out.put_i16(weight);
+        out.put_u16(NUMERIC_POS);
+        out.put_u16(0);
+        for digit in digits[skip..].iter().rev() {
+            out.put_i16(*digit);
+        }
+        Ok(IsNull::No)
+    }
+
+    accepts!(NUMERIC);
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for PgU256 {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> SqlResult<Self> {
+        let field = |i: usize| -> SqlResult<u16> {
+            raw.get(i * 2..i * 2 + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or_else(|| "truncated numeric".into())
+        };
+        let ndigits = field(0)? as usize;
+        let weight = field(1)? as i16;
+        if field(2)? != NUMERIC_POS {
+            return Err("negative or NaN numeric is not a U256".into());
+        }
+
+        let mut value = U256::zero();
+        for i in 0..ndigits {
+            let digit = field(4 + i)?;
+            if weight < i as i16 {
+                if digit != 0 {
+                    return Err("fractional numeric is not a U256".into());
+                }
+                continue;
+            }
+            value = value
+                .checked_mul(U256::from(NBASE))
+                .and_then(|v| v.checked_add(U256::from(digit)))
+                .ok_or("numeric overflows U256")?;
+        }
+        for _ in ndigits as i16..=weight {
+            value = value
+                .checked_mul(U256::from(NBASE))
+                .ok_or("numeric overflows U256")?;
+        }
+        Ok(PgU256(value))
+    }
+
+    accepts!(NUMERIC);
+}
+
+pub(crate) fn h160(bytes: &[u8]) -> Result<H160> {
+    if bytes.len() != H160::len_bytes() {
+        return Err(Error::LengthMismatch);
+    }
+    Ok(H160::from_slice(bytes))
+}
+
+pub(crate) fn h256(bytes: &[u8]) -> Result<H256> {
+    if bytes.len() != H256::len_bytes() {
+        return Err(Error::LengthMismatch);
+    }
+    Ok(H256::from_slice(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::PgU256,
+        bytes::BytesMut,
+        primitive_types::U256,
+        r2d2_postgres::postgres::types::{FromSql, ToSql, Type},
+    };
+
+    #[test]
+    fn test_numeric_round_trip() {
+        for value in [
+            U256::zero(),
+            U256::from(9999),
+            U256::from(10_000),
+            U256::from(1_000_000_007),
+            U256::exp10(40),
+            U256::MAX,
+        ] {
+            let mut raw = BytesMut::new();
+            PgU256(value).to_sql(&Type::NUMERIC, &mut raw).unwrap();
+            assert_eq!(PgU256::from_sql(&Type::NUMERIC, &raw).unwrap().0, value);
+        }
+    }
+
+    /// Binary NUMERIC as sent by Postgres: digit count, weight, sign, scale
+    /// and then the base 10000 digits.
+    fn numeric(weight: i16, digits: &[i16]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for field in [digits.len() as i16, weight, 0, 0].iter().chain(digits) {
+            raw.extend_from_slice(&field.to_be_bytes());
+        }
+        raw
+    }
+
+    #[test]
+    fn test_numeric_wire_format() {
+        let max = [
+            11, 5792, 892, 3731, 6195, 4235, 7098, 5008, 6879, 785, 3269, 9846, 6564, 564, 394,
+            5758, 4007, 9131, 2963, 9935,
+        ];
+        for (value, raw) in [
+            (U256::zero(), numeric(0, &[])),
+            (U256::from(10_000), numeric(1, &[1])),
+            (U256::from(1_200_000_000u64), numeric(2, &[12])),
+            (U256::from(100_000_001), numeric(2, &[1, 0, 1])),
+            (U256::exp10(40), numeric(10, &[1])),
+            (U256::MAX, numeric(19, &max)),
+        ] {
+            let mut encoded = BytesMut::new();
+            PgU256(value).to_sql(&Type::NUMERIC, &mut encoded).unwrap();
+            assert_eq!(&encoded[..], &raw[..], "{value}");
+            assert_eq!(PgU256::from_sql(&Type::NUMERIC, &raw).unwrap().0, value);
+        }
+    }
+
+    #[test]
+    fn test_numeric_rejects() {
+        let mut negative = numeric(0, &[1]);
+        negative[4] = 0x40;
+        assert!(PgU256::from_sql(&Type::NUMERIC, &negative).is_err());
+        // 0.5
+        assert!(PgU256::from_sql(&Type::NUMERIC, &numeric(-1, &[5000])).is_err());
+        // 10^80
+        assert!(PgU256::from_sql(&Type::NUMERIC, &numeric(20, &[1])).is_err());
+        assert!(PgU256::from_sql(&Type::NUMERIC, &numeric(0, &[1])[..8]).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Connection pools of the Redis getters and setters.
+
+use {
+    crate::error::Result,
+    r2d2::{CustomizeConnection, ManageConnection, Pool},
+    redis::{RedisError, RedisResult},
+    std::time::Duration,
+};
+
+/// Pool settings, read from the query of the storage url, e.g.
+/// `redis://127.0.0.1:6379/0?pool_size=32&response_timeout=500`:
+///
+/// - `pool_size`: most connections kept open, 16 by default
+/// - `pool_min_idle`: idle connections kept ready, `pool_size` by default
+/// - `connection_timeout`: milliseconds to wait for a free connection, 30000
+///   by default
+/// - `response_timeout`: milliseconds to wait for a reply, unbounded by
+///   default
+/// - `idle_timeout`: milliseconds before an idle connection is closed, 600000
+///   by default
+///
+/// A cluster url takes them once, after the last node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedisPoolOptions {
+    pub max_size: u32,
+    pub min_idle: Option<u32>,
+    pub connection_timeout: Duration,
+    pub response_timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for RedisPoolOptions {
+    fn default() -> Self {
+        Self {
+            max_size: 16,
+            min_idle: None,
+            connection_timeout: Duration::from_secs(30),
+            response_timeout: None,
+            idle_timeout: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+impl RedisPoolOptions {
+    /// Splits the pool settings off `url`, other query parameters are left to
+    /// the redis client.
+    pub fn from_url(url: &str) -> Result<(String, Self)> {
+        let mut options = Self::default();
+        let (base, query) = match url.split_once('?') {
+            Some(split) => split,
+            None => return Ok((url.to_string(), options)),
+        };
+
+        let mut rest = Vec::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let millis = || value.parse().map(Duration::from_millis);
+            match name {
+                "pool_size" => options.max_size = value.parse()?,
+                "pool_min_idle" => options.min_idle = Some(value.parse()?),
+                "connection_timeout" => options.connection_timeout = millis()?,
+                "response_timeout" => options.response_timeout = Some(millis()?),
+                "idle_timeout" => options.idle_timeout = Some(millis()?),
+                _ => rest.push(pair),
+            }
+        }
+
+        let url = if rest.is_empty() {
+            base.to_string()
+        } else {
+            format!("{}?{}", base, rest.join("&"))
+        };
+        Ok((url, options))
+    }
+
+    /// `from_url` for the node urls of a cluster, which all carry the same
+    /// settings.
+    #[cfg(feature = "redis-cluster")]
+    pub fn from_cluster_urls(urls: &[String]) -> Result<(Vec<String>, Self)> {
+        let mut options = Self::default();
+        let mut stripped = Vec::new();
+        for url in urls {
+            let (url, node_options) = Self::from_url(url)?;
+            stripped.push(url);
+            options = node_options;
+        }
+        Ok((stripped, options))
+    }
+
+    pub(crate) fn build<M>(&self, manager: M) -> Result<Pool<M>>
+    where
+        M: ManageConnection<Error = RedisError>,
+        M::Connection: ResponseTimeout,
+    {
+        Ok(Pool::builder()
+            .max_size(self.max_size)
+            .min_idle(self.min_idle)
+            .connection_timeout(self.connection_timeout)
+            .idle_timeout(self.idle_timeout)
+            .connection_customizer(Box::new(SetResponseTimeout(self.response_timeout)))
+            .build(manager)?)
+    }
+}
+
+/// Connections whose replies can be waited for a bounded time.
+pub(crate) trait ResponseTimeout {
+    fn set_response_timeout(&self, timeout: Option<Duration>) -> RedisResult<()>;
+}
+
+impl ResponseTimeout for redis::Connection {
+    fn set_response_timeout(&self, timeout: Option<Duration>) -> RedisResult<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)
+    }
+}
+
+#[cfg(feature = "redis-cluster")]
+impl ResponseTimeout for redis::cluster::ClusterConnection {
+    fn set_response_timeout(&self, timeout: Option<Duration>) -> RedisResult<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)
+    }
+}
+
+#[derive(Debug)]
+struct SetResponseTimeout(Option<Duration>);
+
+impl<C: ResponseTimeout + Send> CustomizeConnection<C, RedisError> for SetResponseTimeout {
+    fn on_acquire(&self, conn: &mut C) -> RedisResult<()> {
+        conn.set_response_timeout(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::RedisPoolOptions, std::time::Duration};
+
+    #[test]
+    fn test_from_url() {
+        let (url, options) = RedisPoolOptions::from_url("redis://127.0.0.1:6379/0").unwrap();
+        assert_eq!(url, "redis://127.0.0.1:6379/0");
+        assert_eq!(options, RedisPoolOptions::default());
+
+        let (url, options) = RedisPoolOptions::from_url(
+            "redis://127.0.0.1:6379/0?pool_size=4&protocol=resp3&response_timeout=250",
+        )
+        .unwrap();
+        assert_eq!(url, "redis://127.0.0.1:6379/0?protocol=resp3");
+        assert_eq!(options.max_size, 4);
+        assert_eq!(options.response_timeout, Some(Duration::from_millis(250)));
+
+        assert!(RedisPoolOptions::from_url("redis://127.0.0.1/?pool_size=many").is_err());
+    }
+}
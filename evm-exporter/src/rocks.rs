@@ -0,0 +1,365 @@
+use {
+    crate::{
+        error::{Error, Result},
+        keys,
+    },
+    rocksdb::{DBIterator, IteratorMode, Options, ReadOptions, WriteBatch, DB},
+    std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex, OnceLock},
+    },
+};
+
+/// Embedded store backing `RocksGetter` and `RocksSetter`.
+///
+/// Keys and value encodings are the ones of the Redis backend. Each version
+/// of a versioned key is stored under `keys::versioned_key`, so the versions
+/// of one key are adjacent and ordered by height, and reading at a height is
+/// a single backward seek.
+#[derive(Clone)]
+pub struct RocksDb {
+    db: Arc<DB>,
+    secondary: bool,
+}
+
+impl RocksDb {
+    /// Opens `path` for reading and writing, creating it if missing.
+    pub fn open(path: &str) -> Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        Ok(Self {
+            db: Arc::new(DB::open(&opts, path)?),
+            secondary: false,
+        })
+    }
+
+    /// Read-only view of a store written by another process, e.g. a
+    /// web3-service next to the exporter. `secondary_path` holds the view's
+    /// own info logs.
+    pub fn open_secondary(path: &str, secondary_path: &str) -> Result<Self> {
+        let mut opts = Options::default();
+        opts.set_max_open_files(-1);
+        Ok(Self {
+            db: Arc::new(DB::open_as_secondary(&opts, path, secondary_path)?),
+            secondary: true,
+        })
+    }
+
+    /// Process-wide handle of `path`, opened on first use.
+    ///
+    /// RocksDB admits a single writer per directory, so a getter and a setter
+    /// opened on the same url in one process share it.
+    pub fn shared(path: &str) -> Result<Self> {
+        static SHARED: OnceLock<Mutex<BTreeMap<String, RocksDb>>> = OnceLock::new();
+
+        let mut shared = SHARED
+            .get_or_init(Default::default)
+            .lock()
+            .map_err(|_| Error::LockPoisoned)?;
+        if let Some(db) = shared.get(path) {
+            return Ok(db.clone());
+        }
+        let db = Self::open(path)?;
+        shared.insert(path.to_string(), db.clone());
+        Ok(db)
+    }
+
+    /// Picks up the latest writes of the primary, a no-op unless secondary.
+    pub(crate) fn catch_up(&self) -> Result<()> {
+        if self.secondary {
+            self.db.try_catch_up_with_primary()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.db.get(key)?.map(|value| to_string(&value)))
+    }
+
+    pub(crate) fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key)?)
+    }
+
+    /// Value of `key` with the greatest height <= `height`.
+    pub(crate) fn versioned_get(&self, key: &str, height: u32) -> Result<Option<String>> {
+        let lower = format!("{}@", key).into_bytes();
+        let mut upper = keys::versioned_key(key, height).into_bytes();
+        upper.push(0);
+        Ok(self
+            .range(lower, upper, IteratorMode::End)
+            .next()
+            .map(|(_, value)| to_string(&value)))
+    }
+
+    pub(crate) fn put(&self, key: &str, value: &str) -> Result<()> {
+        self.db.put(key, value)?;
+        Ok(())
+    }
+
+    pub(crate) fn delete(&self, key: &str) -> Result<()> {
+        self.db.delete(key)?;
+        Ok(())
+    }
+
+    pub(crate) fn write(&self, batch: WriteBatch) -> Result<()> {
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Entries with keys in `lower..upper`.
+    pub(crate) fn range(
+        &self,
+        lower: Vec<u8>,
+        upper: Vec<u8>,
+        mode: IteratorMode,
+    ) -> DBIterator<'_> {
+        let mut readopts = ReadOptions::default();
+        readopts.set_iterate_lower_bound(lower);
+        readopts.set_iterate_upper_bound(upper);
+        self.db.iterator_opt(mode, readopts)
+    }
+
+    /// Entries with keys starting with `prefix`, in key order.
+    pub(crate) fn scan(&self, prefix: &str) -> DBIterator<'_> {
+        let mut upper = prefix.as_bytes().to_vec();
+        if let Some(last) = upper.last_mut() {
+            *last += 1;
+        }
+        self.range(prefix.as_bytes().to_vec(), upper, IteratorMode::Start)
+    }
+
+    /// Visits the versioned keys `<key_prefix><suffix>` whose suffix is from
+    /// `start` on, in key order, with their latest value up to `height`. Keys
+    /// without one are skipped, and the scan stops once `visit` returns false.
+    pub(crate) fn scan_versioned(
+        &self,
+        key_prefix: &str,
+        start: &str,
+        height: u32,
+        mut visit: impl FnMut(&str, &[u8]) -> Result<bool>,
+    ) -> Result<()> {
+        let lower = format!("{}{}", key_prefix, start).into_bytes();
+        let mut upper = key_prefix.as_bytes().to_vec();
+        if let Some(last) = upper.last_mut() {
+            *last += 1;
+        }
+
+        // Versions of a key are next to each other in height order, the
+        // latest one up to `height` is known once the next key starts.
+        let mut latest: Option<(String, Box<[u8]>)> = None;
+        for (key, value) in self.range(lower, upper, IteratorMode::Start) {
+            let (key, version) = match split_versioned(&key) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            match latest.take() {
+                Some((last, value)) if last != key => {
+                    if !visit(&last[key_prefix.len()..], &value)? {
+                        return Ok(());
+                    }
+                }
+                same => latest = same,
+            }
+            if version <= height {
+                latest = Some((key.to_string(), value));
+            }
+        }
+        if let Some((last, value)) = latest {
+            visit(&last[key_prefix.len()..], &value)?;
+        }
+        Ok(())
+    }
+}
+
+fn to_string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).into_owned()
+}
+
+/// Splits a `keys::versioned_key` into the key and the height.
+pub(crate) fn split_versioned(key: &[u8]) -> Option<(&str, u32)> {
+    let (key, height) = std::str::from_utf8(key).ok()?.rsplit_once('@')?;
+    Some((key, u32::from_str_radix(height, 16).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::RocksDb,
+        crate::{ConnectionType, Getter, RocksGetter, RocksSetter, Setter},
+        primitive_types::{H160, H256, U256},
+        std::path::PathBuf,
+    };
+
+    /// Directory of a test store, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn open(name: &str) -> (TempDir, RocksGetter, RocksSetter) {
+        let path =
+            std::env::temp_dir().join(format!("evm-exporter-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let db = RocksDb::open(path.to_str().unwrap()).unwrap();
+        (
+            TempDir(path),
+            RocksGetter::new(ConnectionType::RocksDB(db.clone()), String::from("evm")),
+            RocksSetter::new(ConnectionType::RocksDB(db), String::from("evm")),
+        )
+    }
+
+    #[test]
+    fn test_versioned_get_set() {
+        let (_dir, getter, setter) = open("versioned");
+        let addr = H160::repeat_byte(1);
+        let index = H256::repeat_byte(2);
+
+        setter.set_balance(4, addr, U256::from(10)).unwrap();
+        setter.set_balance(9, addr, U256::from(20)).unwrap();
+        setter
+            .set_state(5, addr, index, H256::repeat_byte(3))
+            .unwrap();
+
+        assert_eq!(getter.get_balance(3, addr).unwrap(), U256::zero());
+        assert_eq!(getter.get_balance(8, addr).unwrap(), U256::from(10));
+        assert_eq!(getter.get_balance(9, addr).unwrap(), U256::from(20));
+        assert_eq!(getter.get_state(4, addr, index).unwrap(), H256::zero());
+        assert_eq!(
+            getter.get_state(6, addr, index).unwrap(),
+            H256::repeat_byte(3)
+        );
+        assert!(!getter.addr_state_exists(4, addr).unwrap());
+        assert!(getter.addr_state_exists(5, addr).unwrap());
+
+        setter.remove_balance(9, addr).unwrap();
+        assert_eq!(getter.get_balance(9, addr).unwrap(), U256::from(10));
+    }
+
+    #[test]
+    fn test_commit_block() {
+        let (_dir, getter, setter) = open("commit");
+        let addr = H160::repeat_byte(5);
+
+        let mut writer = setter.begin_block(3);
+        writer.set_balance(addr, U256::from(7));
+        writer.set_nonce(addr, U256::one());
+        writer.set_byte_code(addr, vec![0x60]);
+        writer.set_total_issuance(U256::from(9));
+        writer.commit().unwrap();
+
+        assert_eq!(getter.latest_height().unwrap(), 3);
+        assert_eq!(getter.get_balance(3, addr).unwrap(), U256::from(7));
+        assert_eq!(getter.get_nonce(3, addr).unwrap(), U256::one());
+        assert_eq!(getter.get_byte_code(3, addr).unwrap(), vec![0x60]);
+        assert_eq!(getter.get_total_issuance(3).unwrap(), U256::from(9));
+        assert_eq!(getter.get_nonce(2, addr).unwrap(), U256::zero());
+    }
+
+    #[test]
+    fn test_rollback_to() {
+        let (_dir, getter, setter) = open("rollback");
+        let addr = H160::repeat_byte(7);
+
+        setter.set_balance(2, addr, U256::from(1)).unwrap();
+        setter.set_balance(5, addr, U256::from(2)).unwrap();
+        setter.set_total_issuance(5, U256::from(9)).unwrap();
+        setter.set_height(5).unwrap();
+        setter.set_lowest_height(4).unwrap();
+        setter.rollback_to(3).unwrap();
+
+        assert_eq!(getter.latest_height().unwrap(), 3);
+        assert_eq!(getter.lowest_height().unwrap(), 3);
+        assert_eq!(getter.get_balance(5, addr).unwrap(), U256::from(1));
+        assert_eq!(getter.get_total_issuance(5).unwrap(), U256::zero());
+
+        // A height above the latest one does not move it forward.
+        setter.rollback_to(8).unwrap();
+        assert_eq!(getter.latest_height().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_prune_below() {
+        let (_dir, getter, setter) = open("prune");
+        let addr = H160::repeat_byte(6);
+
+        setter.set_nonce(2, addr, U256::from(1)).unwrap();
+        setter.set_nonce(5, addr, U256::from(2)).unwrap();
+        setter.set_nonce(8, addr, U256::from(3)).unwrap();
+        setter.set_height(8).unwrap();
+        setter.prune_below(6).unwrap();
+
+        assert_eq!(getter.lowest_height().unwrap(), 6);
+        assert_eq!(getter.get_nonce(4, addr).unwrap(), U256::zero());
+        assert_eq!(getter.get_nonce(6, addr).unwrap(), U256::from(2));
+        assert_eq!(getter.get_nonce(9, addr).unwrap(), U256::from(3));
+
+        // A height above the latest one is clamped to it.
+        setter.prune_below(20).unwrap();
+        assert_eq!(getter.lowest_height().unwrap(), 8);
+        assert_eq!(getter.get_nonce(8, addr).unwrap(), U256::from(3));
+    }
+
+    #[test]
+    fn test_remove_state() {
+        let (_dir, getter, setter) = open("remove_state");
+        let addr = H160::repeat_byte(3);
+        let (a, b) = (H256::repeat_byte(1), H256::repeat_byte(2));
+        setter.set_state(4, addr, a, H256::repeat_byte(5)).unwrap();
+        setter.set_state(4, addr, b, H256::repeat_byte(6)).unwrap();
+
+        // The marker stays while another slot written at the height is live.
+        setter.remove_state(4, addr, a).unwrap();
+        assert!(getter.addr_state_exists(4, addr).unwrap());
+        assert_eq!(getter.get_state(4, addr, a).unwrap(), H256::zero());
+
+        setter.remove_state(4, addr, b).unwrap();
+        assert!(!getter.addr_state_exists(4, addr).unwrap());
+    }
+
+    #[test]
+    fn test_scan_versioned() {
+        let (_dir, getter, setter) = open("scan");
+        let addr = H160::repeat_byte(2);
+        for i in 1..=4 {
+            setter
+                .set_state(5, addr, H256::repeat_byte(i), H256::repeat_byte(i))
+                .unwrap();
+        }
+        setter
+            .set_state(6, addr, H256::repeat_byte(2), H256::zero())
+            .unwrap();
+        setter
+            .set_state(7, addr, H256::repeat_byte(5), H256::repeat_byte(5))
+            .unwrap();
+        setter
+            .set_balance(5, H160::repeat_byte(1), U256::one())
+            .unwrap();
+
+        // Zero values and versions above the height are skipped.
+        let range = getter
+            .get_storage_range(6, addr, H256::repeat_byte(1), 2)
+            .unwrap();
+        assert_eq!(
+            range.slots,
+            vec![
+                (H256::repeat_byte(1), H256::repeat_byte(1)),
+                (H256::repeat_byte(3), H256::repeat_byte(3)),
+            ]
+        );
+        assert_eq!(range.next, Some(H256::repeat_byte(4)));
+        let range = getter
+            .get_storage_range(6, addr, H256::repeat_byte(4), 2)
+            .unwrap();
+        assert_eq!(range.slots.len(), 1);
+        assert_eq!(range.next, None);
+
+        let range = getter.get_account_range(4, H160::zero(), 10).unwrap();
+        assert!(range.accounts.is_empty());
+        let range = getter.get_account_range(5, H160::zero(), 10).unwrap();
+        assert_eq!(range.accounts.len(), 1);
+        assert_eq!(range.accounts[0].0, H160::repeat_byte(1));
+    }
+}
@@ -1,42 +1,102 @@
 use {
     crate::{
         error::{Error, Result},
+        codec::{decode_block, decode_statuses, encode_block, encode_receipts, encode_statuses},
         keys,
         types::{Block, TransactionStatus},
-        utils::recover_signer,
-        ConnectionType, Receipt,
+        utils::{block_logs, code_hash, recover_signer},
+        BlockChanges, BlockWriter, ConnectionType, IndexedLog, Receipt,
     },
     ethereum::LegacyTransaction,
     primitive_types::{H160, H256, U256},
-    std::str::FromStr,
 };
 
+#[cfg(any(feature = "postgres", feature = "redis"))]
+use r2d2::Pool;
+
 #[cfg(feature = "redis")]
 use { 
-    redis_versioned_kv::VersionedKVCommand,
-    redis::{Commands, Client as RedisClient },
+    crate::{
+        getter::{redis_has_storage, redis_key_schema, RedisAccounts, RedisPipelined},
+        keys::{AccountKey, KeySchema},
+        RedisPoolOptions,
+    },
+    redis_versioned_kv::{
+        ensure_loaded, vkv_compact_cmd, vkv_rollback_cmd, vkv_set_cmd, vkv_value_key,
+        with_script_reload, VersionedKVCommand,
+    },
+    redis::{Cmd, Commands, ConnectionLike, Client as RedisClient },
+    std::collections::BTreeSet,
 };
 
 #[cfg(feature = "redis-cluster")]
-use redis::cluster::ClusterClient as RedisClusterClient;
+use {
+    crate::getter::{redis_cluster_ensure_loaded, redis_cluster_masters},
+    redis::cluster::{ClusterClient as RedisClusterClient, ClusterConnection},
+};
 
 #[cfg(feature = "postgres")]
 use {
-    r2d2::Pool,
-    r2d2_postgres::{postgres::NoTls, PostgresConnectionManager},
+    crate::{migrations, pg_types::PgU256},
+    r2d2_postgres::{
+        postgres::{
+            binary_copy::BinaryCopyInWriter,
+            types::{ToSql, Type},
+            GenericClient, NoTls,
+        },
+        PostgresConnectionManager,
+    },
+    std::collections::BTreeMap,
+};
+
+#[cfg(feature = "memory")]
+use crate::memory::{prune_versions, versioned_get, MemoryData, MemoryDb};
+
+#[cfg(feature = "rocksdb")]
+use {
+    crate::{getter::rocks_has_storage, rocks::split_versioned, RocksDb},
+    rocksdb::WriteBatch,
 };
 
-pub trait Setter {
+/// Upcasts any setter to `dyn Setter`, which lets `Setter::begin_block`
+/// have a default body.
+pub trait AsDynSetter {
+    fn as_dyn_setter(&self) -> &dyn Setter;
+}
+
+impl<T: Setter> AsDynSetter for T {
+    fn as_dyn_setter(&self) -> &dyn Setter {
+        self
+    }
+}
+
+pub trait Setter: AsDynSetter {
     fn clear(&self) -> Result<()>;
     fn set_height(&self, height: u32) -> Result<()>;
     fn set_lowest_height(&self, height: u32) -> Result<()>;
+    /// Drops every version that is shadowed at `height`, keeping the newest
+    /// one at or below it for each key, and advances the lowest height to it.
+    /// A height above the latest one is clamped to it.
+    fn prune_below(&self, height: u32) -> Result<()>;
+    /// Removes everything written above `height`: versioned entries, blocks,
+    /// transaction indexes, issuance, allowances and state diffs, then makes
+    /// `height` the latest height again. The latest height never moves up, and
+    /// the lowest one is lowered to `height` if it was above.
+    fn rollback_to(&self, height: u32) -> Result<()>;
     fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()>;
+    /// Removes the balance written at exactly `height`, so reads from
+    /// `height` on see the one written below it again. The `remove_*` methods
+    /// undo a write this way on every backend, they record no tombstone.
     fn remove_balance(&self, height: u32, address: H160) -> Result<()>;
     fn set_nonce(&self, height: u32, address: H160, nonce: U256) -> Result<()>;
+    /// Removes the nonce written at exactly `height`, see `remove_balance`.
     fn remove_nonce(&self, height: u32, address: H160) -> Result<()>;
     fn set_byte_code(&self, height: u32, address: H160, code: Vec<u8>) -> Result<()>;
+    /// Removes the code written at exactly `height`, see `remove_balance`.
     fn remove_byte_code(&self, height: u32, address: H160) -> Result<()>;
     fn set_state(&self, height: u32, address: H160, index: H256, value: H256) -> Result<()>;
+    /// Removes the slot value written at exactly `height`, see
+    /// `remove_balance`.
     fn remove_state(&self, height: u32, address: H160, index: H256) -> Result<()>;
     fn set_block_info(
         &self,
@@ -59,6 +119,22 @@ pub trait Setter {
         spender: H160,
         value: U256,
     ) -> Result<()>;
+
+    /// Bulk variants of `set_balance`, `set_nonce`, `set_byte_code` and
+    /// `set_state`, writing a whole height in as few round trips as the
+    /// backend allows.
+    fn set_balances(&self, height: u32, balances: Vec<(H160, U256)>) -> Result<()>;
+    fn set_nonces(&self, height: u32, nonces: Vec<(H160, U256)>) -> Result<()>;
+    fn set_byte_codes(&self, height: u32, codes: Vec<(H160, Vec<u8>)>) -> Result<()>;
+    fn set_states(&self, height: u32, states: Vec<(H160, H256, H256)>) -> Result<()>;
+
+    /// Starts buffering the writes of `height`, see `BlockWriter`.
+    fn begin_block(&self, height: u32) -> BlockWriter<'_> {
+        BlockWriter::new(self.as_dyn_setter(), height)
+    }
+    /// Writes all changes of one height and moves the latest height to it,
+    /// as a single unit where the backend allows it.
+    fn commit_block(&self, changes: BlockChanges) -> Result<()>;
 }
 
 #[cfg(feature = "postgres")]
@@ -75,11 +151,265 @@ impl PgSetter {
                 NoTls,
             );
             let pool = r2d2::Pool::new(manager).expect("new postgres connection pool failed");
-            Self { conn: pool }
+            let setter = Self { conn: pool };
+            setter.migrate().expect("migrate postgres schema failed");
+            setter
         } else {
             panic!("Invalid connection type for Postgres")
         }
     }
+
+    /// Brings the schema up to date, `new` already does this once.
+    pub fn migrate(&self) -> Result<()> {
+        migrations::migrate(&mut *self.conn.get()?)
+    }
+}
+
+/// Versioned tables and the columns identifying a key within each.
+#[cfg(feature = "postgres")]
+const PG_VERSIONED_TABLES: &[(&str, &[&str])] = &[
+    ("balance", &["address"]),
+    ("nonce", &["address"]),
+    ("byte_code", &["address"]),
+    ("state", &["address", "idx"]),
+    ("allowances", &["owner", "spender"]),
+    ("issuance", &[]),
+];
+
+/// Binary COPY into a versioned table, with `height` appended to every row.
+#[cfg(feature = "postgres")]
+fn pg_copy_versioned<C: GenericClient>(
+    client: &mut C,
+    table: &str,
+    columns: &[(&str, Type)],
+    height: u32,
+    rows: Vec<Vec<Box<dyn ToSql + Sync>>>,
+) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut types = columns.iter().map(|(_, ty)| ty.clone()).collect::<Vec<_>>();
+    types.push(Type::INT8);
+    let sink = client.copy_in(&format!(
+        "COPY {}({}, height) FROM STDIN BINARY",
+        table,
+        columns
+            .iter()
+            .map(|(column, _)| *column)
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))?;
+    let mut writer = BinaryCopyInWriter::new(sink, &types);
+
+    let height = height as i64;
+    for row in rows.iter() {
+        let mut values = row
+            .iter()
+            .map(|v| v.as_ref() as &(dyn ToSql + Sync))
+            .collect::<Vec<_>>();
+        values.push(&height);
+        writer.write(&values)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+fn pg_copy_balances<C: GenericClient>(
+    client: &mut C,
+    height: u32,
+    balances: &[(H160, U256)],
+) -> Result<()> {
+    let rows = balances
+        .iter()
+        .map(|(address, balance)| -> Vec<Box<dyn ToSql + Sync>> {
+            vec![
+                Box::new(PgU256(*balance)),
+                Box::new(address.as_bytes().to_vec()),
+            ]
+        })
+        .collect();
+    let columns = [("balance", Type::NUMERIC), ("address", Type::BYTEA)];
+    pg_copy_versioned(client, "balance", &columns, height, rows)
+}
+
+#[cfg(feature = "postgres")]
+fn pg_copy_nonces<C: GenericClient>(
+    client: &mut C,
+    height: u32,
+    nonces: &[(H160, U256)],
+) -> Result<()> {
+    let rows = nonces
+        .iter()
+        .map(|(address, nonce)| -> Vec<Box<dyn ToSql + Sync>> {
+            vec![
+                Box::new(PgU256(*nonce)),
+                Box::new(address.as_bytes().to_vec()),
+            ]
+        })
+        .collect();
+    let columns = [("nonce", Type::NUMERIC), ("address", Type::BYTEA)];
+    pg_copy_versioned(client, "nonce", &columns, height, rows)
+}
+
+/// Stores `codes` under their hashes, skipping the ones already stored.
+#[cfg(feature = "postgres")]
+fn pg_insert_codes<'a, C: GenericClient>(
+    client: &mut C,
+    codes: impl IntoIterator<Item = (H256, &'a [u8])>,
+) -> Result<()> {
+    let codes = codes.into_iter().collect::<BTreeMap<_, _>>();
+    if codes.is_empty() {
+        return Ok(());
+    }
+
+    let hashes = codes.keys().map(|hash| hash.as_bytes()).collect::<Vec<_>>();
+    let codes = codes.values().collect::<Vec<_>>();
+    client.execute(
+        "INSERT INTO code(hash, code) SELECT * FROM UNNEST($1::BYTEA[], $2::BYTEA[]) ON CONFLICT DO NOTHING",
+        &[&hashes, &codes],
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+fn pg_copy_byte_codes<C: GenericClient>(
+    client: &mut C,
+    height: u32,
+    codes: &[(H160, Vec<u8>)],
+) -> Result<()> {
+    let hashes = codes
+        .iter()
+        .map(|(_, code)| code_hash(code))
+        .collect::<Vec<_>>();
+    pg_insert_codes(
+        client,
+        hashes
+            .iter()
+            .zip(codes.iter())
+            .map(|(hash, (_, code))| (*hash, code.as_slice())),
+    )?;
+
+    let rows = hashes
+        .iter()
+        .zip(codes.iter())
+        .map(|(hash, (address, _))| -> Vec<Box<dyn ToSql + Sync>> {
+            vec![
+                Box::new(hash.as_bytes().to_vec()),
+                Box::new(address.as_bytes().to_vec()),
+            ]
+        })
+        .collect();
+    let columns = [("code_hash", Type::BYTEA), ("address", Type::BYTEA)];
+    pg_copy_versioned(client, "byte_code", &columns, height, rows)
+}
+
+#[cfg(feature = "postgres")]
+fn pg_copy_states<C: GenericClient>(
+    client: &mut C,
+    height: u32,
+    states: &[(H160, H256, H256)],
+) -> Result<()> {
+    let rows = states
+        .iter()
+        .map(|(address, index, value)| -> Vec<Box<dyn ToSql + Sync>> {
+            vec![
+                Box::new(value.as_bytes().to_vec()),
+                Box::new(index.as_bytes().to_vec()),
+                Box::new(address.as_bytes().to_vec()),
+            ]
+        })
+        .collect();
+    let columns = [
+        ("value", Type::BYTEA),
+        ("idx", Type::BYTEA),
+        ("address", Type::BYTEA),
+    ];
+    pg_copy_versioned(client, "state", &columns, height, rows)
+}
+
+#[cfg(feature = "postgres")]
+fn pg_insert_block_info<C: GenericClient>(
+    client: &mut C,
+    block: &Block,
+    receipts: &[Receipt],
+    statuses: &[TransactionStatus],
+) -> Result<()> {
+    let block_hash = block.header.hash();
+    client.execute(
+        "INSERT INTO block_info(block_hash, block_height, block, receipt, statuses) VALUES($1, $2, $3, $4, $5)",
+        &[
+            &block_hash.as_bytes(),
+            &(block.header.number.as_u64() as i64),
+            &encode_block(block)?,
+            &encode_receipts(receipts)?,
+            &encode_statuses(statuses)?,
+        ],
+    )?;
+
+    for (i, tx) in statuses.iter().enumerate() {
+        client.execute(
+            "INSERT INTO transactions(transaction_hash, block_hash, transaction_index) VALUES($1, $2, $3)",
+            &[
+                &tx.transaction_hash.as_bytes(),
+                &block_hash.as_bytes(),
+                &(i as i64),
+            ],
+        )?;
+    }
+
+    pg_copy_logs(client, &block_logs(block, statuses))
+}
+
+#[cfg(feature = "postgres")]
+fn pg_copy_logs<C: GenericClient>(client: &mut C, logs: &[IndexedLog]) -> Result<()> {
+    if logs.is_empty() {
+        return Ok(());
+    }
+
+    let sink = client.copy_in(
+        "COPY logs(block_number, block_hash, transaction_hash, transaction_index, log_index, \
+         transaction_log_index, address, topic0, topic1, topic2, topic3, data) FROM STDIN BINARY",
+    )?;
+    let mut writer = BinaryCopyInWriter::new(
+        sink,
+        &[
+            Type::INT8,
+            Type::BYTEA,
+            Type::BYTEA,
+            Type::INT8,
+            Type::INT8,
+            Type::INT8,
+            Type::BYTEA,
+            Type::BYTEA,
+            Type::BYTEA,
+            Type::BYTEA,
+            Type::BYTEA,
+            Type::BYTEA,
+        ],
+    );
+    for log in logs.iter() {
+        let topics = (0..4)
+            .map(|i| log.topics.get(i).map(|topic| topic.as_bytes()))
+            .collect::<Vec<_>>();
+        writer.write(&[
+            &(log.block_number as i64),
+            &log.block_hash.as_bytes(),
+            &log.transaction_hash.as_bytes(),
+            &(log.transaction_index as i64),
+            &(log.log_index as i64),
+            &(log.transaction_log_index as i64),
+            &log.address.as_bytes(),
+            &topics[0],
+            &topics[1],
+            &topics[2],
+            &topics[3],
+            &log.data,
+        ])?;
+    }
+    writer.finish()?;
+    Ok(())
 }
 
 #[cfg(feature = "postgres")]
@@ -95,10 +425,13 @@ impl Setter for PgSetter {
              state,
              balance,
              byte_code,
+             code,
              issuance,
              pending_byte_code,
              pending_transactions,
-             transactions",
+             transactions,
+             logs,
+             state_diff",
             &[],
         )?;
         Ok(())
@@ -113,45 +446,110 @@ impl Setter for PgSetter {
             .execute("UPDATE common set lowest_height = $1", &[&( height as i64 )])?;
         Ok(())
     }
+    fn prune_below(&self, height: u32) -> Result<()> {
+        let mut conn = self.conn.get()?;
+        let mut tx = conn.transaction()?;
+        let latest: i64 = tx
+            .query_one("SELECT latest_height FROM common", &[])?
+            .get("latest_height");
+        let height = (height as i64).min(latest);
+
+        for (table, columns) in PG_VERSIONED_TABLES {
+            let same_key = columns
+                .iter()
+                .map(|c| format!(" AND n.{0} = o.{0}", c))
+                .collect::<String>();
+            tx.execute(
+                format!(
+                    "DELETE FROM {0} o WHERE o.height < $1 AND EXISTS \
+                     (SELECT 1 FROM {0} n WHERE n.height > o.height AND n.height <= $1{1})",
+                    table, same_key
+                )
+                .as_str(),
+                &[&height],
+            )?;
+        }
+        tx.execute("UPDATE common set lowest_height = $1", &[&height])?;
+
+        tx.commit()?;
+        Ok(())
+    }
+    fn rollback_to(&self, height: u32) -> Result<()> {
+        let height = height as i64;
+        let mut conn = self.conn.get()?;
+        let mut tx = conn.transaction()?;
+
+        for (table, _) in PG_VERSIONED_TABLES {
+            tx.execute(
+                format!("DELETE FROM {} WHERE height > $1", table).as_str(),
+                &[&height],
+            )?;
+        }
+
+        let mut transaction_hashes = vec![];
+        for row in tx.query(
+            "SELECT statuses FROM block_info WHERE block_height > $1",
+            &[&height],
+        )? {
+            let statuses = decode_statuses(row.get("statuses"))?;
+            transaction_hashes.extend(
+                statuses
+                    .iter()
+                    .map(|tx| tx.transaction_hash.as_bytes().to_vec()),
+            );
+        }
+        tx.execute(
+            "DELETE FROM transactions WHERE transaction_hash = ANY($1)",
+            &[&transaction_hashes],
+        )?;
+        tx.execute("DELETE FROM block_info WHERE block_height > $1", &[&height])?;
+        tx.execute("DELETE FROM logs WHERE block_number > $1", &[&height])?;
+        tx.execute("DELETE FROM state_diff WHERE height > $1", &[&height])?;
+
+        tx.execute(
+            "UPDATE common set latest_height = LEAST(latest_height, $1), \
+             lowest_height = LEAST(lowest_height, $1), rollbacks = rollbacks + 1",
+            &[&height],
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
     fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()> {
         self.conn.get()?.execute(
             "INSERT INTO balance(balance, address, height) VALUES($1, $2, $3)",
-            &[&format!("{:?}", balance), &format!("{:?}", address), &( height as i64 )],
+            &[&PgU256(balance), &address.as_bytes(), &( height as i64 )],
         )?;
         Ok(())
     }
     fn remove_balance(&self, height: u32, address: H160) -> Result<()> {
         self.conn.get()?.execute(
             "DELETE FROM balance WHERE height = $1 AND address = $2",
-            &[&( height as i64 ), &format!("{:?}", address)],
+            &[&( height as i64 ), &address.as_bytes()],
         )?;
         Ok(())
     }
     fn set_nonce(&self, height: u32, address: H160, nonce: U256) -> Result<()> {
         self.conn.get()?.execute(
             "INSERT INTO nonce(nonce, address, height) VALUES($1, $2, $3)",
-            &[&format!("{:?}", nonce), &format!("{:?}", address), &( height as i64 )],
+            &[&PgU256(nonce), &address.as_bytes(), &( height as i64 )],
         )?;
         Ok(())
     }
     fn remove_nonce(&self, height: u32, address: H160) -> Result<()> {
         self.conn.get()?.execute(
             "DELETE FROM nonce WHERE height = $1 AND address = $2",
-            &[&( height as i64 ), &format!("{:?}", address)],
+            &[&( height as i64 ), &address.as_bytes()],
         )?;
         Ok(())
     }
     fn set_byte_code(&self, height: u32, address: H160, code: Vec<u8>) -> Result<()> {
-        self.conn.get()?.execute(
-            "INSERT INTO byte_code(code, address, height) VALUES($1, $2, $3)",
-            &[&hex::encode(code), &format!("{:?}", address), &( height as i64 )],
-        )?;
-        Ok(())
+        pg_copy_byte_codes(&mut *self.conn.get()?, height, &[(address, code)])
     }
     fn remove_byte_code(&self, height: u32, address: H160) -> Result<()> {
         self.conn.get()?.execute(
             "DELETE FROM byte_code WHERE height = $1 AND address = $2",
-            &[&( height as i64 ), &format!("{:?}", address)],
+            &[&( height as i64 ), &address.as_bytes()],
         )?;
         Ok(())
     }
@@ -159,9 +557,9 @@ impl Setter for PgSetter {
         self.conn.get()?.execute(
             "INSERT INTO state(value, idx, address, height) VALUES($1, $2, $3, $4)",
             &[
-                &format!("{:?}", value),
-                &format!("{:?}", index),
-                &format!("{:?}", address),
+                &value.as_bytes(),
+                &index.as_bytes(),
+                &address.as_bytes(),
                 &( height as i64 ),
             ],
         )?;
@@ -170,7 +568,7 @@ impl Setter for PgSetter {
     fn remove_state(&self, height: u32, address: H160, index: H256) -> Result<()> {
         self.conn.get()?.execute(
             "DELETE FROM state WHERE height = $1 AND address = $2 AND idx = $3",
-            &[&( height as i64 ), &format!("{:?}", address), &format!("{:?}", index)],
+            &[&( height as i64 ), &address.as_bytes(), &index.as_bytes()],
         )?;
         Ok(())
     }
@@ -180,48 +578,32 @@ impl Setter for PgSetter {
         receipts: Vec<Receipt>,
         statuses: Vec<TransactionStatus>,
     ) -> Result<()> {
-        self.conn.get()?.execute(
-            "INSERT INTO block_info(block_hash, block_height, block, receipt, statuses) VALUES($1, $2, $3, $4, $5)",
-            &[
-                &format!("{:?}", block.header.hash()),
-                &format!("{:?}", block.header.number),
-                &serde_json::to_string(&block)?,
-                &serde_json::to_string(&receipts)?,
-                &serde_json::to_string(&statuses)?,
-            ],
-        )?;
-
-        for (i, tx) in statuses.iter().enumerate() {
-            self.conn.get()?.execute(
-                "INSERT INTO transactions(transaction_hash, transaction_index) VALUES($1, $2)",
-                &[
-                    &format!("{:?}", tx.transaction_hash),
-                    &serde_json::to_string(&(block.header.hash(), i as u32))?,
-                ],
-            )?;
-        }
-        Ok(())
+        pg_insert_block_info(&mut *self.conn.get()?, &block, &receipts, &statuses)
     }
     fn remove_block_info(&self, block_height: U256) -> Result<()> {
-        let row: String = self
+        let row: Vec<u8> = self
             .conn
             .get()?
             .query_one(
                 "SELECT statuses FROM block_info WHERE block_height = $1",
-                &[&format!("{:?}", block_height)],
+                &[&(block_height.as_u64() as i64)],
             )?
             .get("statuses");
-        let statuses: Vec<TransactionStatus> = serde_json::from_str(&row)?;
+        let statuses = decode_statuses(&row)?;
 
         self.conn.get()?.execute(
             "DELETE FROM block_info WHERE block_height = $1",
-            &[&format!("{:?}", block_height)],
+            &[&(block_height.as_u64() as i64)],
+        )?;
+        self.conn.get()?.execute(
+            "DELETE FROM logs WHERE block_number = $1",
+            &[&(block_height.as_u64() as i64)],
         )?;
 
         for tx in statuses {
             self.conn.get()?.execute(
                 "DELETE FROM transactions WHERE transaction_hash = $1",
-                &[&format!("{:?}", tx.transaction_hash)],
+                &[&tx.transaction_hash.as_bytes()],
             )?;
         }
 
@@ -234,22 +616,22 @@ impl Setter for PgSetter {
             .get()?
             .query_one("SELECT latest_height FROM common", &[])?
             .get("latest_height");
-        let balance = U256::from_str(
-            self.conn
-                .get()?
-                .query_one(
-                    "SELECT balance FROM balance WHERE address = $1 AND height = $2",
-                    &[&format!("{:?}", sign_address), &latest_height],
-                )?
-                .get("balance"),
-        )?;
+        let balance = self
+            .conn
+            .get()?
+            .query_one(
+                "SELECT balance FROM balance WHERE address = $1 AND height = $2",
+                &[&sign_address.as_bytes(), &latest_height],
+            )?
+            .get::<_, PgU256>("balance")
+            .0;
 
         self.conn.get()?.execute(
             "INSERT INTO pending_transactions(sign_address, pending_balance, pending_nonce) VALUES($1, $2, $3)",
             &[
-                &format!("{:?}", sign_address),
-                &format!("{:?}", balance.saturating_sub(transaction.value.saturating_add(transaction.gas_price.saturating_mul(transaction.gas_limit)))),
-                &format!("{:?}", transaction.nonce),
+                &sign_address.as_bytes(),
+                &PgU256(balance.saturating_sub(transaction.value.saturating_add(transaction.gas_price.saturating_mul(transaction.gas_limit)))),
+                &PgU256(transaction.nonce),
             ],
             
         )?;
@@ -257,31 +639,31 @@ impl Setter for PgSetter {
     }
     fn set_pending_code(&self, address: H160, code: Vec<u8>) -> Result<()> {
         self.conn.get()?.execute("INSERT INTO pending_byte_code(code, address) VALUES($1, $2)", 
-            &[&serde_json::to_string(&code)?, &format!("{:?}", address)],
+            &[&code, &address.as_bytes()],
         )?;
         Ok(())
     }
     fn set_pending_state(&self, address: H160, index: H256, value: H256) -> Result<()> {
         self.conn.get()?.execute("INSERT INTO pending_state(value, idx, address) VALUES($1, $2, $3)", 
-            &[&format!("{:?}", value), &format!("{:?}", index), &format!("{:?}", address)],
+            &[&value.as_bytes(), &index.as_bytes(), &address.as_bytes()],
         )?;
         Ok(())
     }
     fn remove_pending_tx(&self, transaction: LegacyTransaction) -> Result<()> {
         let sign_address = recover_signer(&transaction)?;
-        self.conn.get()?.execute("DELETE FROM pending_transactions WHERE sign_address = $1", &[&format!("{:?}", sign_address)])?;
+        self.conn.get()?.execute("DELETE FROM pending_transactions WHERE sign_address = $1", &[&sign_address.as_bytes()])?;
         Ok(())
     }
     fn remove_pending_code(&self, address: H160) -> Result<()> {
-        self.conn.get()?.execute("DELETE FROM pending_byte_code WHERE address = $1", &[&format!("{:?}", address)])?;
+        self.conn.get()?.execute("DELETE FROM pending_byte_code WHERE address = $1", &[&address.as_bytes()])?;
         Ok(())
     }
     fn remove_pending_state(&self, address: H160, index: H256) -> Result<()> {
-        self.conn.get()?.execute("DELETE FROM pending_state WHERE address = $1 AND idx = $2", &[&format!("{:?}", address), &format!("{:?}", index)])?;
+        self.conn.get()?.execute("DELETE FROM pending_state WHERE address = $1 AND idx = $2", &[&address.as_bytes(), &index.as_bytes()])?;
         Ok(())
     }
     fn set_total_issuance(&self, height: u32, value: U256) -> Result<()> {
-        self.conn.get()?.execute("INSERT INTO issuance(value, height) VALUES($1, $2)", &[&format!("{:?}", value), &( height as i64 )])?;
+        self.conn.get()?.execute("INSERT INTO issuance(value, height) VALUES($1, $2)", &[&PgU256(value), &( height as i64 )])?;
         Ok(())
     }
     fn set_allowances(
@@ -292,15 +674,81 @@ impl Setter for PgSetter {
         value: U256,
     ) -> Result<()> {
         self.conn.get()?.execute("INSERT INTO allowances(owner, spender, value, height) VALUES($1, $2, $3, $4)", 
-            &[&format!("{:?}", owner), &format!("{:?}", spender), &format!("{:?}", value), &( height as i64 )],
+            &[&owner.as_bytes(), &spender.as_bytes(), &PgU256(value), &( height as i64 )],
         )?;
         Ok(())
     }
+
+    fn set_balances(&self, height: u32, balances: Vec<(H160, U256)>) -> Result<()> {
+        pg_copy_balances(&mut *self.conn.get()?, height, &balances)
+    }
+    fn set_nonces(&self, height: u32, nonces: Vec<(H160, U256)>) -> Result<()> {
+        pg_copy_nonces(&mut *self.conn.get()?, height, &nonces)
+    }
+    fn set_byte_codes(&self, height: u32, codes: Vec<(H160, Vec<u8>)>) -> Result<()> {
+        pg_copy_byte_codes(&mut *self.conn.get()?, height, &codes)
+    }
+    fn set_states(&self, height: u32, states: Vec<(H160, H256, H256)>) -> Result<()> {
+        pg_copy_states(&mut *self.conn.get()?, height, &states)
+    }
+
+    fn commit_block(&self, changes: BlockChanges) -> Result<()> {
+        let height = changes.height as i64;
+        let mut conn = self.conn.get()?;
+        let mut tx = conn.transaction()?;
+
+        if let Some((block, receipts, statuses)) = &changes.block_info {
+            pg_insert_block_info(&mut tx, block, receipts, statuses)?;
+        }
+        pg_copy_balances(&mut tx, changes.height, &changes.balances)?;
+        pg_copy_nonces(&mut tx, changes.height, &changes.nonces)?;
+        pg_copy_byte_codes(&mut tx, changes.height, &changes.byte_codes)?;
+        pg_copy_states(&mut tx, changes.height, &changes.states)?;
+        for (owner, spender, value) in changes.allowances.iter() {
+            tx.execute(
+                "INSERT INTO allowances(owner, spender, value, height) VALUES($1, $2, $3, $4)",
+                &[
+                    &owner.as_bytes(),
+                    &spender.as_bytes(),
+                    &PgU256(*value),
+                    &height,
+                ],
+            )?;
+        }
+        if let Some(value) = changes.total_issuance {
+            tx.execute(
+                "INSERT INTO issuance(value, height) VALUES($1, $2)",
+                &[&PgU256(value), &height],
+            )?;
+        }
+        if let Some(diff) = &changes.state_diff {
+            tx.execute(
+                "INSERT INTO state_diff(height, diff) VALUES($1, $2) \
+                 ON CONFLICT (height) DO UPDATE SET diff = EXCLUDED.diff",
+                &[&height, &serde_json::to_vec(diff)?],
+            )?;
+        }
+        tx.execute("UPDATE common set latest_height = $1", &[&height])?;
+
+        tx.commit()?;
+        Ok(())
+    }
 }
 
+/// Version of the data Redis and RocksDB stores derive from exported blocks,
+/// recorded under `keys::data_version_key`. Setters bring stores written by
+/// older versions up to date when opening them, like the Postgres migrations:
+///
+/// 1. the logs index
+/// 2. the storage slot index of Redis, `keys::storage_index_key`
+/// 3. the accounts index of Redis, `keys::accounts_index_key`
+#[cfg(any(feature = "redis", feature = "rocksdb"))]
+const DATA_VERSION: u32 = 3;
+
 #[cfg(feature = "redis")]
 pub struct RedisSetter {
-    conn: RedisClient,
+    conn: Pool<RedisClient>,
+    accounts: RedisAccounts,
     pub prefix: String,
 }
 
@@ -308,88 +756,629 @@ pub struct RedisSetter {
 impl RedisSetter  {
     pub fn new(connection: ConnectionType, prefix: String) -> Self {
         if let ConnectionType::Redis(url) = connection {
+            let (url, options) = RedisPoolOptions::from_url(&url).expect("parse redis url failed");
+            let client = RedisClient::open(url).expect("Connect to Redis failed");
+            let conn = options
+                .build(client)
+                .expect("new redis connection pool failed");
+            ensure_loaded(&mut *conn.get().expect("get redis connection failed"))
+                .expect("load versioned-kv library failed");
+            let schema = redis_key_schema(
+                &mut *conn.get().expect("get redis connection failed"),
+                &prefix,
+            )
+            .expect("read redis key schema failed");
+            redis_upgrade_data(
+                &mut *conn.get().expect("get redis connection failed"),
+                &prefix,
+                |conn, version| Self::backfill_indexes(conn, &prefix, schema, version),
+            )
+            .expect("upgrade redis data failed");
             Self {
-                conn: RedisClient::open(url).expect("Connect to Redis failed"),
+                conn,
+                accounts: RedisAccounts::new(&prefix, schema),
                 prefix,
             }
         } else {
             panic!("Invalid connection type for Redis")
         }
     }
+
+    /// Indexes the versioned keys of the store, see `redis_upgrade_data`.
+    fn backfill_indexes(
+        conn: &mut redis::Connection,
+        prefix: &str,
+        schema: KeySchema,
+        version: u32,
+    ) -> Result<()> {
+        let mut cursor = 0u64;
+        loop {
+            let (next, keys) = redis_scan_versioned(conn, prefix, cursor)?;
+            redis_index_keys(conn, prefix, schema, version, keys)?;
+            if 0 == next {
+                return Ok(());
+            }
+            cursor = next;
+        }
+    }
+
+    fn query_pipelined(&self, cmds: Vec<Cmd>) -> Result<()> {
+        let mut pipe = redis::pipe();
+        for cmd in cmds {
+            pipe.add_command(cmd).ignore();
+        }
+        with_script_reload(&mut *self.conn.get()?, |conn| pipe.query::<()>(conn))?;
+        Ok(())
+    }
+
+    /// Rewrites the per-account keys in the `to` layout and records it.
+    /// Writers and readers have to be stopped meanwhile and reopened after,
+    /// this setter included. Returns how many keys were moved.
+    pub fn migrate_key_schema(&self, to: KeySchema) -> Result<u64> {
+        let mut conn = self.conn.get()?;
+        let mut moved = 0;
+        if self.accounts.schema != to {
+            let mut cursor = 0u64;
+            loop {
+                let (next, keys) = redis_scan_versioned(&mut *conn, &self.prefix, cursor)?;
+                moved += redis_move_account_keys(
+                    &mut *conn,
+                    &self.prefix,
+                    keys,
+                    self.accounts.schema,
+                    to,
+                )?;
+                if 0 == next {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+        conn.set::<_, _, ()>(keys::key_schema_key(&self.prefix), to.version())?;
+        Ok(moved)
+    }
+}
+
+/// Commands writing `changes`, shared by the single node and cluster setters.
+/// The latest height is left to the caller so it can be written last.
+#[cfg(feature = "redis")]
+fn redis_block_cmds(
+    prefix: &str,
+    accounts: &RedisAccounts,
+    changes: &BlockChanges,
+) -> Result<Vec<Cmd>> {
+    let height = changes.height;
+    let mut cmds = Vec::new();
+
+    if let Some((block, receipts, statuses)) = &changes.block_info {
+        let block_hash = block.header.hash();
+        let number = block.header.number;
+
+        cmds.push(Cmd::set(
+            keys::block_hash_key(prefix, number),
+            serde_json::to_string(&block_hash)?,
+        ));
+        cmds.push(Cmd::set(
+            keys::block_height_key(prefix, block_hash),
+            serde_json::to_string(&number)?,
+        ));
+        cmds.push(Cmd::set(
+            keys::block_key(prefix, block_hash),
+            encode_block(block)?,
+        ));
+        cmds.push(Cmd::set(
+            keys::receipt_key(prefix, block_hash),
+            encode_receipts(receipts)?,
+        ));
+        cmds.push(Cmd::set(
+            keys::status_key(prefix, block_hash),
+            encode_statuses(statuses)?,
+        ));
+        for (i, tx) in statuses.iter().enumerate() {
+            cmds.push(Cmd::set(
+                keys::transaction_index_key(prefix, tx.transaction_hash),
+                serde_json::to_string(&(block_hash, i as u32))?,
+            ));
+        }
+
+        let logs = block_logs(block, statuses);
+        cmds.extend(redis_logs_cmds(prefix, number.as_u32(), &logs)?);
+    }
+    cmds.extend(redis_balance_cmds(prefix, accounts, height, &changes.balances)?);
+    cmds.extend(redis_nonce_cmds(prefix, accounts, height, &changes.nonces)?);
+    cmds.extend(redis_byte_code_cmds(prefix, accounts, height, &changes.byte_codes)?);
+    cmds.extend(redis_state_cmds(prefix, accounts, height, &changes.states)?);
+    for (owner, spender, value) in changes.allowances.iter() {
+        cmds.push(vkv_set_cmd(
+            keys::allowances_key(prefix, *owner, *spender),
+            height,
+            serde_json::to_string(value)?,
+        ));
+    }
+    if let Some(value) = changes.total_issuance {
+        cmds.push(vkv_set_cmd(
+            keys::total_issuance_key(prefix),
+            height,
+            serde_json::to_string(&value)?,
+        ));
+    }
+    if let Some(diff) = &changes.state_diff {
+        cmds.push(Cmd::set(
+            keys::state_diff_key(prefix, height),
+            serde_json::to_string(diff)?,
+        ));
+    }
+    Ok(cmds)
+}
+
+/// Adds `address` to `keys::accounts_index_key`.
+#[cfg(feature = "redis")]
+fn redis_index_account_cmd(prefix: &str, address: H160) -> Cmd {
+    Cmd::zadd(keys::accounts_index_key(prefix), hex::encode(address), 0)
+}
+
+#[cfg(feature = "redis")]
+fn redis_balance_cmds(
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    balances: &[(H160, U256)],
+) -> Result<Vec<Cmd>> {
+    let mut cmds = Vec::with_capacity(balances.len() * 2);
+    for (address, balance) in balances.iter() {
+        let key = accounts.key(AccountKey::Balance(*address));
+        cmds.push(accounts.balance.set_at_cmd(&key, height, balance)?);
+        cmds.push(redis_index_account_cmd(prefix, *address));
+    }
+    Ok(cmds)
+}
+
+#[cfg(feature = "redis")]
+fn redis_nonce_cmds(
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    nonces: &[(H160, U256)],
+) -> Result<Vec<Cmd>> {
+    let mut cmds = Vec::with_capacity(nonces.len() * 2);
+    for (address, nonce) in nonces.iter() {
+        let key = accounts.key(AccountKey::Nonce(*address));
+        cmds.push(accounts.nonce.set_at_cmd(&key, height, nonce)?);
+        cmds.push(redis_index_account_cmd(prefix, *address));
+    }
+    Ok(cmds)
+}
+
+#[cfg(feature = "redis")]
+fn redis_byte_code_cmds(
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    codes: &[(H160, Vec<u8>)],
+) -> Result<Vec<Cmd>> {
+    let mut cmds = Vec::with_capacity(codes.len() * 3);
+    for (address, code) in codes.iter() {
+        let hash = code_hash(code);
+        cmds.push(Cmd::set_nx(
+            keys::code_by_hash_key(prefix, hash),
+            hex::encode(code),
+        ));
+        let key = accounts.key(AccountKey::CodeHash(*address));
+        cmds.push(accounts.code_hash.set_at_cmd(&key, height, &hash)?);
+        cmds.push(redis_index_account_cmd(prefix, *address));
+    }
+    Ok(cmds)
+}
+
+#[cfg(feature = "redis")]
+fn redis_state_cmds(
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    states: &[(H160, H256, H256)],
+) -> Result<Vec<Cmd>> {
+    let mut cmds = Vec::with_capacity(states.len() * 3);
+    for (address, index, value) in states.iter() {
+        let key = accounts.key(AccountKey::State(*address, *index));
+        cmds.push(accounts.state.set_at_cmd(&key, height, value)?);
+        let state_addr_key = accounts.schema.state_addr_key(prefix, *address);
+        cmds.push(vkv_set_cmd(state_addr_key.clone(), height, state_addr_key));
+        cmds.push(Cmd::zadd(
+            keys::storage_index_key(prefix, *address),
+            hex::encode(index),
+            0,
+        ));
+    }
+    Ok(cmds)
+}
+
+/// Removes the version of a slot of `address` written at `height`. The
+/// account itself keeps its state there while any other slot has a value.
+#[cfg(feature = "redis")]
+fn redis_remove_state<C: RedisPipelined>(
+    conn: &mut C,
+    prefix: &str,
+    accounts: &RedisAccounts,
+    height: u32,
+    address: H160,
+    index: H256,
+) -> Result<()> {
+    let key = accounts.key(AccountKey::State(address, index));
+    accounts.state.unset_at(conn, &key, height)?;
+    if !redis_has_storage(conn, prefix, accounts, height, address)? {
+        conn.vkv_unset(accounts.schema.state_addr_key(prefix, address), height)?;
+    }
+    Ok(())
+}
+
+/// Starts a rollback to `height`, returning the height rolled back to and
+/// the one rolled back from. Heights above the latest one are clamped to it.
+///
+/// The latest height is moved, the lowest one lowered and the rollback count
+/// bumped right away, while
+/// the height rolled back from is kept under `rollback_from_key` until the
+/// rollback completes. A rerun after an interruption thus still removes every
+/// block the first attempt started from.
+#[cfg(feature = "redis")]
+fn redis_begin_rollback<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    height: u32,
+) -> Result<(u32, u32)> {
+    let latest = redis_get_height(conn, &keys::latest_height_key(prefix))?;
+    let lowest = redis_get_height(conn, &keys::lowest_height_key(prefix))?;
+    let from = redis_get_height(conn, &keys::rollback_from_key(prefix))?.max(latest);
+    let height = height.min(latest);
+
+    conn.set::<_, _, ()>(keys::rollback_from_key(prefix), format!("{}", from))?;
+    conn.set::<_, _, ()>(keys::latest_height_key(prefix), format!("{}", height))?;
+    conn.incr::<_, _, ()>(keys::rollback_count_key(prefix), 1)?;
+    if height < lowest {
+        conn.set::<_, _, ()>(keys::lowest_height_key(prefix), format!("{}", height))?;
+    }
+    Ok((height, from))
+}
+
+/// The height stored at `key`, 0 when it is unset.
+#[cfg(feature = "redis")]
+fn redis_get_height<C: ConnectionLike>(conn: &mut C, key: &str) -> Result<u32> {
+    let height: Option<String> = conn.get(key)?;
+    match height {
+        Some(str) => Ok(str.parse::<u32>()?),
+        _ => Ok(0),
+    }
+}
+
+/// Commands storing the `logs` of `height` and indexing them.
+#[cfg(feature = "redis")]
+fn redis_logs_cmds(prefix: &str, height: u32, logs: &[IndexedLog]) -> Result<Vec<Cmd>> {
+    let mut cmds = Vec::new();
+    if logs.is_empty() {
+        return Ok(cmds);
+    }
+    cmds.push(Cmd::set(
+        keys::logs_key(prefix, height),
+        serde_json::to_string(logs)?,
+    ));
+    cmds.push(Cmd::zadd(keys::logs_heights_key(prefix), height, height));
+    for key in redis_logs_index_keys(prefix, logs) {
+        cmds.push(Cmd::zadd(key, height, height));
+    }
+    Ok(cmds)
+}
+
+/// Brings the derived data of a store written by an older version up to
+/// date, see `DATA_VERSION`. Run when a setter opens the store; a fresh one
+/// has nothing to backfill.
+///
+/// The indexes are backfilled from the versioned keys of every node by
+/// `backfill_indexes`, given the data version of the store, see
+/// `redis_index_keys`.
+#[cfg(feature = "redis")]
+fn redis_upgrade_data<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    backfill_indexes: impl FnOnce(&mut C, u32) -> Result<()>,
+) -> Result<()> {
+    let version: Option<u32> = conn.get(keys::data_version_key(prefix))?;
+    let version = version.unwrap_or_default();
+    if version >= DATA_VERSION {
+        return Ok(());
+    }
+    if version < 1 {
+        redis_backfill_logs(conn, prefix)?;
+    }
+    if version < 3 {
+        backfill_indexes(conn, version)?;
+    }
+    conn.set::<_, _, ()>(keys::data_version_key(prefix), DATA_VERSION)?;
+    Ok(())
+}
+
+/// Adds `keys`, versioned keys listed on one node, to the indexes that a
+/// store at data `version` does not have them in yet.
+#[cfg(feature = "redis")]
+fn redis_index_keys<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    schema: KeySchema,
+    version: u32,
+    keys: Vec<String>,
+) -> Result<()> {
+    for key in keys {
+        match schema.parse_account_key(prefix, &key) {
+            Some(AccountKey::State(address, index)) if version < 2 => {
+                conn.zadd::<_, _, _, ()>(
+                    keys::storage_index_key(prefix, address),
+                    hex::encode(index),
+                    0,
+                )?;
+            }
+            Some(
+                AccountKey::Balance(address)
+                | AccountKey::Nonce(address)
+                | AccountKey::Code(address)
+                | AccountKey::CodeHash(address),
+            ) if version < 3 => {
+                redis_index_account_cmd(prefix, address).query::<()>(conn)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Indexes the logs of every block exported before the logs index existed.
+#[cfg(feature = "redis")]
+fn redis_backfill_logs<C: ConnectionLike>(conn: &mut C, prefix: &str) -> Result<()> {
+    let latest = redis_get_height(conn, &keys::latest_height_key(prefix))?;
+    for height in 0..=latest {
+        let block_hash_key = keys::block_hash_key(prefix, U256::from(height));
+        let block_hash: Option<String> = conn.get(block_hash_key)?;
+        let block_hash: H256 = match block_hash {
+            Some(v) => serde_json::from_str(&v)?,
+            None => continue,
+        };
+        let block: Option<Vec<u8>> = conn.get(keys::block_key(prefix, block_hash))?;
+        let statuses: Option<Vec<u8>> = conn.get(keys::status_key(prefix, block_hash))?;
+        if let (Some(block), Some(statuses)) = (block, statuses) {
+            let logs = block_logs(&decode_block(&block)?, &decode_statuses(&statuses)?);
+            for cmd in redis_logs_cmds(prefix, height, &logs)? {
+                cmd.query::<()>(conn)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Address and topic index keys referencing a block with `logs`.
+#[cfg(feature = "redis")]
+fn redis_logs_index_keys(prefix: &str, logs: &[IndexedLog]) -> BTreeSet<String> {
+    let mut index_keys = BTreeSet::new();
+    for log in logs.iter() {
+        index_keys.insert(keys::logs_address_key(prefix, log.address));
+        for (position, topic) in log.topics.iter().enumerate() {
+            index_keys.insert(keys::logs_topic_key(prefix, position, *topic));
+        }
+    }
+    index_keys
+}
+
+/// Drops the logs of `height` and its entries in the logs index.
+#[cfg(feature = "redis")]
+fn redis_remove_logs<C: ConnectionLike>(conn: &mut C, prefix: &str, height: u32) -> Result<()> {
+    let logs_key = keys::logs_key(prefix, height);
+    let logs: Option<String> = conn.get(&logs_key)?;
+    if let Some(logs) = logs {
+        let logs: Vec<IndexedLog> = serde_json::from_str(&logs)?;
+        for key in redis_logs_index_keys(prefix, &logs) {
+            conn.zrem::<_, _, ()>(key, height)?;
+        }
+        conn.zrem::<_, _, ()>(keys::logs_heights_key(prefix), height)?;
+        conn.del::<_, ()>(logs_key)?;
+    }
+    Ok(())
+}
+
+/// One SCAN page of the versioned keys of `prefix` held by this node, with
+/// the cursor of the next one.
+#[cfg(feature = "redis")]
+fn redis_scan_versioned<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    cursor: u64,
+) -> Result<(u64, Vec<String>)> {
+    let logs_prefix = keys::logs_index_prefix(prefix);
+    let index_prefix = keys::index_prefix(prefix);
+    let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+        .arg(cursor)
+        .arg("MATCH")
+        .arg(format!("{}:*", prefix))
+        .arg("COUNT")
+        .arg(1000)
+        .arg("TYPE")
+        .arg("zset")
+        .query(conn)?;
+    let keys = keys
+        .into_iter()
+        .filter(|key| !key.starts_with(&logs_prefix) && !key.starts_with(&index_prefix))
+        .collect();
+    Ok((next, keys))
+}
+
+/// Runs the command built by `make_cmd` on every versioned key of `prefix`
+/// held by this node.
+#[cfg(feature = "redis")]
+fn redis_for_each_versioned(
+    conn: &mut redis::Connection,
+    prefix: &str,
+    make_cmd: impl Fn(String) -> Cmd,
+) -> Result<()> {
+    let mut cursor = 0u64;
+    loop {
+        let (next, keys) = redis_scan_versioned(conn, prefix, cursor)?;
+
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.add_command(make_cmd(key)).ignore();
+        }
+        with_script_reload(conn, |conn| pipe.query::<()>(conn))?;
+
+        if 0 == next {
+            return Ok(());
+        }
+        cursor = next;
+    }
+}
+
+/// Moves the per-account keys among `keys` from the `from` layout to `to`.
+/// Every version is copied before the old one is deleted, so an interrupted
+/// move can simply be rerun. Returns how many keys were moved.
+#[cfg(feature = "redis")]
+fn redis_move_account_keys<C: ConnectionLike>(
+    conn: &mut C,
+    prefix: &str,
+    keys: Vec<String>,
+    from: KeySchema,
+    to: KeySchema,
+) -> Result<u64> {
+    let mut moved = 0;
+    for key in keys {
+        let account_key = match from.parse_account_key(prefix, &key) {
+            Some(account_key) => account_key,
+            None => continue,
+        };
+        let new_key = to.account_key(prefix, account_key);
+
+        let versions: Vec<(String, u32)> = conn.zrange_withscores(&key, 0, -1)?;
+        for (value_key, height) in versions {
+            let value: Option<Vec<u8>> = conn.get(&value_key)?;
+            let new_value_key = vkv_value_key(&new_key, height);
+            // A version without a value is a tombstone, moved as is.
+            if let Some(value) = value {
+                conn.set::<_, _, ()>(&new_value_key, value)?;
+            }
+            conn.zadd::<_, _, _, ()>(&new_key, new_value_key, height)?;
+            conn.del::<_, ()>(value_key)?;
+        }
+        conn.del::<_, ()>(key)?;
+        moved += 1;
+    }
+    Ok(moved)
 }
 
 #[cfg(feature = "redis")]
 impl Setter for RedisSetter {
     fn clear(&self) -> Result<()> {
-        redis::cmd("FLUSHDB").arg("SYNC").query(&mut self.conn.get_connection()?)?;
+        let mut conn = self.conn.get()?;
+        redis::cmd("FLUSHDB").arg("SYNC").query(&mut *conn)?;
+        conn.set::<_, _, ()>(keys::key_schema_key(&self.prefix), self.accounts.schema.version())?;
+        conn.set::<_, _, ()>(keys::data_version_key(&self.prefix), DATA_VERSION)?;
         Ok(())
     }
 
     fn set_height(&self, height: u32) -> Result<()> {
         let height_key = keys::latest_height_key(&self.prefix);
-        self.conn.get_connection()?.set(height_key, format!("{}", height))?;
+        self.conn.get()?.set(height_key, format!("{}", height))?;
         Ok(())
     }
     fn set_lowest_height(&self, height: u32) -> Result<()> {
         let height_key = keys::lowest_height_key(&self.prefix);
-        self.conn.get_connection()?.set(height_key, format!("{}", height))?;
+        self.conn.get()?.set(height_key, format!("{}", height))?;
         Ok(())
     }
 
-    fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()> {
-        let balance_key = keys::balance_key(&self.prefix, address);
-        self.conn.get_connection()?
-            .vkv_set(balance_key, height, serde_json::to_string(&balance)?)?;
+    fn prune_below(&self, height: u32) -> Result<()> {
+        let mut conn = self.conn.get()?;
+        let height = height.min(redis_get_height(
+            &mut *conn,
+            &keys::latest_height_key(&self.prefix),
+        )?);
+        redis_for_each_versioned(&mut *conn, &self.prefix, |key| {
+            vkv_compact_cmd(key, height)
+        })?;
+        self.set_lowest_height(height)
+    }
+
+    /// The latest height is moved first, so readers never see a height whose
+    /// data is being removed. An interrupted rollback can simply be rerun,
+    /// see `redis_begin_rollback`.
+    fn rollback_to(&self, height: u32) -> Result<()> {
+        let (height, from) = redis_begin_rollback(&mut *self.conn.get()?, &self.prefix, height)?;
 
+        for h in (height + 1)..=from {
+            self.remove_block_info(U256::from(h))?;
+            self.conn
+                .get()?
+                .del::<_, ()>(keys::state_diff_key(&self.prefix, h))?;
+        }
+        redis_for_each_versioned(&mut *self.conn.get()?, &self.prefix, |key| {
+            vkv_rollback_cmd(key, height)
+        })?;
+        self.conn.get()?.del::<_, ()>(keys::rollback_from_key(&self.prefix))?;
         Ok(())
     }
 
+    fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()> {
+        self.query_pipelined(redis_balance_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, balance)],
+        )?)
+    }
+
     fn remove_balance(&self, height: u32, address: H160) -> Result<()> {
-        let balance_key = keys::balance_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(balance_key, height)?;
+        let key = self.accounts.key(AccountKey::Balance(address));
+        self.accounts.balance.unset_at(&mut *self.conn.get()?, &key, height)?;
         Ok(())
     }
 
     fn set_nonce(&self, height: u32, address: H160, nonce: U256) -> Result<()> {
-        let nonce_key = keys::nonce_key(&self.prefix, address);
-        self.conn.get_connection()?
-            .vkv_set(nonce_key, height, serde_json::to_string(&nonce)?)?;
-
-        Ok(())
+        self.query_pipelined(redis_nonce_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, nonce)],
+        )?)
     }
     fn remove_nonce(&self, height: u32, address: H160) -> Result<()> {
-        let nonce_key = keys::nonce_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(nonce_key, height)?;
+        let key = self.accounts.key(AccountKey::Nonce(address));
+        self.accounts.nonce.unset_at(&mut *self.conn.get()?, &key, height)?;
         Ok(())
     }
     fn set_byte_code(&self, height: u32, address: H160, code: Vec<u8>) -> Result<()> {
-        let code_key = keys::code_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_set(code_key, height, hex::encode(code))?;
-
-        Ok(())
+        self.query_pipelined(redis_byte_code_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, code)],
+        )?)
     }
     fn remove_byte_code(&self, height: u32, address: H160) -> Result<()> {
-        let code_key = keys::code_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(code_key, height)?;
+        let key = self.accounts.key(AccountKey::CodeHash(address));
+        self.accounts.code_hash.unset_at(&mut *self.conn.get()?, &key, height)?;
         Ok(())
     }
 
     fn set_state(&self, height: u32, address: H160, index: H256, value: H256) -> Result<()> {
-        let key = keys::state_key(&self.prefix, address, index);
-        self.conn.get_connection()?
-            .vkv_set(key, height, serde_json::to_string(&value)?)?;
-        let state_addr_key = keys::state_addr_key(&self.prefix, address);
-        self.conn.get_connection()?
-            .vkv_set(state_addr_key.clone(), height, state_addr_key)?;
-        Ok(())
+        self.query_pipelined(redis_state_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, index, value)],
+        )?)
     }
 
     fn remove_state(&self, height: u32, address: H160, index: H256) -> Result<()> {
-        let key = keys::state_key(&self.prefix, address, index);
-        self.conn.get_connection()?.vkv_del(key, height)?;
-        let state_addr_key = keys::state_addr_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(state_addr_key, height)?;
-        Ok(())
+        redis_remove_state(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            address,
+            index,
+        )
     }
 
     fn set_block_info(
@@ -398,42 +1387,22 @@ impl Setter for RedisSetter {
         receipts: Vec<Receipt>,
         statuses: Vec<TransactionStatus>,
     ) -> Result<()> {
-        let block_hash = block.header.hash();
-        let height = block.header.number;
-
-        let block_hash_key = keys::block_hash_key(&self.prefix, height);
-        self.conn.get_connection()?
-            .set(block_hash_key, serde_json::to_string(&block_hash)?)?;
-
-        let block_height_key = keys::block_height_key(&self.prefix, block_hash);
-        self.conn.get_connection()?
-            .set(block_height_key, serde_json::to_string(&height)?)?;
-
-        let block_key = keys::block_key(&self.prefix, block_hash);
-        self.conn.get_connection()?.set(block_key, serde_json::to_string(&block)?)?;
-
-        let receipt_key = keys::receipt_key(&self.prefix, block_hash);
-        self.conn.get_connection()?
-            .set(receipt_key, serde_json::to_string(&receipts)?)?;
-
-        let status_key = keys::status_key(&self.prefix, block_hash);
-        self.conn.get_connection()?
-            .set(status_key, serde_json::to_string(&statuses)?)?;
-
-        for (i, tx) in statuses.iter().enumerate() {
-            let transaction_index_key =
-                keys::transaction_index_key(&self.prefix, tx.transaction_hash);
-            self.conn.get_connection()?.set(
-                transaction_index_key,
-                serde_json::to_string(&(block_hash, i as u32))?,
-            )?;
-        }
-        Ok(())
+        self.query_pipelined(redis_block_cmds(
+            &self.prefix,
+            &self.accounts,
+            &BlockChanges {
+                height: block.header.number.as_u32(),
+                block_info: Some((block, receipts, statuses)),
+                ..Default::default()
+            },
+        )?)
     }
 
     fn remove_block_info(&self, height: U256) -> Result<()> {
+        redis_remove_logs(&mut *self.conn.get()?, &self.prefix, height.as_u32())?;
+
         let block_hash_key = keys::block_hash_key(&self.prefix, height);
-        let block_hash: H256 = match self.conn.get_connection()?
+        let block_hash: H256 = match self.conn.get()?
             .get::<String, Option<String>>(block_hash_key.clone())?
         {
             Some(v) => serde_json::from_str(&v)?,
@@ -446,10 +1415,10 @@ impl Setter for RedisSetter {
         let receipt_key = keys::receipt_key(&self.prefix, block_hash);
         let status_key = keys::status_key(&self.prefix, block_hash);
         let statuses: Vec<TransactionStatus> = match self
-            .conn.get_connection()?
-            .get::<String, Option<String>>(status_key.clone())?
+            .conn.get()?
+            .get::<String, Option<Vec<u8>>>(status_key.clone())?
         {
-            Some(v) => serde_json::from_str(&v)?,
+            Some(v) => decode_statuses(&v)?,
             None => {
                 return Err(Error::ValueNotFound);
             }
@@ -457,13 +1426,13 @@ impl Setter for RedisSetter {
         for tx in statuses.iter() {
             let transaction_index_key =
                 keys::transaction_index_key(&self.prefix, tx.transaction_hash);
-            self.conn.get_connection()?.del(transaction_index_key)?;
+            self.conn.get()?.del(transaction_index_key)?;
         }
-        self.conn.get_connection()?.del(block_height_key)?;
-        self.conn.get_connection()?.del(block_key)?;
-        self.conn.get_connection()?.del(receipt_key)?;
-        self.conn.get_connection()?.del(status_key)?;
-        self.conn.get_connection()?.del(block_hash_key)?;
+        self.conn.get()?.del(block_height_key)?;
+        self.conn.get()?.del(block_key)?;
+        self.conn.get()?.del(receipt_key)?;
+        self.conn.get()?.del(status_key)?;
+        self.conn.get()?.del(block_hash_key)?;
         Ok(())
     }
 
@@ -471,30 +1440,29 @@ impl Setter for RedisSetter {
         let sign_address = recover_signer(&transaction)?;
 
         let height_key = keys::latest_height_key(&self.prefix);
-        let height: Option<String> = self.conn.get_connection()?.get(height_key)?;
+        let height: Option<String> = self.conn.get()?.get(height_key)?;
         let height = match height {
             Some(str) => str.parse::<u32>()?,
             _ => 0,
         };
-        let balance_key = keys::balance_key(&self.prefix, sign_address);
-        let balance: Option<String> = self.conn.get_connection()?.vkv_get(balance_key, height)?;
-        let balance = if let Some(s) = balance {
-            serde_json::from_str(s.as_str())?
-        } else {
-            U256::zero()
-        };
+        let key = self.accounts.key(AccountKey::Balance(sign_address));
+        let balance = self
+            .accounts
+            .balance
+            .get_at(&mut *self.conn.get()?, &key, height)?
+            .unwrap_or_default();
 
         let pending_balance_key = keys::pending_balance_key(&self.prefix, sign_address);
         let total_payment = transaction
             .value
             .saturating_add(transaction.gas_price.saturating_mul(transaction.gas_limit));
-        self.conn.get_connection()?.set(
+        self.conn.get()?.set(
             pending_balance_key,
             serde_json::to_string(&balance.saturating_sub(total_payment))?,
         )?;
 
         let pending_nonce_key = keys::pending_nonce_key(&self.prefix, sign_address);
-        self.conn.get_connection()?.set(
+        self.conn.get()?.set(
             pending_nonce_key,
             serde_json::to_string(&transaction.nonce)?,
         )?;
@@ -504,14 +1472,14 @@ impl Setter for RedisSetter {
 
     fn set_pending_code(&self, address: H160, code: Vec<u8>) -> Result<()> {
         let pending_code_key = keys::pending_code_key(&self.prefix, address);
-        self.conn.get_connection()?
+        self.conn.get()?
             .set(pending_code_key, serde_json::to_string(&code)?)?;
         Ok(())
     }
 
     fn set_pending_state(&self, address: H160, index: H256, value: H256) -> Result<()> {
         let pending_state_key = keys::pending_state_key(&self.prefix, address, index);
-        self.conn.get_connection()?
+        self.conn.get()?
             .set(pending_state_key, serde_json::to_string(&value)?)?;
 
         Ok(())
@@ -521,30 +1489,30 @@ impl Setter for RedisSetter {
         let sign_address = recover_signer(&transaction)?;
         let pending_balance_key = keys::pending_balance_key(&self.prefix, sign_address);
 
-        self.conn.get_connection()?.del(pending_balance_key)?;
+        self.conn.get()?.del(pending_balance_key)?;
 
         let pending_nonce_key = keys::pending_nonce_key(&self.prefix, sign_address);
-        self.conn.get_connection()?.del(pending_nonce_key)?;
+        self.conn.get()?.del(pending_nonce_key)?;
 
         Ok(())
     }
 
     fn remove_pending_code(&self, address: H160) -> Result<()> {
         let pending_code_key = keys::pending_code_key(&self.prefix, address);
-        self.conn.get_connection()?.del(pending_code_key)?;
+        self.conn.get()?.del(pending_code_key)?;
         Ok(())
     }
 
     fn remove_pending_state(&self, address: H160, index: H256) -> Result<()> {
         let pending_state_key = keys::pending_state_key(&self.prefix, address, index);
-        self.conn.get_connection()?.del(pending_state_key)?;
+        self.conn.get()?.del(pending_state_key)?;
 
         Ok(())
     }
 
     fn set_total_issuance(&self, height: u32, value: U256) -> Result<()> {
         let key = keys::total_issuance_key(&self.prefix);
-        self.conn.get_connection()?
+        self.conn.get()?
             .vkv_set(key, height, serde_json::to_string(&value)?)?;
         Ok(())
     }
@@ -557,15 +1525,54 @@ impl Setter for RedisSetter {
         value: U256,
     ) -> Result<()> {
         let key = keys::allowances_key(&self.prefix, owner, spender);
-        self.conn.get_connection()?
+        self.conn.get()?
             .vkv_set(key, height, serde_json::to_string(&value)?)?;
         Ok(())
     }
-}
 
-#[cfg(feature = "redis-cluster")]
+    fn set_balances(&self, height: u32, balances: Vec<(H160, U256)>) -> Result<()> {
+        self.query_pipelined(redis_balance_cmds(&self.prefix, &self.accounts, height, &balances)?)
+    }
+
+    fn set_nonces(&self, height: u32, nonces: Vec<(H160, U256)>) -> Result<()> {
+        self.query_pipelined(redis_nonce_cmds(&self.prefix, &self.accounts, height, &nonces)?)
+    }
+
+    fn set_byte_codes(&self, height: u32, byte_codes: Vec<(H160, Vec<u8>)>) -> Result<()> {
+        self.query_pipelined(redis_byte_code_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &byte_codes,
+        )?)
+    }
+
+    fn set_states(&self, height: u32, states: Vec<(H160, H256, H256)>) -> Result<()> {
+        self.query_pipelined(redis_state_cmds(&self.prefix, &self.accounts, height, &states)?)
+    }
+
+    /// Sent as one MULTI/EXEC transaction, the latest height included.
+    fn commit_block(&self, changes: BlockChanges) -> Result<()> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for cmd in redis_block_cmds(&self.prefix, &self.accounts, &changes)? {
+            pipe.add_command(cmd).ignore();
+        }
+        pipe.set(
+            keys::latest_height_key(&self.prefix),
+            format!("{}", changes.height),
+        )
+        .ignore();
+        with_script_reload(&mut *self.conn.get()?, |conn| pipe.query::<()>(conn))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis-cluster")]
 pub struct RedisClusterSetter {
-    conn: RedisClusterClient,
+    conn: Pool<RedisClusterClient>,
+    accounts: RedisAccounts,
+    urls: Vec<String>,
     pub prefix: String,
 }
 
@@ -573,89 +1580,225 @@ pub struct RedisClusterSetter {
 impl RedisClusterSetter  {
     pub fn new(connection: ConnectionType, prefix: String) -> Self {
         if let ConnectionType::RedisCluster(urls) = connection {
+            let (urls, options) =
+                RedisPoolOptions::from_cluster_urls(&urls).expect("parse redis url failed");
+            let client =
+                RedisClusterClient::new(urls.to_vec()).expect("Connect to Redis Cluster failed");
+            let conn = options
+                .build(client)
+                .expect("new redis connection pool failed");
+            redis_cluster_ensure_loaded(
+                &mut *conn.get().expect("get redis connection failed"),
+                &urls,
+            )
+            .expect("load versioned-kv library failed");
+            let schema = redis_key_schema(
+                &mut *conn.get().expect("get redis connection failed"),
+                &prefix,
+            )
+            .expect("read redis key schema failed");
+            redis_upgrade_data(
+                &mut *conn.get().expect("get redis connection failed"),
+                &prefix,
+                |conn, version| Self::backfill_indexes(conn, &urls, &prefix, schema, version),
+            )
+            .expect("upgrade redis data failed");
             Self {
-                conn: RedisClusterClient::new(urls.to_vec())
-                    .expect("Connect to Redis Cluster failed"),
+                conn,
+                accounts: RedisAccounts::new(&prefix, schema),
+                urls,
                 prefix,
             }
         } else {
             panic!("Invalid connection type for Redis Cluster")
         }
     }
+
+    /// Direct clients for every master, for work that has to visit the whole
+    /// keyspace.
+    fn masters(&self) -> Result<Vec<RedisClient>> {
+        redis_cluster_masters(&mut *self.conn.get()?, &self.urls)
+    }
+
+    /// Indexes the versioned keys listed on every master through the cluster
+    /// connection, see `redis_upgrade_data`.
+    fn backfill_indexes(
+        conn: &mut ClusterConnection,
+        urls: &[String],
+        prefix: &str,
+        schema: KeySchema,
+        version: u32,
+    ) -> Result<()> {
+        for master in redis_cluster_masters(conn, urls)? {
+            let mut node = master.get_connection()?;
+            let mut cursor = 0u64;
+            loop {
+                let (next, keys) = redis_scan_versioned(&mut node, prefix, cursor)?;
+                redis_index_keys(conn, prefix, schema, version, keys)?;
+                if 0 == next {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+        Ok(())
+    }
+
+    fn query_pipelined(&self, cmds: Vec<Cmd>) -> Result<()> {
+        let mut pipe = redis::cluster::cluster_pipe();
+        for cmd in cmds {
+            pipe.add_command(cmd).ignore();
+        }
+        with_script_reload(&mut *self.conn.get()?, |conn| pipe.query::<()>(conn))?;
+        Ok(())
+    }
+
+    /// Rewrites the per-account keys in the `to` layout and records it, see
+    /// `RedisSetter::migrate_key_schema`. Keys are listed on every master and
+    /// moved through the cluster connection.
+    pub fn migrate_key_schema(&self, to: KeySchema) -> Result<u64> {
+        let mut conn = self.conn.get()?;
+        let mut moved = 0;
+        if self.accounts.schema != to {
+            for master in self.masters()? {
+                let mut node = master.get_connection()?;
+                let mut cursor = 0u64;
+                loop {
+                    let (next, keys) = redis_scan_versioned(&mut node, &self.prefix, cursor)?;
+                    moved += redis_move_account_keys(
+                        &mut *conn,
+                        &self.prefix,
+                        keys,
+                        self.accounts.schema,
+                        to,
+                    )?;
+                    if 0 == next {
+                        break;
+                    }
+                    cursor = next;
+                }
+            }
+        }
+        conn.set::<_, _, ()>(keys::key_schema_key(&self.prefix), to.version())?;
+        Ok(moved)
+    }
 }
 
 #[cfg(feature = "redis-cluster")]
 impl Setter for RedisClusterSetter {
     fn clear(&self) -> Result<()> {
-        redis::cmd("FLUSHDB").arg("SYNC").query(&mut self.conn.get_connection()?)?;
+        let mut conn = self.conn.get()?;
+        redis::cmd("FLUSHDB").arg("SYNC").query(&mut *conn)?;
+        conn.set::<_, _, ()>(keys::key_schema_key(&self.prefix), self.accounts.schema.version())?;
+        conn.set::<_, _, ()>(keys::data_version_key(&self.prefix), DATA_VERSION)?;
         Ok(())
     }
 
     fn set_height(&self, height: u32) -> Result<()> {
         let height_key = keys::latest_height_key(&self.prefix);
-        self.conn.get_connection()?.set(height_key, format!("{}", height))?;
+        self.conn.get()?.set(height_key, format!("{}", height))?;
         Ok(())
     }
     fn set_lowest_height(&self, height: u32) -> Result<()> {
         let height_key = keys::lowest_height_key(&self.prefix);
-        self.conn.get_connection()?.set(height_key, format!("{}", height))?;
+        self.conn.get()?.set(height_key, format!("{}", height))?;
         Ok(())
     }
 
-    fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()> {
-        let balance_key = keys::balance_key(&self.prefix, address);
-        self.conn.get_connection()?
-            .vkv_set(balance_key, height, serde_json::to_string(&balance)?)?;
+    fn prune_below(&self, height: u32) -> Result<()> {
+        let height = height.min(redis_get_height(
+            &mut *self.conn.get()?,
+            &keys::latest_height_key(&self.prefix),
+        )?);
+        for master in self.masters()? {
+            redis_for_each_versioned(&mut master.get_connection()?, &self.prefix, |key| {
+                vkv_compact_cmd(key, height)
+            })?;
+        }
+        self.set_lowest_height(height)
+    }
+
+    /// The latest height is moved first, so readers never see a height whose
+    /// data is being removed. An interrupted rollback can simply be rerun,
+    /// see `redis_begin_rollback`.
+    fn rollback_to(&self, height: u32) -> Result<()> {
+        let (height, from) = redis_begin_rollback(&mut *self.conn.get()?, &self.prefix, height)?;
 
+        for h in (height + 1)..=from {
+            self.remove_block_info(U256::from(h))?;
+            self.conn
+                .get()?
+                .del::<_, ()>(keys::state_diff_key(&self.prefix, h))?;
+        }
+        for master in self.masters()? {
+            redis_for_each_versioned(&mut master.get_connection()?, &self.prefix, |key| {
+                vkv_rollback_cmd(key, height)
+            })?;
+        }
+        self.conn.get()?.del::<_, ()>(keys::rollback_from_key(&self.prefix))?;
         Ok(())
     }
 
+    fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()> {
+        self.query_pipelined(redis_balance_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, balance)],
+        )?)
+    }
+
     fn remove_balance(&self, height: u32, address: H160) -> Result<()> {
-        let balance_key = keys::balance_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(balance_key, height)?;
+        let key = self.accounts.key(AccountKey::Balance(address));
+        self.accounts.balance.unset_at(&mut *self.conn.get()?, &key, height)?;
         Ok(())
     }
 
     fn set_nonce(&self, height: u32, address: H160, nonce: U256) -> Result<()> {
-        let nonce_key = keys::nonce_key(&self.prefix, address);
-        self.conn.get_connection()?
-            .vkv_set(nonce_key, height, serde_json::to_string(&nonce)?)?;
-
-        Ok(())
+        self.query_pipelined(redis_nonce_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, nonce)],
+        )?)
     }
     fn remove_nonce(&self, height: u32, address: H160) -> Result<()> {
-        let nonce_key = keys::nonce_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(nonce_key, height)?;
+        let key = self.accounts.key(AccountKey::Nonce(address));
+        self.accounts.nonce.unset_at(&mut *self.conn.get()?, &key, height)?;
         Ok(())
     }
     fn set_byte_code(&self, height: u32, address: H160, code: Vec<u8>) -> Result<()> {
-        let code_key = keys::code_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_set(code_key, height, hex::encode(code))?;
-
-        Ok(())
+        self.query_pipelined(redis_byte_code_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, code)],
+        )?)
     }
     fn remove_byte_code(&self, height: u32, address: H160) -> Result<()> {
-        let code_key = keys::code_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(code_key, height)?;
+        let key = self.accounts.key(AccountKey::CodeHash(address));
+        self.accounts.code_hash.unset_at(&mut *self.conn.get()?, &key, height)?;
         Ok(())
     }
 
     fn set_state(&self, height: u32, address: H160, index: H256, value: H256) -> Result<()> {
-        let key = keys::state_key(&self.prefix, address, index);
-        self.conn.get_connection()?
-            .vkv_set(key, height, serde_json::to_string(&value)?)?;
-        let state_addr_key = keys::state_addr_key(&self.prefix, address);
-        self.conn.get_connection()?
-            .vkv_set(state_addr_key.clone(), height, state_addr_key)?;
-        Ok(())
+        self.query_pipelined(redis_state_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &[(address, index, value)],
+        )?)
     }
 
     fn remove_state(&self, height: u32, address: H160, index: H256) -> Result<()> {
-        let key = keys::state_key(&self.prefix, address, index);
-        self.conn.get_connection()?.vkv_del(key, height)?;
-        let state_addr_key = keys::state_addr_key(&self.prefix, address);
-        self.conn.get_connection()?.vkv_del(state_addr_key, height)?;
-        Ok(())
+        redis_remove_state(
+            &mut *self.conn.get()?,
+            &self.prefix,
+            &self.accounts,
+            height,
+            address,
+            index,
+        )
     }
 
     fn set_block_info(
@@ -664,42 +1807,22 @@ impl Setter for RedisClusterSetter {
         receipts: Vec<Receipt>,
         statuses: Vec<TransactionStatus>,
     ) -> Result<()> {
-        let block_hash = block.header.hash();
-        let height = block.header.number;
-
-        let block_hash_key = keys::block_hash_key(&self.prefix, height);
-        self.conn.get_connection()?
-            .set(block_hash_key, serde_json::to_string(&block_hash)?)?;
-
-        let block_height_key = keys::block_height_key(&self.prefix, block_hash);
-        self.conn.get_connection()?
-            .set(block_height_key, serde_json::to_string(&height)?)?;
-
-        let block_key = keys::block_key(&self.prefix, block_hash);
-        self.conn.get_connection()?.set(block_key, serde_json::to_string(&block)?)?;
-
-        let receipt_key = keys::receipt_key(&self.prefix, block_hash);
-        self.conn.get_connection()?
-            .set(receipt_key, serde_json::to_string(&receipts)?)?;
-
-        let status_key = keys::status_key(&self.prefix, block_hash);
-        self.conn.get_connection()?
-            .set(status_key, serde_json::to_string(&statuses)?)?;
-
-        for (i, tx) in statuses.iter().enumerate() {
-            let transaction_index_key =
-                keys::transaction_index_key(&self.prefix, tx.transaction_hash);
-            self.conn.get_connection()?.set(
-                transaction_index_key,
-                serde_json::to_string(&(block_hash, i as u32))?,
-            )?;
-        }
-        Ok(())
+        self.query_pipelined(redis_block_cmds(
+            &self.prefix,
+            &self.accounts,
+            &BlockChanges {
+                height: block.header.number.as_u32(),
+                block_info: Some((block, receipts, statuses)),
+                ..Default::default()
+            },
+        )?)
     }
 
     fn remove_block_info(&self, height: U256) -> Result<()> {
+        redis_remove_logs(&mut *self.conn.get()?, &self.prefix, height.as_u32())?;
+
         let block_hash_key = keys::block_hash_key(&self.prefix, height);
-        let block_hash: H256 = match self.conn.get_connection()?
+        let block_hash: H256 = match self.conn.get()?
             .get::<String, Option<String>>(block_hash_key.clone())?
         {
             Some(v) => serde_json::from_str(&v)?,
@@ -712,10 +1835,10 @@ impl Setter for RedisClusterSetter {
         let receipt_key = keys::receipt_key(&self.prefix, block_hash);
         let status_key = keys::status_key(&self.prefix, block_hash);
         let statuses: Vec<TransactionStatus> = match self
-            .conn.get_connection()?
-            .get::<String, Option<String>>(status_key.clone())?
+            .conn.get()?
+            .get::<String, Option<Vec<u8>>>(status_key.clone())?
         {
-            Some(v) => serde_json::from_str(&v)?,
+            Some(v) => decode_statuses(&v)?,
             None => {
                 return Err(Error::ValueNotFound);
             }
@@ -723,13 +1846,13 @@ impl Setter for RedisClusterSetter {
         for tx in statuses.iter() {
             let transaction_index_key =
                 keys::transaction_index_key(&self.prefix, tx.transaction_hash);
-            self.conn.get_connection()?.del(transaction_index_key)?;
+            self.conn.get()?.del(transaction_index_key)?;
         }
-        self.conn.get_connection()?.del(block_height_key)?;
-        self.conn.get_connection()?.del(block_key)?;
-        self.conn.get_connection()?.del(receipt_key)?;
-        self.conn.get_connection()?.del(status_key)?;
-        self.conn.get_connection()?.del(block_hash_key)?;
+        self.conn.get()?.del(block_height_key)?;
+        self.conn.get()?.del(block_key)?;
+        self.conn.get()?.del(receipt_key)?;
+        self.conn.get()?.del(status_key)?;
+        self.conn.get()?.del(block_hash_key)?;
         Ok(())
     }
 
@@ -737,30 +1860,29 @@ impl Setter for RedisClusterSetter {
         let sign_address = recover_signer(&transaction)?;
 
         let height_key = keys::latest_height_key(&self.prefix);
-        let height: Option<String> = self.conn.get_connection()?.get(height_key)?;
+        let height: Option<String> = self.conn.get()?.get(height_key)?;
         let height = match height {
             Some(str) => str.parse::<u32>()?,
             _ => 0,
         };
-        let balance_key = keys::balance_key(&self.prefix, sign_address);
-        let balance: Option<String> = self.conn.get_connection()?.vkv_get(balance_key, height)?;
-        let balance = if let Some(s) = balance {
-            serde_json::from_str(s.as_str())?
-        } else {
-            U256::zero()
-        };
+        let key = self.accounts.key(AccountKey::Balance(sign_address));
+        let balance = self
+            .accounts
+            .balance
+            .get_at(&mut *self.conn.get()?, &key, height)?
+            .unwrap_or_default();
 
         let pending_balance_key = keys::pending_balance_key(&self.prefix, sign_address);
         let total_payment = transaction
             .value
             .saturating_add(transaction.gas_price.saturating_mul(transaction.gas_limit));
-        self.conn.get_connection()?.set(
+        self.conn.get()?.set(
             pending_balance_key,
             serde_json::to_string(&balance.saturating_sub(total_payment))?,
         )?;
 
         let pending_nonce_key = keys::pending_nonce_key(&self.prefix, sign_address);
-        self.conn.get_connection()?.set(
+        self.conn.get()?.set(
             pending_nonce_key,
             serde_json::to_string(&transaction.nonce)?,
         )?;
@@ -770,14 +1892,14 @@ impl Setter for RedisClusterSetter {
 
     fn set_pending_code(&self, address: H160, code: Vec<u8>) -> Result<()> {
         let pending_code_key = keys::pending_code_key(&self.prefix, address);
-        self.conn.get_connection()?
+        self.conn.get()?
             .set(pending_code_key, serde_json::to_string(&code)?)?;
         Ok(())
     }
 
     fn set_pending_state(&self, address: H160, index: H256, value: H256) -> Result<()> {
         let pending_state_key = keys::pending_state_key(&self.prefix, address, index);
-        self.conn.get_connection()?
+        self.conn.get()?
             .set(pending_state_key, serde_json::to_string(&value)?)?;
 
         Ok(())
@@ -787,30 +1909,30 @@ impl Setter for RedisClusterSetter {
         let sign_address = recover_signer(&transaction)?;
         let pending_balance_key = keys::pending_balance_key(&self.prefix, sign_address);
 
-        self.conn.get_connection()?.del(pending_balance_key)?;
+        self.conn.get()?.del(pending_balance_key)?;
 
         let pending_nonce_key = keys::pending_nonce_key(&self.prefix, sign_address);
-        self.conn.get_connection()?.del(pending_nonce_key)?;
+        self.conn.get()?.del(pending_nonce_key)?;
 
         Ok(())
     }
 
     fn remove_pending_code(&self, address: H160) -> Result<()> {
         let pending_code_key = keys::pending_code_key(&self.prefix, address);
-        self.conn.get_connection()?.del(pending_code_key)?;
+        self.conn.get()?.del(pending_code_key)?;
         Ok(())
     }
 
     fn remove_pending_state(&self, address: H160, index: H256) -> Result<()> {
         let pending_state_key = keys::pending_state_key(&self.prefix, address, index);
-        self.conn.get_connection()?.del(pending_state_key)?;
+        self.conn.get()?.del(pending_state_key)?;
 
         Ok(())
     }
 
     fn set_total_issuance(&self, height: u32, value: U256) -> Result<()> {
         let key = keys::total_issuance_key(&self.prefix);
-        self.conn.get_connection()?
+        self.conn.get()?
             .vkv_set(key, height, serde_json::to_string(&value)?)?;
         Ok(())
     }
@@ -823,8 +1945,951 @@ impl Setter for RedisClusterSetter {
         value: U256,
     ) -> Result<()> {
         let key = keys::allowances_key(&self.prefix, owner, spender);
-        self.conn.get_connection()?
+        self.conn.get()?
             .vkv_set(key, height, serde_json::to_string(&value)?)?;
         Ok(())
     }
+
+    fn set_balances(&self, height: u32, balances: Vec<(H160, U256)>) -> Result<()> {
+        self.query_pipelined(redis_balance_cmds(&self.prefix, &self.accounts, height, &balances)?)
+    }
+
+    fn set_nonces(&self, height: u32, nonces: Vec<(H160, U256)>) -> Result<()> {
+        self.query_pipelined(redis_nonce_cmds(&self.prefix, &self.accounts, height, &nonces)?)
+    }
+
+    fn set_byte_codes(&self, height: u32, byte_codes: Vec<(H160, Vec<u8>)>) -> Result<()> {
+        self.query_pipelined(redis_byte_code_cmds(
+            &self.prefix,
+            &self.accounts,
+            height,
+            &byte_codes,
+        )?)
+    }
+
+    fn set_states(&self, height: u32, states: Vec<(H160, H256, H256)>) -> Result<()> {
+        self.query_pipelined(redis_state_cmds(&self.prefix, &self.accounts, height, &states)?)
+    }
+
+    /// Keys of one block span many slots, so a cluster cannot apply them in
+    /// one transaction. The data is pipelined first and the latest height is
+    /// only moved once it has all been written, so readers still never see
+    /// a partial height.
+    fn commit_block(&self, changes: BlockChanges) -> Result<()> {
+        self.query_pipelined(redis_block_cmds(&self.prefix, &self.accounts, &changes)?)?;
+        self.set_height(changes.height)
+    }
+}
+
+#[cfg(feature = "memory")]
+pub struct MemorySetter {
+    db: MemoryDb,
+}
+
+#[cfg(feature = "memory")]
+impl MemorySetter {
+    pub fn new(connection: ConnectionType, _something: String) -> Self {
+        if let ConnectionType::Memory(db) = connection {
+            Self { db }
+        } else {
+            panic!("Invalid connection type for Memory")
+        }
+    }
+}
+
+#[cfg(feature = "memory")]
+impl Setter for MemorySetter {
+    fn clear(&self) -> Result<()> {
+        *self.db.write()? = MemoryData::default();
+        Ok(())
+    }
+    fn set_height(&self, height: u32) -> Result<()> {
+        self.db.write()?.latest_height = height;
+        Ok(())
+    }
+    fn set_lowest_height(&self, height: u32) -> Result<()> {
+        self.db.write()?.lowest_height = height;
+        Ok(())
+    }
+    fn prune_below(&self, height: u32) -> Result<()> {
+        let mut data = self.db.write()?;
+        let height = height.min(data.latest_height);
+        data.balance
+            .values_mut()
+            .for_each(|v| prune_versions(v, height));
+        data.nonce
+            .values_mut()
+            .for_each(|v| prune_versions(v, height));
+        data.code_hash
+            .values_mut()
+            .for_each(|v| prune_versions(v, height));
+        data.state
+            .values_mut()
+            .for_each(|v| prune_versions(v, height));
+        data.allowances
+            .values_mut()
+            .for_each(|v| prune_versions(v, height));
+        prune_versions(&mut data.total_issuance, height);
+        data.lowest_height = height;
+        Ok(())
+    }
+    fn rollback_to(&self, height: u32) -> Result<()> {
+        let mut data = self.db.write()?;
+        data.balance.values_mut().for_each(|v| {
+            v.split_off(&(height + 1));
+        });
+        data.nonce.values_mut().for_each(|v| {
+            v.split_off(&(height + 1));
+        });
+        data.code_hash.values_mut().for_each(|v| {
+            v.split_off(&(height + 1));
+        });
+        data.state.values_mut().for_each(|v| {
+            v.split_off(&(height + 1));
+        });
+        data.allowances.values_mut().for_each(|v| {
+            v.split_off(&(height + 1));
+        });
+        data.total_issuance.split_off(&(height + 1));
+
+        data.logs.split_off(&(height + 1));
+        data.state_diffs.split_off(&(height + 1));
+        let removed = data.block_hash.split_off(&U256::from(height + 1));
+        for block_hash in removed.into_values() {
+            if let Some(statuses) = data.statuses.remove(&block_hash) {
+                for tx in statuses.iter() {
+                    data.transaction_index.remove(&tx.transaction_hash);
+                }
+            }
+            data.block_height.remove(&block_hash);
+            data.block.remove(&block_hash);
+            data.receipts.remove(&block_hash);
+        }
+
+        data.latest_height = data.latest_height.min(height);
+        data.lowest_height = data.lowest_height.min(height);
+        data.rollbacks += 1;
+        Ok(())
+    }
+    fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()> {
+        self.db
+            .write()?
+            .balance
+            .entry(address)
+            .or_default()
+            .insert(height, balance);
+        Ok(())
+    }
+    fn remove_balance(&self, height: u32, address: H160) -> Result<()> {
+        if let Some(versions) = self.db.write()?.balance.get_mut(&address) {
+            versions.remove(&height);
+        }
+        Ok(())
+    }
+    fn set_nonce(&self, height: u32, address: H160, nonce: U256) -> Result<()> {
+        self.db
+            .write()?
+            .nonce
+            .entry(address)
+            .or_default()
+            .insert(height, nonce);
+        Ok(())
+    }
+    fn remove_nonce(&self, height: u32, address: H160) -> Result<()> {
+        if let Some(versions) = self.db.write()?.nonce.get_mut(&address) {
+            versions.remove(&height);
+        }
+        Ok(())
+    }
+    fn set_byte_code(&self, height: u32, address: H160, code: Vec<u8>) -> Result<()> {
+        self.db.write()?.insert_byte_code(height, address, code);
+        Ok(())
+    }
+    fn remove_byte_code(&self, height: u32, address: H160) -> Result<()> {
+        if let Some(versions) = self.db.write()?.code_hash.get_mut(&address) {
+            versions.remove(&height);
+        }
+        Ok(())
+    }
+    fn set_state(&self, height: u32, address: H160, index: H256, value: H256) -> Result<()> {
+        self.db
+            .write()?
+            .state
+            .entry((address, index))
+            .or_default()
+            .insert(height, value);
+        Ok(())
+    }
+    fn remove_state(&self, height: u32, address: H160, index: H256) -> Result<()> {
+        if let Some(versions) = self.db.write()?.state.get_mut(&(address, index)) {
+            versions.remove(&height);
+        }
+        Ok(())
+    }
+    fn set_block_info(
+        &self,
+        block: Block,
+        receipts: Vec<Receipt>,
+        statuses: Vec<TransactionStatus>,
+    ) -> Result<()> {
+        self.db
+            .write()?
+            .insert_block_info(block, receipts, statuses);
+        Ok(())
+    }
+    fn remove_block_info(&self, height: U256) -> Result<()> {
+        let mut data = self.db.write()?;
+        data.logs.remove(&height.as_u32());
+        let block_hash = match data.block_hash.remove(&height) {
+            Some(v) => v,
+            None => {
+                return Ok(());
+            }
+        };
+        if let Some(statuses) = data.statuses.remove(&block_hash) {
+            for tx in statuses.iter() {
+                data.transaction_index.remove(&tx.transaction_hash);
+            }
+        }
+        data.block_height.remove(&block_hash);
+        data.block.remove(&block_hash);
+        data.receipts.remove(&block_hash);
+        Ok(())
+    }
+    fn set_pending_tx(&self, transaction: LegacyTransaction) -> Result<()> {
+        let sign_address = recover_signer(&transaction)?;
+
+        let mut data = self.db.write()?;
+        let balance = versioned_get(data.balance.get(&sign_address), data.latest_height)
+            .copied()
+            .unwrap_or_default();
+        let total_payment = transaction
+            .value
+            .saturating_add(transaction.gas_price.saturating_mul(transaction.gas_limit));
+        data.pending_balance
+            .insert(sign_address, balance.saturating_sub(total_payment));
+        data.pending_nonce.insert(sign_address, transaction.nonce);
+        Ok(())
+    }
+    fn set_pending_code(&self, address: H160, code: Vec<u8>) -> Result<()> {
+        self.db.write()?.pending_code.insert(address, code);
+        Ok(())
+    }
+    fn set_pending_state(&self, address: H160, index: H256, value: H256) -> Result<()> {
+        self.db
+            .write()?
+            .pending_state
+            .insert((address, index), value);
+        Ok(())
+    }
+    fn remove_pending_tx(&self, transaction: LegacyTransaction) -> Result<()> {
+        let sign_address = recover_signer(&transaction)?;
+
+        let mut data = self.db.write()?;
+        data.pending_balance.remove(&sign_address);
+        data.pending_nonce.remove(&sign_address);
+        Ok(())
+    }
+    fn remove_pending_code(&self, address: H160) -> Result<()> {
+        self.db.write()?.pending_code.remove(&address);
+        Ok(())
+    }
+    fn remove_pending_state(&self, address: H160, index: H256) -> Result<()> {
+        self.db.write()?.pending_state.remove(&(address, index));
+        Ok(())
+    }
+    fn set_total_issuance(&self, height: u32, value: U256) -> Result<()> {
+        self.db.write()?.total_issuance.insert(height, value);
+        Ok(())
+    }
+    fn set_allowances(&self, height: u32, owner: H160, spender: H160, value: U256) -> Result<()> {
+        self.db
+            .write()?
+            .allowances
+            .entry((owner, spender))
+            .or_default()
+            .insert(height, value);
+        Ok(())
+    }
+
+    fn set_balances(&self, height: u32, balances: Vec<(H160, U256)>) -> Result<()> {
+        let mut data = self.db.write()?;
+        for (address, balance) in balances {
+            data.balance
+                .entry(address)
+                .or_default()
+                .insert(height, balance);
+        }
+        Ok(())
+    }
+    fn set_nonces(&self, height: u32, nonces: Vec<(H160, U256)>) -> Result<()> {
+        let mut data = self.db.write()?;
+        for (address, nonce) in nonces {
+            data.nonce.entry(address).or_default().insert(height, nonce);
+        }
+        Ok(())
+    }
+    fn set_byte_codes(&self, height: u32, codes: Vec<(H160, Vec<u8>)>) -> Result<()> {
+        let mut data = self.db.write()?;
+        for (address, code) in codes {
+            data.insert_byte_code(height, address, code);
+        }
+        Ok(())
+    }
+    fn set_states(&self, height: u32, states: Vec<(H160, H256, H256)>) -> Result<()> {
+        let mut data = self.db.write()?;
+        for (address, index, value) in states {
+            data.state
+                .entry((address, index))
+                .or_default()
+                .insert(height, value);
+        }
+        Ok(())
+    }
+
+    fn commit_block(&self, changes: BlockChanges) -> Result<()> {
+        let height = changes.height;
+
+        let mut data = self.db.write()?;
+        if let Some((block, receipts, statuses)) = changes.block_info {
+            data.insert_block_info(block, receipts, statuses);
+        }
+        for (address, balance) in changes.balances {
+            data.balance
+                .entry(address)
+                .or_default()
+                .insert(height, balance);
+        }
+        for (address, nonce) in changes.nonces {
+            data.nonce.entry(address).or_default().insert(height, nonce);
+        }
+        for (address, code) in changes.byte_codes {
+            data.insert_byte_code(height, address, code);
+        }
+        for (address, index, value) in changes.states {
+            data.state
+                .entry((address, index))
+                .or_default()
+                .insert(height, value);
+        }
+        for (owner, spender, value) in changes.allowances {
+            data.allowances
+                .entry((owner, spender))
+                .or_default()
+                .insert(height, value);
+        }
+        if let Some(value) = changes.total_issuance {
+            data.total_issuance.insert(height, value);
+        }
+        if let Some(diff) = changes.state_diff {
+            data.state_diffs.insert(height, diff);
+        }
+        data.latest_height = height;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+pub struct RocksSetter {
+    db: RocksDb,
+    pub prefix: String,
+}
+
+#[cfg(feature = "rocksdb")]
+impl RocksSetter {
+    pub fn new(connection: ConnectionType, prefix: String) -> Self {
+        if let ConnectionType::RocksDB(db) = connection {
+            rocks_upgrade_data(&db, &prefix).expect("upgrade rocksdb data failed");
+            Self { db, prefix }
+        } else {
+            panic!("Invalid connection type for RocksDB")
+        }
+    }
+
+    fn write_changes(&self, changes: BlockChanges) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        rocks_block_batch(&self.prefix, &changes, &mut batch)?;
+        self.db.write(batch)
+    }
+
+    fn get_height(&self, key: &str) -> Result<u32> {
+        match self.db.get(key)? {
+            Some(str) => Ok(str.parse::<u32>()?),
+            _ => Ok(0),
+        }
+    }
+}
+
+/// RocksDB counterpart of `redis_block_cmds`, the latest height is left to
+/// the caller.
+#[cfg(feature = "rocksdb")]
+fn rocks_block_batch(prefix: &str, changes: &BlockChanges, batch: &mut WriteBatch) -> Result<()> {
+    let height = changes.height;
+
+    if let Some((block, receipts, statuses)) = &changes.block_info {
+        let block_hash = block.header.hash();
+        let number = block.header.number;
+
+        batch.put(
+            keys::block_hash_key(prefix, number),
+            serde_json::to_string(&block_hash)?,
+        );
+        batch.put(
+            keys::block_height_key(prefix, block_hash),
+            serde_json::to_string(&number)?,
+        );
+        batch.put(keys::block_key(prefix, block_hash), encode_block(block)?);
+        batch.put(
+            keys::receipt_key(prefix, block_hash),
+            encode_receipts(receipts)?,
+        );
+        batch.put(
+            keys::status_key(prefix, block_hash),
+            encode_statuses(statuses)?,
+        );
+        for (i, tx) in statuses.iter().enumerate() {
+            batch.put(
+                keys::transaction_index_key(prefix, tx.transaction_hash),
+                serde_json::to_string(&(block_hash, i as u32))?,
+            );
+        }
+
+        rocks_logs_batch(prefix, number.as_u32(), &block_logs(block, statuses), batch)?;
+    }
+    for (address, balance) in changes.balances.iter() {
+        batch.put(
+            keys::versioned_key(&keys::balance_key(prefix, *address), height),
+            serde_json::to_string(balance)?,
+        );
+    }
+    for (address, nonce) in changes.nonces.iter() {
+        batch.put(
+            keys::versioned_key(&keys::nonce_key(prefix, *address), height),
+            serde_json::to_string(nonce)?,
+        );
+    }
+    for (address, code) in changes.byte_codes.iter() {
+        let hash = code_hash(code);
+        batch.put(keys::code_by_hash_key(prefix, hash), hex::encode(code));
+        batch.put(
+            keys::versioned_key(&keys::code_hash_key(prefix, *address), height),
+            serde_json::to_string(&hash)?,
+        );
+    }
+    for (address, index, value) in changes.states.iter() {
+        batch.put(
+            keys::versioned_key(&keys::state_key(prefix, *address, *index), height),
+            serde_json::to_string(value)?,
+        );
+        let state_addr_key = keys::state_addr_key(prefix, *address);
+        batch.put(
+            keys::versioned_key(&state_addr_key, height),
+            &state_addr_key,
+        );
+    }
+    for (owner, spender, value) in changes.allowances.iter() {
+        batch.put(
+            keys::versioned_key(&keys::allowances_key(prefix, *owner, *spender), height),
+            serde_json::to_string(value)?,
+        );
+    }
+    if let Some(value) = changes.total_issuance {
+        batch.put(
+            keys::versioned_key(&keys::total_issuance_key(prefix), height),
+            serde_json::to_string(&value)?,
+        );
+    }
+    if let Some(diff) = &changes.state_diff {
+        batch.put(
+            keys::state_diff_key(prefix, height),
+            serde_json::to_string(diff)?,
+        );
+    }
+    Ok(())
+}
+
+/// Queues storing the `logs` of `height`. They are stored as a version of the
+/// logs prefix, so a height range is one scan.
+#[cfg(feature = "rocksdb")]
+fn rocks_logs_batch(
+    prefix: &str,
+    height: u32,
+    logs: &[IndexedLog],
+    batch: &mut WriteBatch,
+) -> Result<()> {
+    if !logs.is_empty() {
+        batch.put(
+            keys::versioned_key(&keys::logs_index_prefix(prefix), height),
+            serde_json::to_string(logs)?,
+        );
+    }
+    Ok(())
+}
+
+/// RocksDB counterpart of `redis_upgrade_data`.
+#[cfg(feature = "rocksdb")]
+fn rocks_upgrade_data(db: &RocksDb, prefix: &str) -> Result<()> {
+    let version = match db.get(&keys::data_version_key(prefix))? {
+        Some(str) => str.parse::<u32>()?,
+        _ => 0,
+    };
+    if version >= DATA_VERSION {
+        return Ok(());
+    }
+    if version < 1 {
+        rocks_backfill_logs(db, prefix)?;
+    }
+    db.put(&keys::data_version_key(prefix), &format!("{}", DATA_VERSION))
+}
+
+/// Indexes the logs of every block exported before the logs index existed.
+#[cfg(feature = "rocksdb")]
+fn rocks_backfill_logs(db: &RocksDb, prefix: &str) -> Result<()> {
+    let latest = match db.get(&keys::latest_height_key(prefix))? {
+        Some(str) => str.parse::<u32>()?,
+        _ => 0,
+    };
+    for height in 0..=latest {
+        let block_hash: H256 = match db.get(&keys::block_hash_key(prefix, U256::from(height)))? {
+            Some(v) => serde_json::from_str(&v)?,
+            None => continue,
+        };
+        let block = db.get_bytes(&keys::block_key(prefix, block_hash))?;
+        let statuses = db.get_bytes(&keys::status_key(prefix, block_hash))?;
+        if let (Some(block), Some(statuses)) = (block, statuses) {
+            let logs = block_logs(&decode_block(&block)?, &decode_statuses(&statuses)?);
+            let mut batch = WriteBatch::default();
+            rocks_logs_batch(prefix, height, &logs, &mut batch)?;
+            db.write(batch)?;
+        }
+    }
+    Ok(())
+}
+
+/// Queues the removal of the block at `height`, its logs included.
+#[cfg(feature = "rocksdb")]
+fn rocks_remove_block_info(
+    db: &RocksDb,
+    prefix: &str,
+    height: U256,
+    batch: &mut WriteBatch,
+) -> Result<()> {
+    batch.delete(keys::versioned_key(
+        &keys::logs_index_prefix(prefix),
+        height.as_u32(),
+    ));
+
+    let block_hash_key = keys::block_hash_key(prefix, height);
+    let block_hash: H256 = match db.get(&block_hash_key)? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => {
+            return Ok(());
+        }
+    };
+    let status_key = keys::status_key(prefix, block_hash);
+    let statuses: Vec<TransactionStatus> = match db.get_bytes(&status_key)? {
+        Some(v) => decode_statuses(&v)?,
+        None => {
+            return Err(Error::ValueNotFound);
+        }
+    };
+    for tx in statuses.iter() {
+        batch.delete(keys::transaction_index_key(prefix, tx.transaction_hash));
+    }
+    batch.delete(keys::block_height_key(prefix, block_hash));
+    batch.delete(keys::block_key(prefix, block_hash));
+    batch.delete(keys::receipt_key(prefix, block_hash));
+    batch.delete(status_key);
+    batch.delete(block_hash_key);
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+impl Setter for RocksSetter {
+    fn clear(&self) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for (key, _) in self.db.scan(&format!("{}:", self.prefix)) {
+            batch.delete(key);
+        }
+        batch.put(
+            keys::data_version_key(&self.prefix),
+            format!("{}", DATA_VERSION),
+        );
+        self.db.write(batch)
+    }
+    fn set_height(&self, height: u32) -> Result<()> {
+        let height_key = keys::latest_height_key(&self.prefix);
+        self.db.put(&height_key, &format!("{}", height))
+    }
+    fn set_lowest_height(&self, height: u32) -> Result<()> {
+        let height_key = keys::lowest_height_key(&self.prefix);
+        self.db.put(&height_key, &format!("{}", height))
+    }
+
+    /// Versions of one key are adjacent, so a version at or below `height`
+    /// is dropped once the next one is also at or below it.
+    fn prune_below(&self, height: u32) -> Result<()> {
+        let height = height.min(self.get_height(&keys::latest_height_key(&self.prefix))?);
+        let logs_prefix = keys::logs_index_prefix(&self.prefix);
+        let mut batch = WriteBatch::default();
+        let mut shadowed: Option<(String, Box<[u8]>)> = None;
+        for (key, _) in self.db.scan(&format!("{}:", self.prefix)) {
+            let name = match split_versioned(&key) {
+                Some((name, version)) if version <= height && !name.starts_with(&logs_prefix) => {
+                    name.to_string()
+                }
+                _ => continue,
+            };
+            if let Some((shadowed_name, shadowed_key)) = shadowed.take() {
+                if shadowed_name == name {
+                    batch.delete(shadowed_key);
+                }
+            }
+            shadowed = Some((name, key));
+        }
+        batch.put(keys::lowest_height_key(&self.prefix), format!("{}", height));
+        self.db.write(batch)
+    }
+
+    /// Written as a single batch, heights included.
+    fn rollback_to(&self, height: u32) -> Result<()> {
+        let latest = self.get_height(&keys::latest_height_key(&self.prefix))?;
+        let lowest = self.get_height(&keys::lowest_height_key(&self.prefix))?;
+
+        let mut batch = WriteBatch::default();
+        for h in (height + 1)..=latest {
+            rocks_remove_block_info(&self.db, &self.prefix, U256::from(h), &mut batch)?;
+            batch.delete(keys::state_diff_key(&self.prefix, h));
+        }
+        for (key, _) in self.db.scan(&format!("{}:", self.prefix)) {
+            if matches!(split_versioned(&key), Some((_, version)) if version > height) {
+                batch.delete(key);
+            }
+        }
+        batch.put(
+            keys::latest_height_key(&self.prefix),
+            format!("{}", latest.min(height)),
+        );
+        batch.put(
+            keys::lowest_height_key(&self.prefix),
+            format!("{}", lowest.min(height)),
+        );
+        let rollbacks = match self.db.get(&keys::rollback_count_key(&self.prefix))? {
+            Some(count) => count.parse::<u64>()?,
+            None => 0,
+        };
+        batch.put(
+            keys::rollback_count_key(&self.prefix),
+            format!("{}", rollbacks + 1),
+        );
+        self.db.write(batch)
+    }
+    fn set_balance(&self, height: u32, address: H160, balance: U256) -> Result<()> {
+        let balance_key = keys::balance_key(&self.prefix, address);
+        self.db.put(
+            &keys::versioned_key(&balance_key, height),
+            &serde_json::to_string(&balance)?,
+        )
+    }
+    fn remove_balance(&self, height: u32, address: H160) -> Result<()> {
+        let balance_key = keys::balance_key(&self.prefix, address);
+        self.db.delete(&keys::versioned_key(&balance_key, height))
+    }
+    fn set_nonce(&self, height: u32, address: H160, nonce: U256) -> Result<()> {
+        let nonce_key = keys::nonce_key(&self.prefix, address);
+        self.db.put(
+            &keys::versioned_key(&nonce_key, height),
+            &serde_json::to_string(&nonce)?,
+        )
+    }
+    fn remove_nonce(&self, height: u32, address: H160) -> Result<()> {
+        let nonce_key = keys::nonce_key(&self.prefix, address);
+        self.db.delete(&keys::versioned_key(&nonce_key, height))
+    }
+    fn set_byte_code(&self, height: u32, address: H160, code: Vec<u8>) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            byte_codes: vec![(address, code)],
+            ..Default::default()
+        })
+    }
+    fn remove_byte_code(&self, height: u32, address: H160) -> Result<()> {
+        let code_hash_key = keys::code_hash_key(&self.prefix, address);
+        self.db.delete(&keys::versioned_key(&code_hash_key, height))
+    }
+    fn set_state(&self, height: u32, address: H160, index: H256, value: H256) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            states: vec![(address, index, value)],
+            ..Default::default()
+        })
+    }
+    /// The account itself keeps its state while any other slot has a value.
+    fn remove_state(&self, height: u32, address: H160, index: H256) -> Result<()> {
+        let state_key = keys::state_key(&self.prefix, address, index);
+        self.db.delete(&keys::versioned_key(&state_key, height))?;
+        if !rocks_has_storage(&self.db, &self.prefix, height, address)? {
+            let state_addr_key = keys::state_addr_key(&self.prefix, address);
+            self.db.delete(&keys::versioned_key(&state_addr_key, height))?;
+        }
+        Ok(())
+    }
+    fn set_block_info(
+        &self,
+        block: Block,
+        receipts: Vec<Receipt>,
+        statuses: Vec<TransactionStatus>,
+    ) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height: block.header.number.as_u32(),
+            block_info: Some((block, receipts, statuses)),
+            ..Default::default()
+        })
+    }
+    fn remove_block_info(&self, height: U256) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        rocks_remove_block_info(&self.db, &self.prefix, height, &mut batch)?;
+        self.db.write(batch)
+    }
+    fn set_pending_tx(&self, transaction: LegacyTransaction) -> Result<()> {
+        let sign_address = recover_signer(&transaction)?;
+
+        let height = self.get_height(&keys::latest_height_key(&self.prefix))?;
+        let balance_key = keys::balance_key(&self.prefix, sign_address);
+        let balance = match self.db.versioned_get(&balance_key, height)? {
+            Some(s) => serde_json::from_str(s.as_str())?,
+            None => U256::zero(),
+        };
+        let total_payment = transaction
+            .value
+            .saturating_add(transaction.gas_price.saturating_mul(transaction.gas_limit));
+
+        let mut batch = WriteBatch::default();
+        batch.put(
+            keys::pending_balance_key(&self.prefix, sign_address),
+            serde_json::to_string(&balance.saturating_sub(total_payment))?,
+        );
+        batch.put(
+            keys::pending_nonce_key(&self.prefix, sign_address),
+            serde_json::to_string(&transaction.nonce)?,
+        );
+        self.db.write(batch)
+    }
+    fn set_pending_code(&self, address: H160, code: Vec<u8>) -> Result<()> {
+        let pending_code_key = keys::pending_code_key(&self.prefix, address);
+        self.db.put(&pending_code_key, &hex::encode(code))
+    }
+    fn set_pending_state(&self, address: H160, index: H256, value: H256) -> Result<()> {
+        let pending_state_key = keys::pending_state_key(&self.prefix, address, index);
+        self.db
+            .put(&pending_state_key, &serde_json::to_string(&value)?)
+    }
+    fn remove_pending_tx(&self, transaction: LegacyTransaction) -> Result<()> {
+        let sign_address = recover_signer(&transaction)?;
+
+        let mut batch = WriteBatch::default();
+        batch.delete(keys::pending_balance_key(&self.prefix, sign_address));
+        batch.delete(keys::pending_nonce_key(&self.prefix, sign_address));
+        self.db.write(batch)
+    }
+    fn remove_pending_code(&self, address: H160) -> Result<()> {
+        self.db
+            .delete(&keys::pending_code_key(&self.prefix, address))
+    }
+    fn remove_pending_state(&self, address: H160, index: H256) -> Result<()> {
+        self.db
+            .delete(&keys::pending_state_key(&self.prefix, address, index))
+    }
+    fn set_total_issuance(&self, height: u32, value: U256) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            total_issuance: Some(value),
+            ..Default::default()
+        })
+    }
+    fn set_allowances(&self, height: u32, owner: H160, spender: H160, value: U256) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            allowances: vec![(owner, spender, value)],
+            ..Default::default()
+        })
+    }
+
+    fn set_balances(&self, height: u32, balances: Vec<(H160, U256)>) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            balances,
+            ..Default::default()
+        })
+    }
+    fn set_nonces(&self, height: u32, nonces: Vec<(H160, U256)>) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            nonces,
+            ..Default::default()
+        })
+    }
+    fn set_byte_codes(&self, height: u32, byte_codes: Vec<(H160, Vec<u8>)>) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            byte_codes,
+            ..Default::default()
+        })
+    }
+    fn set_states(&self, height: u32, states: Vec<(H160, H256, H256)>) -> Result<()> {
+        self.write_changes(BlockChanges {
+            height,
+            states,
+            ..Default::default()
+        })
+    }
+
+    /// Written as one `WriteBatch`, the latest height included.
+    fn commit_block(&self, changes: BlockChanges) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        rocks_block_batch(&self.prefix, &changes, &mut batch)?;
+        batch.put(
+            keys::latest_height_key(&self.prefix),
+            format!("{}", changes.height),
+        );
+        self.db.write(batch)
+    }
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod tests {
+    use {
+        crate::{ConnectionType, Getter, RedisGetter, RedisSetter, Setter},
+        primitive_types::{H160, H256, U256},
+        redis::Commands,
+    };
+
+    const URL: &str = "redis://127.0.0.1/";
+
+    /// A setter and a getter of `prefix`, whose keys are deleted first.
+    fn open(prefix: &str) -> (RedisSetter, RedisGetter) {
+        let mut conn = redis::Client::open(URL).unwrap().get_connection().unwrap();
+        let keys: Vec<String> = conn.keys(format!("{}:*", prefix)).unwrap();
+        if !keys.is_empty() {
+            conn.del::<_, ()>(keys).unwrap();
+        }
+        (
+            RedisSetter::new(ConnectionType::Redis(URL.to_string()), prefix.to_string()),
+            RedisGetter::new(ConnectionType::Redis(URL.to_string()), prefix.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_remove_state() {
+        let (setter, getter) = open("test_remove_state");
+        let address = H160::repeat_byte(1);
+        let (a, b) = (H256::repeat_byte(2), H256::repeat_byte(3));
+        setter.set_state(1, address, a, H256::repeat_byte(4)).unwrap();
+        setter.set_state(1, address, b, H256::repeat_byte(5)).unwrap();
+
+        setter.remove_state(1, address, a).unwrap();
+        assert!(getter.addr_state_exists(1, address).unwrap());
+        assert_eq!(getter.get_state(2, address, a).unwrap(), H256::zero());
+
+        setter.remove_state(1, address, b).unwrap();
+        assert!(!getter.addr_state_exists(2, address).unwrap());
+    }
+
+    #[test]
+    fn test_remove_balance() {
+        let (setter, getter) = open("test_remove_balance");
+        let address = H160::repeat_byte(1);
+        setter.set_balance(4, address, U256::from(10)).unwrap();
+        setter.set_balance(9, address, U256::from(20)).unwrap();
+
+        // Only the version at the height goes, as on the other backends.
+        setter.remove_balance(9, address).unwrap();
+        assert_eq!(getter.get_balance(11, address).unwrap(), U256::from(10));
+        setter.remove_balance(6, address).unwrap();
+        assert_eq!(getter.get_balance(11, address).unwrap(), U256::from(10));
+    }
+
+    #[test]
+    fn test_prune_below() {
+        let (setter, getter) = open("test_prune_below");
+        let addr = H160::repeat_byte(6);
+        setter.set_nonce(2, addr, U256::from(1)).unwrap();
+        setter.set_nonce(5, addr, U256::from(2)).unwrap();
+        setter.set_nonce(8, addr, U256::from(3)).unwrap();
+        setter.set_height(8).unwrap();
+        setter.prune_below(6).unwrap();
+
+        assert_eq!(getter.lowest_height().unwrap(), 6);
+        assert_eq!(getter.get_nonce(4, addr).unwrap(), U256::zero());
+        assert_eq!(getter.get_nonce(6, addr).unwrap(), U256::from(2));
+
+        // A height above the latest one is clamped to it.
+        setter.prune_below(20).unwrap();
+        assert_eq!(getter.lowest_height().unwrap(), 8);
+        assert_eq!(getter.get_nonce(8, addr).unwrap(), U256::from(3));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let (setter, getter) = open("test_ranges");
+        let address = H160::repeat_byte(1);
+        let slot = |i: u8| (H256::repeat_byte(i), H256::repeat_byte(i));
+        let states = (1..=5).map(|i| (address, slot(i).0, slot(i).1)).collect();
+        setter.set_states(1, states).unwrap();
+        setter.set_state(2, address, slot(2).0, H256::zero()).unwrap();
+        let other = H160::repeat_byte(2);
+        setter
+            .set_balances(1, vec![(address, U256::from(7)), (other, U256::one())])
+            .unwrap();
+        setter.set_nonce(2, H160::repeat_byte(3), U256::one()).unwrap();
+
+        let range = getter.get_storage_range(2, address, slot(1).0, 2).unwrap();
+        assert_eq!(range.slots, vec![slot(1), slot(3)]);
+        assert_eq!(range.next, Some(slot(4).0));
+        let range = getter.get_storage_range(1, address, slot(4).0, 2).unwrap();
+        assert_eq!(range.slots, vec![slot(4), slot(5)]);
+        assert_eq!(range.next, None);
+
+        let range = getter.get_account_range(1, H160::zero(), 10).unwrap();
+        let addresses = range.accounts.iter().map(|(a, _)| *a).collect::<Vec<_>>();
+        assert_eq!(addresses, vec![address, other]);
+        assert_eq!(range.accounts[0].1.balance, U256::from(7));
+        let range = getter.get_account_range(2, other, 1).unwrap();
+        assert_eq!(range.accounts[0].0, other);
+        assert_eq!(range.next, Some(H160::repeat_byte(3)));
+    }
+
+    #[test]
+    fn test_batched_reads() {
+        let (setter, getter) = open("test_batched_reads");
+        let (alice, bob) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let index = H256::repeat_byte(3);
+        setter.set_balance(1, alice, U256::from(10)).unwrap();
+        setter.set_balance(2, alice, U256::from(7)).unwrap();
+        setter.set_nonce(1, bob, U256::one()).unwrap();
+        setter.set_byte_code(1, alice, vec![0x60, 0x00]).unwrap();
+        setter.set_state(2, bob, index, H256::repeat_byte(4)).unwrap();
+
+        let addresses = [alice, bob, alice];
+        let slots = [(alice, index), (bob, index)];
+        for height in 0..=2 {
+            assert_eq!(
+                getter.get_balances(height, &addresses).unwrap(),
+                addresses.map(|address| getter.get_balance(height, address).unwrap())
+            );
+            assert_eq!(
+                getter.get_nonces(height, &addresses).unwrap(),
+                addresses.map(|address| getter.get_nonce(height, address).unwrap())
+            );
+            assert_eq!(
+                getter.get_code_hashes(height, &addresses).unwrap(),
+                addresses.map(|address| getter.get_code_hash(height, address).unwrap())
+            );
+            assert_eq!(
+                getter.get_states(height, &slots).unwrap(),
+                slots.map(|(address, index)| getter.get_state(height, address, index).unwrap())
+            );
+        }
+        assert_eq!(getter.get_states(2, &[]).unwrap(), Vec::<H256>::new());
+    }
 }
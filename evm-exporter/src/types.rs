@@ -3,6 +3,7 @@ use {
     ethereum_types::Bloom,
     primitive_types::{H160, H256, U256},
     serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
 };
 
 pub const PREFIX: &str = "evm";
@@ -25,6 +26,90 @@ pub struct State {
     pub index: H256,
     pub value: H256,
 }
+/// One page of the storage of an account, see `Getter::get_storage_range`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageRange {
+    /// Slots with a non-zero value and their values, in slot order.
+    pub slots: Vec<(H256, H256)>,
+    /// First slot of the next page, `None` on the last one.
+    pub next: Option<H256>,
+}
+
+impl StorageRange {
+    /// Page of the first `limit` non-zero `slots`, which are in slot order.
+    pub fn from_sorted(slots: impl IntoIterator<Item = (H256, H256)>, limit: usize) -> Self {
+        let mut slots = slots
+            .into_iter()
+            .filter(|(_, value)| !value.is_zero())
+            .take(limit.saturating_add(1))
+            .collect::<Vec<_>>();
+        let next = if slots.len() > limit {
+            slots.pop().map(|(index, _)| index)
+        } else {
+            None
+        };
+        Self { slots, next }
+    }
+}
+
+/// Balance, nonce and code hash of an account, see `Getter::get_account_range`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountSummary {
+    pub balance: U256,
+    pub nonce: U256,
+    /// `None` for an account without code.
+    pub code_hash: Option<H256>,
+}
+
+/// One page of the accounts known at a height, see `Getter::get_account_range`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountRange {
+    /// Accounts in address order.
+    pub accounts: Vec<(H160, AccountSummary)>,
+    /// First address of the next page, `None` on the last one.
+    pub next: Option<H160>,
+}
+
+impl AccountRange {
+    /// Page of the first `limit` of `accounts`, which are in address order.
+    pub fn from_sorted(
+        accounts: impl IntoIterator<Item = (H160, AccountSummary)>,
+        limit: usize,
+    ) -> Self {
+        let mut accounts = accounts
+            .into_iter()
+            .take(limit.saturating_add(1))
+            .collect::<Vec<_>>();
+        let next = if accounts.len() > limit {
+            accounts.pop().map(|(address, _)| address)
+        } else {
+            None
+        };
+        Self { accounts, next }
+    }
+}
+
+/// Value of a field before and after a block.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+/// Fields of an account changed by a block, `None` when unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub balance: Option<Change<U256>>,
+    pub nonce: Option<Change<U256>>,
+    /// Keccak hashes of the code, `None` for an account without code.
+    pub code_hash: Option<Change<Option<H256>>>,
+    #[serde(default)]
+    pub storage: BTreeMap<H256, Change<H256>>,
+}
+
+/// Accounts changed by a block, see `Getter::get_state_diff`.
+pub type StateDiff = BTreeMap<H160, AccountDiff>;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransactionStatus {
     pub transaction_hash: H256,
@@ -35,3 +120,72 @@ pub struct TransactionStatus {
     pub logs: Vec<Log>,
     pub logs_bloom: Bloom,
 }
+
+/// One entry of the logs index written next to every block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedLog {
+    pub block_number: u32,
+    pub block_hash: H256,
+    pub transaction_hash: H256,
+    pub transaction_index: u32,
+    pub log_index: u32,
+    pub transaction_log_index: u32,
+    pub address: H160,
+    pub topics: Vec<H256>,
+    pub data: Vec<u8>,
+}
+
+impl IndexedLog {
+    /// `eth_getLogs` matching: an empty `addresses` matches any address, and
+    /// `topics[i]` restricts the i-th topic unless it is `None`.
+    pub fn matches(&self, addresses: &[H160], topics: &[Option<Vec<H256>>]) -> bool {
+        if !addresses.is_empty() && !addresses.contains(&self.address) {
+            return false;
+        }
+        topics.iter().enumerate().all(|(i, wanted)| match wanted {
+            Some(wanted) if !wanted.is_empty() => self
+                .topics
+                .get(i)
+                .map(|topic| wanted.contains(topic))
+                .unwrap_or(false),
+            _ => true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::IndexedLog,
+        primitive_types::{H160, H256},
+    };
+
+    #[test]
+    fn test_log_matches() {
+        let log = IndexedLog {
+            block_number: 1,
+            block_hash: H256::zero(),
+            transaction_hash: H256::zero(),
+            transaction_index: 0,
+            log_index: 0,
+            transaction_log_index: 0,
+            address: H160::repeat_byte(1),
+            topics: vec![H256::repeat_byte(2), H256::repeat_byte(3)],
+            data: vec![],
+        };
+
+        assert!(log.matches(&[], &[]));
+        assert!(log.matches(&[H160::repeat_byte(9), H160::repeat_byte(1)], &[]));
+        assert!(!log.matches(&[H160::repeat_byte(9)], &[]));
+
+        assert!(log.matches(&[], &[None, Some(vec![H256::repeat_byte(3)])]));
+        assert!(log.matches(
+            &[],
+            &[Some(vec![H256::repeat_byte(9), H256::repeat_byte(2)])]
+        ));
+        assert!(!log.matches(&[], &[Some(vec![H256::repeat_byte(3)])]));
+        // No alternatives is a wildcard, a position past the last topic is not.
+        assert!(log.matches(&[], &[Some(vec![])]));
+        assert!(!log.matches(&[], &[None, None, Some(vec![H256::repeat_byte(2)])]));
+    }
+}
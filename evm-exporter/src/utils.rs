@@ -1,5 +1,5 @@
 use {
-    crate::error::Result,
+    crate::{error::Result, Block, IndexedLog, TransactionStatus},
     ethereum::{LegacyTransaction, LegacyTransactionMessage},
     ethereum_types::{H160, H256},
     sha3::{Digest, Keccak256},
@@ -26,3 +26,33 @@ pub fn recover_signer(transaction: &LegacyTransaction) -> Result<H160> {
         Keccak256::digest(&pubkey).as_slice(),
     )))
 }
+
+/// Keccak hash of `code`, the key it is stored under.
+pub fn code_hash(code: &[u8]) -> H256 {
+    H256::from_slice(Keccak256::digest(code).as_slice())
+}
+
+/// Flattens the logs of a block into index entries, numbered the same way
+/// `eth_getLogs` numbers them.
+pub fn block_logs(block: &Block, statuses: &[TransactionStatus]) -> Vec<IndexedLog> {
+    let block_hash = block.header.hash();
+    let block_number = block.header.number.as_u32();
+
+    let mut logs = Vec::new();
+    for status in statuses.iter() {
+        for (transaction_log_index, log) in status.logs.iter().enumerate() {
+            logs.push(IndexedLog {
+                block_number,
+                block_hash,
+                transaction_hash: status.transaction_hash,
+                transaction_index: status.transaction_index,
+                log_index: logs.len() as u32,
+                transaction_log_index: transaction_log_index as u32,
+                address: log.address,
+                topics: log.topics.clone(),
+                data: log.data.clone(),
+            });
+        }
+    }
+    logs
+}
@@ -1,5 +1,9 @@
 #!lua name=versioned_kv
 
+-- Bumped with every change to the functions below, clients check it on
+-- startup against the library they embed.
+local version = 4
+
 -- vkv_set <key> <height> <value>
 local function vkv_set (keys, args)
     local key = keys[1]
@@ -30,28 +34,158 @@ local function vkv_get(keys, args)
 end
 
 -- vkv_del <key> <height>
+-- Records a tombstone: a version whose value key does not exist, so vkv_get
+-- returns nil from <height> on while earlier heights keep their values.
 local function vkv_del(keys, args)
     local key = keys[1]
     local height = args[1]
 
     local res = redis.call('ZRANGE', key, height, '-inf', 'BYSCORE', 'REV', 'LIMIT', 0, 1)
-    if #res ~= 0 then
-        local value_key = res[1]
-        local value = redis.call('GET', value_key);
+    if #res == 0 or redis.call('EXISTS', res[1]) == 0 then
+        return
+    end
+
+    local heighted_key = string.format("%s:%08X", key, height)
+    redis.call('ZADD', key, height, heighted_key)
+    redis.call('DEL', heighted_key)
+end
+
+-- vkv_unset <key> <height> -> <removed>
+-- Drops the version written at exactly <height>, value or tombstone, so the
+-- one below it shows through again.
+local function vkv_unset(keys, args)
+    local key = keys[1]
+    local height = args[1]
 
-        local val_keys = redis.call('ZRANGE', key, height, '-inf', 'BYSCORE', 'REV')
-        for i,val_key in pairs(val_keys) do
-            redis.call('ZREM', key, val_key);
-            redis.call('DEL', val_key)
-        end;
+    local heighted_key = string.format("%s:%08X", key, height)
+    redis.call('DEL', heighted_key)
+
+    return redis.call('ZREM', key, heighted_key)
+end
 
-        local heighted_key = string.format("%s:%08X", key, height)
-        redis.call('ZADD', key, height, heighted_key)
-        redis.call('SET', heighted_key, value)
+-- vkv_compact <key> <height> -> <removed>
+-- Drops every version older than the newest one at or below <height>, and
+-- that one too when it is a tombstone, discarding the history before it.
+local function vkv_compact(keys, args)
+    local key = keys[1]
+    local height = args[1]
+
+    local res = redis.call('ZRANGE', key, height, '-inf', 'BYSCORE', 'REV', 'LIMIT', 0, 1)
+    if #res == 0 then
+        return 0
     end
+
+    local newest = redis.call('ZSCORE', key, res[1])
+    local val_keys = redis.call('ZRANGE', key, '(' .. newest, '-inf', 'BYSCORE', 'REV')
+    for i,val_key in pairs(val_keys) do
+        redis.call('DEL', val_key)
+    end;
+    if #val_keys ~= 0 then
+        redis.call('ZREMRANGEBYSCORE', key, '-inf', '(' .. newest)
+    end
+
+    if redis.call('EXISTS', res[1]) == 0 then
+        redis.call('ZREM', key, res[1])
+        return #val_keys + 1
+    end
+
+    return #val_keys
+end
+
+-- vkv_rollback <key> <height> -> <removed>
+-- Drops every version above <height>.
+local function vkv_rollback(keys, args)
+    local key = keys[1]
+    local height = args[1]
+
+    local val_keys = redis.call('ZRANGE', key, '+inf', '(' .. height, 'BYSCORE', 'REV')
+    for i,val_key in pairs(val_keys) do
+        redis.call('DEL', val_key)
+    end;
+    if #val_keys ~= 0 then
+        redis.call('ZREMRANGEBYSCORE', key, '(' .. height, '+inf')
+    end
+
+    return #val_keys
+end
+
+-- vkv_history <key> <from> <to> -> [[<height>, <value>]...]
+-- Every version written at a height in <from>..=<to>, oldest first. The
+-- value of a tombstone is nil.
+local function vkv_history(keys, args)
+    local key = keys[1]
+    local from = args[1]
+    local to = args[2]
+
+    local res = redis.call('ZRANGE', key, from, to, 'BYSCORE', 'WITHSCORES')
+    local history = {}
+    for i = 1, #res, 2 do
+        history[#history + 1] = {tonumber(res[i + 1]), redis.call('GET', res[i])}
+    end
+
+    return history
+end
+
+-- vkv_heights <key> -> [<height>...]
+-- Heights at which <key> was written or deleted, oldest first.
+local function vkv_heights(keys, args)
+    local key = keys[1]
+
+    local res = redis.call('ZRANGE', key, 0, -1, 'WITHSCORES')
+    local heights = {}
+    for i = 2, #res, 2 do
+        heights[#heights + 1] = tonumber(res[i])
+    end
+
+    return heights
+end
+
+-- vkv_mget <key>... <height> -> [<value>...]
+-- vkv_get of every key at <height>, in order.
+local function vkv_mget(keys, args)
+    local values = {}
+    for i, key in ipairs(keys) do
+        -- false rather than nil, so a missing key does not end the reply
+        values[i] = vkv_get({key}, args) or false
+    end
+
+    return values
+end
+
+-- vkv_scan <prefix> <height> <cursor> <count> -> [<cursor>, [[<key>, <value>]...]]
+-- One SCAN step over the versioned keys starting with <prefix> on this node,
+-- with their values at <height>. Keys without one there are left out. The keys
+-- are not declared, so a cluster client has to send it to one node itself.
+local function vkv_scan(keys, args)
+    local pattern = string.gsub(args[1], '[%*%?%[%]\\]', '\\%0') .. '*'
+    local height = args[2]
+
+    local res = redis.call('SCAN', args[3], 'MATCH', pattern, 'COUNT', args[4], 'TYPE', 'zset')
+    local entries = {}
+    for _, key in ipairs(res[2]) do
+        local value = vkv_get({key}, {height})
+        if value then
+            entries[#entries + 1] = {key, value}
+        end
+    end
+
+    return {res[1], entries}
+end
+
+-- vkv_version -> <version>
+local function vkv_version(keys, args)
+    return version
 end
 
 redis.register_function("vkv_set", vkv_set)
 redis.register_function("vkv_get", vkv_get)
 redis.register_function("vkv_del", vkv_del)
+redis.register_function("vkv_unset", vkv_unset)
+redis.register_function("vkv_compact", vkv_compact)
+redis.register_function("vkv_rollback", vkv_rollback)
+redis.register_function("vkv_history", vkv_history)
+redis.register_function("vkv_heights", vkv_heights)
+redis.register_function("vkv_mget", vkv_mget)
+redis.register_function{function_name = "vkv_scan", callback = vkv_scan, flags = {"no-writes"}}
+redis.register_function{function_name = "vkv_version", callback = vkv_version, flags = {"no-writes"}}
 
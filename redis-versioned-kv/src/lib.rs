@@ -1,4 +1,199 @@
-use redis::{ConnectionLike, FromRedisValue, RedisResult, ToRedisArgs};
+use redis::{Cmd, ConnectionLike, ErrorKind, FromRedisValue, RedisError, RedisResult, ToRedisArgs};
+
+mod map;
+pub use map::*;
+
+mod scan;
+pub use scan::*;
+
+mod script;
+pub use script::*;
+
+#[cfg(feature = "aio")]
+use redis::RedisFuture;
+
+/// Key holding the version of `key` written at `height`, as laid out by the
+/// Lua functions. The versions of `key` are the members of the sorted set
+/// under `key`, scored by height.
+pub fn vkv_value_key(key: &str, height: u32) -> String {
+    format!("{}:{:08X}", key, height)
+}
+
+/// The `versioned_kv` function library, as loaded by `ensure_loaded`.
+pub const LIBRARY: &str = include_str!("../lua/versioned-kv.lua");
+
+/// Version of `LIBRARY`, as returned by its `vkv_version` function.
+pub const LIBRARY_VERSION: u32 = 4;
+
+fn vkv_version_cmd() -> Cmd {
+    let mut cmd = redis::cmd("FCALL");
+    cmd.arg("vkv_version").arg(0);
+    cmd
+}
+
+fn library_load_cmd() -> Cmd {
+    let mut cmd = redis::cmd("FUNCTION");
+    cmd.arg("LOAD").arg("REPLACE").arg(LIBRARY);
+    cmd
+}
+
+/// Whether the reply to `vkv_version` is `LIBRARY_VERSION`. A library that is
+/// missing, or older than `vkv_version`, fails the call and is outdated too.
+/// A newer one was loaded by a newer client and is not replaced.
+fn is_current(reply: RedisResult<u32>) -> RedisResult<bool> {
+    match reply {
+        Ok(LIBRARY_VERSION) => Ok(true),
+        Ok(version) if version > LIBRARY_VERSION => Err(version_error(version)),
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == ErrorKind::ResponseError => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn version_error(version: u32) -> RedisError {
+    RedisError::from((
+        ErrorKind::ClientError,
+        "unsupported versioned_kv library",
+        format!("loaded version {}, expected {}", version, LIBRARY_VERSION),
+    ))
+}
+
+/// Whether a failed `FUNCTION LOAD` was refused by the server, which then
+/// lacks function support, e.g. before Redis 7 or on a managed store.
+fn is_refused(e: &RedisError) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::ResponseError | ErrorKind::ExtensionError
+    )
+}
+
+/// Loads `LIBRARY` unless the server already has this version of it. Every
+/// node a client writes to needs it, on a cluster that is every master. A
+/// server without function support gets it as a script, selecting
+/// `Mode::Scripts` for the whole process.
+pub fn ensure_loaded<C: ConnectionLike>(conn: &mut C) -> RedisResult<()> {
+    if mode() == Mode::Scripts {
+        return load_script(conn);
+    }
+    if is_current(vkv_version_cmd().query(conn))? {
+        return Ok(());
+    }
+    match library_load_cmd().query::<()>(conn) {
+        Err(e) if is_refused(&e) => return load_script(conn),
+        reply => reply?,
+    }
+    match vkv_version_cmd().query(conn)? {
+        LIBRARY_VERSION => Ok(()),
+        version => Err(version_error(version)),
+    }
+}
+
+/// `ensure_loaded` over an async connection.
+#[cfg(feature = "aio")]
+pub async fn ensure_loaded_async<C: redis::aio::ConnectionLike>(conn: &mut C) -> RedisResult<()> {
+    if mode() == Mode::Scripts {
+        return load_script_async(conn).await;
+    }
+    if is_current(vkv_version_cmd().query_async(conn).await)? {
+        return Ok(());
+    }
+    match library_load_cmd().query_async::<_, ()>(conn).await {
+        Err(e) if is_refused(&e) => return load_script_async(conn).await,
+        reply => reply?,
+    }
+    match vkv_version_cmd().query_async(conn).await? {
+        LIBRARY_VERSION => Ok(()),
+        version => Err(version_error(version)),
+    }
+}
+
+/// `vkv_set`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_set_cmd<K, V>(key: K, height: u32, value: V) -> Cmd
+where
+    K: ToRedisArgs,
+    V: ToRedisArgs,
+{
+    vkv_cmd("vkv_set", 1, key, (height, value))
+}
+
+/// `vkv_get`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_get_cmd<K>(key: K, height: u32) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_get", 1, key, height)
+}
+
+/// `vkv_del`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_del_cmd<K>(key: K, height: u32) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_del", 1, key, height)
+}
+
+/// `vkv_unset`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_unset_cmd<K>(key: K, height: u32) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_unset", 1, key, height)
+}
+
+/// `vkv_compact`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_compact_cmd<K>(key: K, height: u32) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_compact", 1, key, height)
+}
+
+/// `vkv_rollback`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_rollback_cmd<K>(key: K, height: u32) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_rollback", 1, key, height)
+}
+
+/// `vkv_history`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_history_cmd<K>(key: K, from: u32, to: u32) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_history", 1, key, (from, to))
+}
+
+/// `vkv_heights`, for callers that batch it into a pipeline or transaction.
+pub fn vkv_heights_cmd<K>(key: K) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_heights", 1, key, &[] as &[u32])
+}
+
+/// `vkv_mget`, for callers that batch it into a pipeline or transaction.
+/// On a cluster, `keys` have to share a hash slot.
+pub fn vkv_mget_cmd<K>(keys: &[K], height: u32) -> Cmd
+where
+    K: ToRedisArgs,
+{
+    vkv_cmd("vkv_mget", keys.len(), keys, height)
+}
+
+/// `vkv_scan` step from `cursor`, for callers that drive the cursor themselves.
+/// Its reply reads into a `(u64, Vec<(String, RV)>)`, the next cursor, zero
+/// once done, and the keys found with their values. The command declares no
+/// keys, so on a cluster it has to go to one node, as the cursor is that
+/// node's.
+pub fn vkv_scan_cmd(prefix: &str, height: u32, cursor: u64, count: usize) -> Cmd {
+    vkv_cmd(
+        "vkv_scan",
+        0,
+        &[] as &[u32],
+        (prefix, height, cursor, count),
+    )
+}
 
 pub trait VersionedKVCommand: ConnectionLike + Sized {
     fn vkv_set<K, V>(&mut self, key: K, height: u32, value: V) -> RedisResult<()>
@@ -6,13 +201,7 @@ pub trait VersionedKVCommand: ConnectionLike + Sized {
         K: ToRedisArgs,
         V: ToRedisArgs,
     {
-        redis::cmd("FCALL")
-            .arg("vkv_set")
-            .arg(1)
-            .arg(key)
-            .arg(height)
-            .arg(value)
-            .query(self)?;
+        query_vkv::<_, ()>(self, vkv_set_cmd(key, height, value))?;
 
         Ok(())
     }
@@ -22,38 +211,199 @@ pub trait VersionedKVCommand: ConnectionLike + Sized {
         K: ToRedisArgs,
         RV: FromRedisValue,
     {
-        redis::cmd("FCALL")
-            .arg("vkv_get")
-            .arg(1)
-            .arg(key)
-            .arg(height)
-            .query(self)
+        query_vkv(self, vkv_get_cmd(key, height))
     }
 
+    /// Deletes `key` from `height` on. The delete is a tombstone version, so
+    /// earlier heights keep their values until compacted.
     fn vkv_del<K>(&mut self, key: K, height: u32) -> RedisResult<()>
     where
         K: ToRedisArgs,
     {
-        redis::cmd("FCALL")
-            .arg("vkv_del")
-            .arg(1)
-            .arg(key)
-            .arg(height)
-            .query(self)?;
+        query_vkv::<_, ()>(self, vkv_del_cmd(key, height))?;
 
         Ok(())
     }
+
+    /// Drops the version of `key` written at exactly `height`, a value or a
+    /// tombstone, so the one below it is read from `height` on again. Returns
+    /// how many versions were removed, 0 or 1.
+    fn vkv_unset<K>(&mut self, key: K, height: u32) -> RedisResult<u64>
+    where
+        K: ToRedisArgs,
+    {
+        query_vkv(self, vkv_unset_cmd(key, height))
+    }
+
+    /// Drops the versions of `key` that are shadowed at `height`, keeping the
+    /// newest one at or below it unless it is a tombstone. Returns how many
+    /// versions were removed.
+    fn vkv_compact<K>(&mut self, key: K, height: u32) -> RedisResult<u64>
+    where
+        K: ToRedisArgs,
+    {
+        query_vkv(self, vkv_compact_cmd(key, height))
+    }
+
+    /// Drops the versions of `key` above `height`. Returns how many versions
+    /// were removed.
+    fn vkv_rollback<K>(&mut self, key: K, height: u32) -> RedisResult<u64>
+    where
+        K: ToRedisArgs,
+    {
+        query_vkv(self, vkv_rollback_cmd(key, height))
+    }
+
+    /// Versions of `key` written at heights `from..=to`, oldest first, as
+    /// `(height, value)` pairs, e.g. into a `Vec<(u32, Option<String>)>`
+    /// where deletes are `None`.
+    fn vkv_history<K, RV>(&mut self, key: K, from: u32, to: u32) -> RedisResult<RV>
+    where
+        K: ToRedisArgs,
+        RV: FromRedisValue,
+    {
+        query_vkv(self, vkv_history_cmd(key, from, to))
+    }
+
+    /// Heights at which `key` was written or deleted, oldest first.
+    fn vkv_heights<K>(&mut self, key: K) -> RedisResult<Vec<u32>>
+    where
+        K: ToRedisArgs,
+    {
+        query_vkv(self, vkv_heights_cmd(key))
+    }
+
+    /// `vkv_get` of every key in `keys` in one round trip, e.g. into a
+    /// `Vec<Option<String>>`.
+    fn vkv_mget<K, RV>(&mut self, keys: &[K], height: u32) -> RedisResult<RV>
+    where
+        K: ToRedisArgs,
+        RV: FromRedisValue,
+    {
+        query_vkv(self, vkv_mget_cmd(keys, height))
+    }
 }
 
 impl<T: ConnectionLike + Sized> VersionedKVCommand for T {}
 
-pub trait AsyncVersionedKVCommand: ConnectionLike {}
+/// `VersionedKVCommand` over an async connection, e.g. a
+/// `MultiplexedConnection` or a `ConnectionManager`.
+#[cfg(feature = "aio")]
+pub trait AsyncVersionedKVCommand: redis::aio::ConnectionLike + Send + Sized {
+    fn vkv_set<'a, K, V>(&'a mut self, key: K, height: u32, value: V) -> RedisFuture<'a, ()>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+        V: ToRedisArgs + Send + Sync + 'a,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_set_cmd(key, height, value)).await })
+    }
+
+    fn vkv_get<'a, K, RV>(&'a mut self, key: K, height: u32) -> RedisFuture<'a, RV>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+        RV: FromRedisValue,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_get_cmd(key, height)).await })
+    }
+
+    fn vkv_del<'a, K>(&'a mut self, key: K, height: u32) -> RedisFuture<'a, ()>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_del_cmd(key, height)).await })
+    }
+
+    /// See `VersionedKVCommand::vkv_unset`.
+    fn vkv_unset<'a, K>(&'a mut self, key: K, height: u32) -> RedisFuture<'a, u64>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_unset_cmd(key, height)).await })
+    }
+
+    /// See `VersionedKVCommand::vkv_compact`.
+    fn vkv_compact<'a, K>(&'a mut self, key: K, height: u32) -> RedisFuture<'a, u64>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_compact_cmd(key, height)).await })
+    }
+
+    /// See `VersionedKVCommand::vkv_rollback`.
+    fn vkv_rollback<'a, K>(&'a mut self, key: K, height: u32) -> RedisFuture<'a, u64>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_rollback_cmd(key, height)).await })
+    }
+
+    /// See `VersionedKVCommand::vkv_history`.
+    fn vkv_history<'a, K, RV>(&'a mut self, key: K, from: u32, to: u32) -> RedisFuture<'a, RV>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+        RV: FromRedisValue,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_history_cmd(key, from, to)).await })
+    }
+
+    /// See `VersionedKVCommand::vkv_heights`.
+    fn vkv_heights<'a, K>(&'a mut self, key: K) -> RedisFuture<'a, Vec<u32>>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_heights_cmd(key)).await })
+    }
+
+    /// See `VersionedKVCommand::vkv_mget`.
+    fn vkv_mget<'a, K, RV>(&'a mut self, keys: &'a [K], height: u32) -> RedisFuture<'a, RV>
+    where
+        K: ToRedisArgs + Send + Sync + 'a,
+        RV: FromRedisValue,
+    {
+        Box::pin(async move { query_vkv_async(self, vkv_mget_cmd(keys, height)).await })
+    }
+
+    /// Runs `pipe`, a pipeline of `vkv_*` commands, like `with_script_reload`.
+    fn vkv_pipeline<'a, RV>(&'a mut self, pipe: &'a redis::Pipeline) -> RedisFuture<'a, RV>
+    where
+        RV: FromRedisValue,
+    {
+        Box::pin(async move {
+            match pipe.query_async(self).await {
+                Err(e) if is_script_lost(&e) => {}
+                reply => return reply,
+            }
+            load_script_async(self).await?;
+            pipe.query_async(self).await
+        })
+    }
+}
+
+#[cfg(feature = "aio")]
+impl<T: redis::aio::ConnectionLike + Send + Sized> AsyncVersionedKVCommand for T {}
 
 #[cfg(test)]
 mod tests {
     use redis::Client;
 
-    use crate::VersionedKVCommand;
+    use crate::{VersionedKVCommand, VersionedKVScan};
+
+    #[test]
+    fn test_library_version() {
+        assert!(crate::LIBRARY.contains(&format!("local version = {}\n", crate::LIBRARY_VERSION)));
+    }
+
+    #[test]
+    fn test_ensure_loaded() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        crate::ensure_loaded(&mut con).unwrap();
+        // already loaded, a no-op
+        crate::ensure_loaded(&mut con).unwrap();
+        let version: u32 = crate::vkv_version_cmd().query(&mut con).unwrap();
+        assert_eq!(version, crate::LIBRARY_VERSION);
+    }
 
     #[test]
     fn test_get_set() {
@@ -90,4 +440,174 @@ mod tests {
             assert_eq!(r, None);
         }
     }
+
+    #[test]
+    fn test_compact() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        let key = "0x23456";
+
+        con.vkv_set(key, 2, "a").unwrap();
+        con.vkv_set(key, 5, "b").unwrap();
+        con.vkv_set(key, 8, "c").unwrap();
+
+        assert_eq!(con.vkv_compact(key, 6).unwrap(), 1);
+
+        let r: Option<String> = con.vkv_get(key, 3).unwrap();
+        assert_eq!(r, None);
+        let r: Option<String> = con.vkv_get(key, 6).unwrap();
+        assert_eq!(r, Some(String::from("b")));
+        let r: Option<String> = con.vkv_get(key, 9).unwrap();
+        assert_eq!(r, Some(String::from("c")));
+    }
+
+    #[test]
+    fn test_rollback() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        let key = "0x34567";
+
+        con.vkv_set(key, 2, "a").unwrap();
+        con.vkv_set(key, 5, "b").unwrap();
+        con.vkv_set(key, 8, "c").unwrap();
+
+        assert_eq!(con.vkv_rollback(key, 5).unwrap(), 1);
+
+        let r: Option<String> = con.vkv_get(key, 3).unwrap();
+        assert_eq!(r, Some(String::from("a")));
+        let r: Option<String> = con.vkv_get(key, 9).unwrap();
+        assert_eq!(r, Some(String::from("b")));
+    }
+
+    #[test]
+    fn test_del_keeps_history() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        let key = "0x3456a";
+
+        con.vkv_set(key, 2, "a").unwrap();
+        con.vkv_del(key, 5).unwrap();
+        con.vkv_set(key, 8, "b").unwrap();
+
+        let r: Option<String> = con.vkv_get(key, 4).unwrap();
+        assert_eq!(r, Some(String::from("a")));
+        let r: Option<String> = con.vkv_get(key, 6).unwrap();
+        assert_eq!(r, None);
+        let r: Option<String> = con.vkv_get(key, 8).unwrap();
+        assert_eq!(r, Some(String::from("b")));
+
+        let r: Vec<(u32, Option<String>)> = con.vkv_history(key, 0, 10).unwrap();
+        assert_eq!(
+            r,
+            vec![
+                (2, Some(String::from("a"))),
+                (5, None),
+                (8, Some(String::from("b")))
+            ]
+        );
+
+        assert_eq!(con.vkv_compact(key, 6).unwrap(), 2);
+        assert_eq!(con.vkv_heights(key).unwrap(), vec![8]);
+        let r: Option<String> = con.vkv_get(key, 4).unwrap();
+        assert_eq!(r, None);
+    }
+
+    #[test]
+    fn test_unset() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        let key = "0x3456b";
+
+        con.vkv_set(key, 2, "a").unwrap();
+        con.vkv_set(key, 5, "b").unwrap();
+        con.vkv_del(key, 8).unwrap();
+
+        assert_eq!(con.vkv_unset(key, 5).unwrap(), 1);
+        assert_eq!(con.vkv_unset(key, 6).unwrap(), 0);
+        let r: Option<String> = con.vkv_get(key, 6).unwrap();
+        assert_eq!(r, Some(String::from("a")));
+
+        // a tombstone is a version too
+        assert_eq!(con.vkv_unset(key, 8).unwrap(), 1);
+        let r: Option<String> = con.vkv_get(key, 9).unwrap();
+        assert_eq!(r, Some(String::from("a")));
+        assert_eq!(con.vkv_heights(key).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn test_history() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        let key = "0x56789";
+
+        con.vkv_set(key, 2, "a").unwrap();
+        con.vkv_set(key, 5, "b").unwrap();
+        con.vkv_set(key, 8, "c").unwrap();
+
+        assert_eq!(con.vkv_heights(key).unwrap(), vec![2, 5, 8]);
+
+        let r: Vec<(u32, String)> = con.vkv_history(key, 3, 8).unwrap();
+        assert_eq!(r, vec![(5, String::from("b")), (8, String::from("c"))]);
+        let r: Vec<(u32, String)> = con.vkv_history(key, 9, 12).unwrap();
+        assert_eq!(r, vec![]);
+    }
+
+    #[test]
+    fn test_mget() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        con.vkv_set("0x67890", 2, "a").unwrap();
+        con.vkv_set("0x67891", 4, "b").unwrap();
+
+        let r: Vec<Option<String>> = con.vkv_mget(&["0x67890", "0x67891", "0x67892"], 3).unwrap();
+        assert_eq!(r, vec![Some(String::from("a")), None, None]);
+        let r: Vec<Option<String>> = con.vkv_mget(&["0x67891", "0x67890"], 4).unwrap();
+        assert_eq!(r, vec![Some(String::from("b")), Some(String::from("a"))]);
+    }
+
+    #[test]
+    fn test_scan() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        con.vkv_set("scan:a", 2, "a").unwrap();
+        con.vkv_set("scan:b", 4, "b").unwrap();
+        con.vkv_set("scan:c", 1, "c").unwrap();
+        con.vkv_del("scan:c", 3).unwrap();
+
+        let mut r: Vec<(String, String)> = con
+            .vkv_scan("scan:", 3, 1)
+            .collect::<redis::RedisResult<_>>()
+            .unwrap();
+        r.sort();
+        r.dedup();
+        assert_eq!(r, vec![(String::from("scan:a"), String::from("a"))]);
+    }
+
+    #[cfg(feature = "aio")]
+    #[tokio::test]
+    async fn test_async_get_set() {
+        use crate::AsyncVersionedKVCommand;
+
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_multiplexed_tokio_connection().await.unwrap();
+
+        let key = "0x45678";
+
+        con.vkv_set(key, 3, "a").await.unwrap();
+        con.vkv_del(key, 6).await.unwrap();
+
+        let r: Option<String> = con.vkv_get(key, 2).await.unwrap();
+        assert_eq!(r, None);
+        let r: Option<String> = con.vkv_get(key, 4).await.unwrap();
+        assert_eq!(r, Some(String::from("a")));
+        let r: Option<String> = con.vkv_get(key, 7).await.unwrap();
+        assert_eq!(r, None);
+    }
 }
@@ -0,0 +1,233 @@
+use {
+    crate::{vkv_del_cmd, vkv_get_cmd, vkv_set_cmd, VersionedKVCommand},
+    redis::{Cmd, ConnectionLike, ErrorKind, RedisError, RedisResult},
+    serde::{de::DeserializeOwned, Serialize},
+    std::{fmt, marker::PhantomData},
+};
+
+/// Key of a `VersionedMap` entry, laid out under the namespace of the map.
+pub trait VersionedKey {
+    fn versioned_key(&self, namespace: &str) -> String;
+}
+
+macro_rules! impl_versioned_key {
+    ($($ty:ty),*) => {
+        $(
+            /// `<namespace>:<key>`.
+            impl VersionedKey for $ty {
+                fn versioned_key(&self, namespace: &str) -> String {
+                    format!("{}:{}", namespace, self)
+                }
+            }
+        )*
+    };
+}
+
+impl_versioned_key!(str, String, u32, u64);
+
+/// Encoding of the values of a `VersionedMap`.
+pub trait Codec<V> {
+    fn encode(&self, value: &V) -> RedisResult<Vec<u8>>;
+    fn decode(&self, bytes: &[u8]) -> RedisResult<V>;
+}
+
+/// Values as JSON, through serde.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+impl<V: Serialize + DeserializeOwned> Codec<V> for JsonCodec {
+    fn encode(&self, value: &V) -> RedisResult<Vec<u8>> {
+        serde_json::to_vec(value).map_err(json_error)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> RedisResult<V> {
+        serde_json::from_slice(bytes).map_err(json_error)
+    }
+}
+
+fn json_error(e: serde_json::Error) -> RedisError {
+    RedisError::from((ErrorKind::TypeError, "invalid json value", e.to_string()))
+}
+
+/// Typed view of the versioned keys under a namespace: keys are laid out by
+/// `VersionedKey` and values encoded by the codec `C`. The map holds no
+/// connection, every operation takes one.
+pub struct VersionedMap<K: ?Sized, V, C = JsonCodec> {
+    namespace: String,
+    codec: C,
+    marker: PhantomData<fn(&K) -> V>,
+}
+
+impl<K: ?Sized, V> VersionedMap<K, V> {
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self::with_codec(namespace, JsonCodec)
+    }
+}
+
+impl<K: ?Sized, V, C> VersionedMap<K, V, C> {
+    pub fn with_codec(namespace: impl Into<String>, codec: C) -> Self {
+        Self {
+            namespace: namespace.into(),
+            codec,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+}
+
+impl<K: ?Sized, V, C: Clone> Clone for VersionedMap<K, V, C> {
+    fn clone(&self) -> Self {
+        Self::with_codec(self.namespace.clone(), self.codec.clone())
+    }
+}
+
+impl<K: ?Sized, V, C: fmt::Debug> fmt::Debug for VersionedMap<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionedMap")
+            .field("namespace", &self.namespace)
+            .field("codec", &self.codec)
+            .finish()
+    }
+}
+
+impl<K, V, C> VersionedMap<K, V, C>
+where
+    K: VersionedKey + ?Sized,
+    C: Codec<V>,
+{
+    /// Redis key holding the versions of `key`.
+    pub fn key(&self, key: &K) -> String {
+        key.versioned_key(&self.namespace)
+    }
+
+    /// Value of `key` at `height`, `None` when it was never written or was
+    /// deleted by then.
+    pub fn get_at<T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key: &K,
+        height: u32,
+    ) -> RedisResult<Option<V>> {
+        let value: Option<Vec<u8>> = conn.vkv_get(self.key(key), height)?;
+        self.decode(value)
+    }
+
+    pub fn set_at<T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key: &K,
+        height: u32,
+        value: &V,
+    ) -> RedisResult<()> {
+        conn.vkv_set(self.key(key), height, self.codec.encode(value)?)
+    }
+
+    pub fn del_at<T: ConnectionLike>(&self, conn: &mut T, key: &K, height: u32) -> RedisResult<()> {
+        conn.vkv_del(self.key(key), height)
+    }
+
+    /// Drops the version of `key` written at exactly `height`, see
+    /// `VersionedKVCommand::vkv_unset`.
+    pub fn unset_at<T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key: &K,
+        height: u32,
+    ) -> RedisResult<()> {
+        conn.vkv_unset(self.key(key), height)?;
+        Ok(())
+    }
+
+    /// Versions of `key` written at heights `from..=to`, oldest first, deletes
+    /// are `None`.
+    pub fn history<T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key: &K,
+        from: u32,
+        to: u32,
+    ) -> RedisResult<Vec<(u32, Option<V>)>> {
+        let history: Vec<(u32, Option<Vec<u8>>)> = conn.vkv_history(self.key(key), from, to)?;
+        history
+            .into_iter()
+            .map(|(height, value)| Ok((height, self.decode(value)?)))
+            .collect()
+    }
+
+    /// Value of the newest version of `key`.
+    pub fn latest<T: ConnectionLike>(&self, conn: &mut T, key: &K) -> RedisResult<Option<V>> {
+        self.get_at(conn, key, u32::MAX)
+    }
+
+    /// `get_at` for a pipeline, its reply is read with `decode`.
+    pub fn get_at_cmd(&self, key: &K, height: u32) -> Cmd {
+        vkv_get_cmd(self.key(key), height)
+    }
+
+    /// `set_at` for a pipeline.
+    pub fn set_at_cmd(&self, key: &K, height: u32, value: &V) -> RedisResult<Cmd> {
+        Ok(vkv_set_cmd(
+            self.key(key),
+            height,
+            self.codec.encode(value)?,
+        ))
+    }
+
+    /// `del_at` for a pipeline.
+    pub fn del_at_cmd(&self, key: &K, height: u32) -> Cmd {
+        vkv_del_cmd(self.key(key), height)
+    }
+
+    pub fn decode(&self, value: Option<Vec<u8>>) -> RedisResult<Option<V>> {
+        value.map(|bytes| self.codec.decode(&bytes)).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{Codec, JsonCodec, VersionedMap},
+        redis::Client,
+    };
+
+    #[test]
+    fn test_key_and_codec() {
+        let map = VersionedMap::<str, u64>::new("evm:counter");
+        assert_eq!(map.key("a"), "evm:counter:a");
+        assert_eq!(
+            VersionedMap::<u32, u64>::new("evm:block").key(&7),
+            "evm:block:7"
+        );
+
+        assert_eq!(JsonCodec.encode(&vec![1u8, 2]).unwrap(), b"[1,2]");
+        assert_eq!(
+            Codec::<Vec<u8>>::decode(&JsonCodec, b"[1,2]").unwrap(),
+            vec![1, 2]
+        );
+        assert!(Codec::<u64>::decode(&JsonCodec, b"\"x\"").is_err());
+    }
+
+    #[test]
+    fn test_map() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        let map = VersionedMap::<str, Vec<u32>>::new("test:map");
+
+        map.set_at(&mut con, "a", 2, &vec![1]).unwrap();
+        map.set_at(&mut con, "a", 5, &vec![1, 2]).unwrap();
+        map.del_at(&mut con, "a", 8).unwrap();
+
+        assert_eq!(map.get_at(&mut con, "a", 1).unwrap(), None);
+        assert_eq!(map.get_at(&mut con, "a", 4).unwrap(), Some(vec![1]));
+        assert_eq!(map.get_at(&mut con, "a", 6).unwrap(), Some(vec![1, 2]));
+        assert_eq!(map.latest(&mut con, "a").unwrap(), None);
+        assert_eq!(
+            map.history(&mut con, "a", 0, 10).unwrap(),
+            vec![(2, Some(vec![1])), (5, Some(vec![1, 2])), (8, None)]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use {
+    crate::{query_vkv, vkv_scan_cmd},
+    redis::{Connection, ConnectionLike, FromRedisValue, RedisResult},
+    std::vec,
+};
+
+/// Iterator of `VersionedKVScan::vkv_scan`, running one `vkv_scan` step
+/// whenever the keys of the previous one are used up.
+pub struct VkvScan<'a, C, RV> {
+    conn: &'a mut C,
+    prefix: String,
+    height: u32,
+    count: usize,
+    /// Cursor of the next step, `None` once SCAN is done or failed.
+    cursor: Option<u64>,
+    entries: vec::IntoIter<(String, RV)>,
+}
+
+/// `vkv_scan` over a connection to a single node. A cluster connection would
+/// send every step to whichever node it picks, so there the scan goes over a
+/// connection to each master in turn.
+pub trait VersionedKVScan: ConnectionLike + Sized {
+    /// Keys starting with `prefix` that have a value at `height`, with it,
+    /// fetched `count` keys per SCAN step. A key may be returned more than
+    /// once, as with SCAN.
+    fn vkv_scan<RV>(&mut self, prefix: &str, height: u32, count: usize) -> VkvScan<'_, Self, RV>
+    where
+        RV: FromRedisValue,
+    {
+        VkvScan::new(self, prefix, height, count)
+    }
+}
+
+impl VersionedKVScan for Connection {}
+
+impl<'a, C, RV> VkvScan<'a, C, RV> {
+    fn new(conn: &'a mut C, prefix: &str, height: u32, count: usize) -> Self {
+        Self {
+            conn,
+            prefix: prefix.to_string(),
+            height,
+            count,
+            cursor: Some(0),
+            entries: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<C: ConnectionLike, RV: FromRedisValue> Iterator for VkvScan<'_, C, RV> {
+    type Item = RedisResult<(String, RV)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            let cursor = self.cursor.take()?;
+            let cmd = vkv_scan_cmd(&self.prefix, self.height, cursor, self.count);
+            match query_vkv::<_, (u64, Vec<(String, RV)>)>(self.conn, cmd) {
+                Ok((next, entries)) => {
+                    self.cursor = (next != 0).then_some(next);
+                    self.entries = entries.into_iter();
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use {
+    crate::LIBRARY,
+    redis::{Cmd, ConnectionLike, ErrorKind, FromRedisValue, RedisResult, ToRedisArgs},
+    std::sync::OnceLock,
+};
+
+/// How the `vkv_*` commands run on the server, chosen by `ensure_loaded`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `FCALL` into the `versioned_kv` function library, Redis 7 and up.
+    Functions,
+    /// `EVALSHA` of the same functions loaded as one script, for servers
+    /// without function support.
+    Scripts,
+}
+
+/// Hash of `script()`, set once `Mode::Scripts` is selected.
+static SCRIPT_SHA: OnceLock<String> = OnceLock::new();
+
+pub fn mode() -> Mode {
+    if SCRIPT_SHA.get().is_some() {
+        Mode::Scripts
+    } else {
+        Mode::Functions
+    }
+}
+
+/// `LIBRARY` as a plain script: the functions are registered into a table
+/// instead, and the one named by the first argument is called with the rest.
+pub(crate) fn script() -> String {
+    let body = LIBRARY
+        .split_once('\n')
+        .map_or(LIBRARY, |(_shebang, body)| body)
+        .replace("redis.register_function", "register");
+    format!(
+        "local functions = {{}}
+local function register(name, callback)
+    if type(name) == \"table\" then
+        name, callback = name.function_name, name.callback
+    end
+    functions[name] = callback
+end
+{}
+return functions[ARGV[1]](KEYS, {{unpack(ARGV, 2)}})
+",
+        body
+    )
+}
+
+fn script_load_cmd() -> Cmd {
+    let mut cmd = redis::cmd("SCRIPT");
+    cmd.arg("LOAD").arg(script());
+    cmd
+}
+
+/// Loads `script()` and selects `Mode::Scripts`.
+pub(crate) fn load_script<C: ConnectionLike>(conn: &mut C) -> RedisResult<()> {
+    let sha: String = script_load_cmd().query(conn)?;
+    let _ = SCRIPT_SHA.set(sha);
+    Ok(())
+}
+
+/// `load_script` over an async connection.
+#[cfg(feature = "aio")]
+pub(crate) async fn load_script_async<C: redis::aio::ConnectionLike>(
+    conn: &mut C,
+) -> RedisResult<()> {
+    let sha: String = script_load_cmd().query_async(conn).await?;
+    let _ = SCRIPT_SHA.set(sha);
+    Ok(())
+}
+
+/// Whether `e` means the server lost the script of `Mode::Scripts`, on a
+/// restart or a `SCRIPT FLUSH`.
+pub(crate) fn is_script_lost(e: &redis::RedisError) -> bool {
+    e.kind() == ErrorKind::NoScriptError && mode() == Mode::Scripts
+}
+
+/// Runs `query`, and runs it again after reloading the script if the server
+/// lost it. Whatever `query` sends, e.g. a pipeline of `vkv_*` commands, may
+/// be partly applied before the error and has to be safe to repeat.
+pub fn with_script_reload<C, T, F>(conn: &mut C, query: F) -> RedisResult<T>
+where
+    C: ConnectionLike,
+    F: Fn(&mut C) -> RedisResult<T>,
+{
+    match query(conn) {
+        Err(e) if is_script_lost(&e) => {
+            load_script(conn)?;
+            query(conn)
+        }
+        reply => reply,
+    }
+}
+
+/// `cmd` through `with_script_reload`.
+pub(crate) fn query_vkv<C: ConnectionLike, T: FromRedisValue>(
+    conn: &mut C,
+    cmd: Cmd,
+) -> RedisResult<T> {
+    with_script_reload(conn, |conn| cmd.query(conn))
+}
+
+/// `query_vkv` over an async connection.
+#[cfg(feature = "aio")]
+pub(crate) async fn query_vkv_async<C, T>(conn: &mut C, cmd: Cmd) -> RedisResult<T>
+where
+    C: redis::aio::ConnectionLike,
+    T: FromRedisValue,
+{
+    match cmd.query_async(conn).await {
+        Err(e) if is_script_lost(&e) => {}
+        // returned right away, the reply is not kept across an await
+        reply => return reply,
+    }
+    load_script_async(conn).await?;
+    cmd.query_async(conn).await
+}
+
+/// `FCALL <function>` of `keys` and `args`, or the same call through
+/// `EVALSHA` in `Mode::Scripts`.
+pub(crate) fn vkv_cmd<K, A>(function: &str, numkeys: usize, keys: K, args: A) -> Cmd
+where
+    K: ToRedisArgs,
+    A: ToRedisArgs,
+{
+    match SCRIPT_SHA.get() {
+        Some(sha) => script_cmd(sha, function, numkeys, keys, args),
+        None => {
+            let mut cmd = redis::cmd("FCALL");
+            cmd.arg(function).arg(numkeys).arg(keys).arg(args);
+            cmd
+        }
+    }
+}
+
+fn script_cmd<K, A>(sha: &str, function: &str, numkeys: usize, keys: K, args: A) -> Cmd
+where
+    K: ToRedisArgs,
+    A: ToRedisArgs,
+{
+    let mut cmd = redis::cmd("EVALSHA");
+    cmd.arg(sha).arg(numkeys).arg(keys).arg(function).arg(args);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{script, script_cmd, script_load_cmd},
+        redis::Client,
+    };
+
+    #[test]
+    fn test_script() {
+        let script = script();
+        assert!(!script.contains("redis.register_function"));
+        assert!(!script.contains("#!lua"));
+        assert!(script.contains("register(\"vkv_get\", vkv_get)"));
+    }
+
+    #[test]
+    fn test_script_mode() {
+        let cli = Client::open("redis://127.0.0.1/").unwrap();
+        let mut con = cli.get_connection().unwrap();
+
+        let sha: String = script_load_cmd().query(&mut con).unwrap();
+        let key = "test:script";
+        script_cmd(&sha, "vkv_set", 1, key, (3, "a"))
+            .query::<()>(&mut con)
+            .unwrap();
+        script_cmd(&sha, "vkv_del", 1, key, 6)
+            .query::<()>(&mut con)
+            .unwrap();
+
+        let get = |height: u32| -> Option<String> {
+            script_cmd(&sha, "vkv_get", 1, key, height)
+                .query(&mut cli.get_connection().unwrap())
+                .unwrap()
+        };
+        assert_eq!(get(2), None);
+        assert_eq!(get(4), Some("a".to_string()));
+        assert_eq!(get(7), None);
+
+        let heights: Vec<u32> = script_cmd(&sha, "vkv_heights", 1, key, &[] as &[u32])
+            .query(&mut con)
+            .unwrap();
+        assert_eq!(heights, vec![3, 6]);
+    }
+}
@@ -0,0 +1,47 @@
+//! Rewrites the per-account keys of a Redis store in another layout, see
+//! `KeySchema`. The exporter and web3-service have to be stopped meanwhile.
+//!
+//! `migrate-key-schema <storage_url> <flat|hash_tagged>`
+
+use {
+    evm_exporter::{keys::KeySchema, ConnectionType, PREFIX},
+    ruc::*,
+};
+
+#[cfg(feature = "redis")]
+use evm_exporter::RedisSetter;
+
+#[cfg(feature = "redis-cluster")]
+use evm_exporter::RedisClusterSetter;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (url, schema) = match (args.next(), args.next()) {
+        (Some(url), Some(schema)) => (url, schema),
+        _ => {
+            eprintln!("usage: migrate-key-schema <storage_url> <flat|hash_tagged>");
+            std::process::exit(2);
+        }
+    };
+    let schema = pnk!(schema.parse::<KeySchema>());
+
+    let moved = match pnk!(ConnectionType::from_url(&url)) {
+        #[cfg(feature = "redis")]
+        ConnectionType::Redis(url) => {
+            let setter = RedisSetter::new(ConnectionType::Redis(url), PREFIX.to_string());
+            pnk!(setter.migrate_key_schema(schema))
+        }
+        #[cfg(feature = "redis-cluster")]
+        ConnectionType::RedisCluster(urls) => {
+            let setter =
+                RedisClusterSetter::new(ConnectionType::RedisCluster(urls), PREFIX.to_string());
+            pnk!(setter.migrate_key_schema(schema))
+        }
+        #[allow(unreachable_patterns)]
+        _ => {
+            eprintln!("{} is not a Redis storage url", url);
+            std::process::exit(2);
+        }
+    };
+    println!("moved {} keys, key schema is now {:?}", moved, schema);
+}
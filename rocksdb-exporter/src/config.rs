@@ -8,9 +8,27 @@ use {
 pub struct Config {
     pub state_db_path: String,
     pub history_db_path: String,
-    pub redis_url: Vec<String>,
     pub clear: bool,
-    pub postgres_uri: String,
+    pub storage_url: String,
+    /// Number of heights below the exported one whose history is kept.
+    /// Unset keeps everything.
+    #[serde(default)]
+    pub retention: Option<u32>,
+    /// Prune once every `prune_interval` exported heights.
+    #[serde(default = "default_prune_interval")]
+    pub prune_interval: u32,
+    /// Record the state diff of every exported height, which reads the
+    /// values it replaces first.
+    #[serde(default = "default_state_diffs")]
+    pub state_diffs: bool,
+}
+
+fn default_prune_interval() -> u32 {
+    1000
+}
+
+fn default_state_diffs() -> bool {
+    true
 }
 
 impl Config {
@@ -1,18 +1,9 @@
 mod config;
 mod evm_rocksdb_storage;
 
-#[cfg(feature = "redis-cluster")]
-use evm_exporter::{RedisClusterGetter, RedisClusterSetter, PREFIX};
-
-#[cfg(feature = "redis")]
-use evm_exporter::{RedisGetter, RedisSetter, PREFIX};
-
-#[cfg(feature = "postgres")]
-use evm_exporter::{PgGetter, PgSetter};
-
 use {
     config::Config,
-    evm_exporter::{ConnectionType, Getter, Setter},
+    evm_exporter::{open_getter, open_setter, Getter, Setter, PREFIX},
     evm_rocksdb_storage::{
         evm_rocksdb::RocksDB, get_account_info, get_block_info, get_current_height,
     },
@@ -28,42 +19,29 @@ fn main() {
     let statedb = Arc::new(pnk!(RocksDB::open(config.state_db_path.as_str())));
     let hisdb = Arc::new(pnk!(RocksDB::open(config.history_db_path.as_str())));
 
-    #[cfg(feature = "redis-cluster")]
-    let setter: Arc<dyn Setter> = Arc::new(RedisClusterSetter::new(
-        ConnectionType::RedisCluster(config.redis_url.clone()),
-        PREFIX.to_string(),
-    ));
-    #[cfg(feature = "redis-cluster")]
-    let getter: Arc<dyn Getter> = Arc::new(RedisClusterGetter::new(
-        ConnectionType::RedisCluster(config.redis_url.clone()),
-        PREFIX.to_string(),
-    ));
-    #[cfg(feature = "redis")]
-    let setter: Arc<dyn Setter> = Arc::new(RedisSetter::new(
-        ConnectionType::Redis(config.redis_url[0].clone()),
-        PREFIX.to_string(),
-    ));
-    #[cfg(feature = "redis")]
-    let getter: Arc<dyn Getter> = Arc::new(RedisGetter::new(
-        ConnectionType::Redis(config.redis_url[0].clone()),
-        PREFIX.to_string(),
-    ));
-    #[cfg(feature = "postgres")]
-    let setter: Arc<dyn Setter> = Arc::new(PgSetter::new(
-        ConnectionType::Postgres(config.postgres_uri.clone()),
-        String::new(),
-    ));
-    #[cfg(feature = "postgres")]
-    let getter: Arc<dyn Getter> = Arc::new(PgGetter::new(
-        ConnectionType::Postgres(config.postgres_uri.clone()),
-        String::new(),
-    ));
+    let setter = pnk!(open_setter(&config.storage_url, PREFIX));
+    let getter = pnk!(open_getter(&config.storage_url, PREFIX));
 
     let current_height = pnk!(get_current_height(&hisdb));
 
+    let rollback_to = rollback_to_arg();
+    if config.clear && rollback_to.is_some() {
+        panic!("--rollback-to cannot be used with clear set in the config");
+    }
+
     let mut height = if config.clear {
         pnk!(setter.clear());
         U256::zero()
+    } else if let Some(rollback_height) = rollback_to {
+        let latest = pnk!(getter.latest_height());
+        if rollback_height > latest {
+            panic!(
+                "cannot roll back to {}, above the latest height {}",
+                rollback_height, latest
+            );
+        }
+        pnk!(setter.rollback_to(rollback_height));
+        U256::from(rollback_height)
     } else {
         U256::from(pnk!(getter.latest_height()))
     };
@@ -75,13 +53,16 @@ fn main() {
         if Ordering::Greater == height.cmp(&current_height) {
             break;
         }
+        let h = height.as_u32();
+        let mut writer = setter.begin_block(h);
+
         let info = pnk!(get_block_info(height, &hisdb));
         if let Some((block, receipts, statuses)) = info {
             let receipts = receipts
                 .into_iter()
                 .map(ethereum::ReceiptAny::Frontier)
                 .collect::<Vec<_>>();
-            pnk!(setter.set_block_info(block.into(), receipts, statuses));
+            writer.set_block_info(block.into(), receipts, statuses);
         } else {
             println!("jump over height:{:?}", height);
             continue;
@@ -96,25 +77,50 @@ fn main() {
             },
         ));
 
-        let h = height.as_u32();
-        for (address, (nonce, balance)) in accountstores {
-            pnk!(setter.set_balance(h, address, balance));
-            pnk!(setter.set_nonce(h, address, nonce));
-        }
-        for (address, code) in codes {
-            pnk!(setter.set_byte_code(h, address, code));
-        }
-        for ((address, index), value) in accounts {
-            pnk!(setter.set_state(h, address, index, value));
-        }
+        let (balances, nonces) = accountstores
+            .into_iter()
+            .map(|(address, (nonce, balance))| ((address, balance), (address, nonce)))
+            .unzip();
+        writer.set_balances(balances);
+        writer.set_nonces(nonces);
+        writer.set_byte_codes(codes);
+        writer.set_states(
+            accounts
+                .into_iter()
+                .map(|((address, index), value)| (address, index, value))
+                .collect(),
+        );
 
         for ((owner, spender), value) in allowances {
-            pnk!(setter.set_allowances(h, owner, spender, value));
+            writer.set_allowances(owner, spender, value);
         }
-        pnk!(setter.set_total_issuance(h, total_issuance));
+        writer.set_total_issuance(total_issuance);
+        if config.state_diffs {
+            pnk!(writer.record_state_diff(&*getter));
+        }
+
+        pnk!(writer.commit());
 
-        pnk!(setter.set_height(height.as_u32()));
+        if let Some(retention) = config.retention {
+            if h > retention && 0 == h % config.prune_interval.max(1) {
+                pnk!(setter.prune_below(h - retention));
+                println!("pruned history below height:{}", h - retention);
+            }
+        }
 
         println!("complete height:{:?}", height);
     }
 }
+
+/// The height given as `--rollback-to <height>`. It is a flag rather than a
+/// config entry so a restart does not roll back again.
+fn rollback_to_arg() -> Option<u32> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--rollback-to" {
+            let height = args.next().and_then(|height| height.parse().ok());
+            return Some(height.expect("--rollback-to needs a height"));
+        }
+    }
+    None
+}
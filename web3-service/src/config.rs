@@ -8,11 +8,14 @@ use {
 pub struct Config {
     pub http_port: u64,
     pub ws_port: u64,
-    pub redis_url: Vec<String>,
     pub tendermint_url: String,
     pub chain_id: u32,
     pub gas_price: u64,
-    pub postgres_uri: String,
+    pub storage_url: String,
+    /// Entries kept per cached data kind, see `evm_exporter::CachingGetter`.
+    /// Unset reads everything from the store.
+    #[serde(default)]
+    pub cache_capacity: Option<usize>,
 }
 
 impl Config {
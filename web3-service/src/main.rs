@@ -6,18 +6,9 @@ mod rpc;
 mod utils;
 mod vm;
 
-#[cfg(feature = "redis")]
-use evm_exporter::{RedisGetter, PREFIX};
-
-#[cfg(feature = "redis-cluster")]
-use evm_exporter::{RedisClusterGetter, PREFIX};
-
-#[cfg(feature = "postgres")]
-use evm_exporter::PgGetter;
-
 use {
     config::Config,
-    evm_exporter::{ConnectionType, Getter},
+    evm_exporter::{open_getter, CachingGetter, Getter, PREFIX},
     jsonrpc_core::MetaIoHandler,
     jsonrpc_http_server::DomainsValidation,
     jsonrpc_pubsub::PubSubHandler,
@@ -35,8 +26,10 @@ use {
     ruc::*,
     std::{
         net::SocketAddr,
+        num::NonZeroUsize,
         sync::Arc,
         thread::{self, available_parallelism},
+        time::Duration,
     },
     tendermint_rpc::HttpClient,
     web3_rpc_core::{EthApi, EthFilterApi, EthPubSubApi, NetApi, Web3Api},
@@ -50,23 +43,20 @@ fn main() {
     let http = format!("0.0.0.0:{}", config.http_port);
     let ws = format!("0.0.0.0:{}", config.ws_port);
 
-    #[cfg(feature = "redis")]
-    let getter: Arc<dyn Getter + Sync + Send> = Arc::new(RedisGetter::new(
-        ConnectionType::Redis(config.redis_url[0].clone()),
-        PREFIX.to_string(),
-    ));
-
-    #[cfg(feature = "redis-cluster")]
-    let getter: Arc<dyn Getter + Sync + Send> = Arc::new(RedisGetter::new(
-        ConnectionType::RedisCluster(config.redis_url.clone()),
-        PREFIX.to_string(),
-    ));
-
-    #[cfg(feature = "postgres")]
-    let getter: Arc<dyn Getter + Sync + Send> = Arc::new(PgGetter::new(
-        ConnectionType::Postgres(config.postgres_uri),
-        String::new(),
-    ));
+    let getter = pnk!(open_getter(&config.storage_url, PREFIX));
+    let getter: Arc<dyn Getter + Send + Sync> =
+        match config.cache_capacity.and_then(NonZeroUsize::new) {
+            Some(capacity) => {
+                let caching = Arc::new(CachingGetter::new(getter, capacity));
+                let stats = caching.clone();
+                thread::spawn(move || loop {
+                    thread::sleep(Duration::from_secs(60));
+                    log::info!(target: "cache", "stats:{:?}", stats.stats());
+                });
+                caching
+            }
+            None => getter,
+        };
 
     // pnk!(GETTER.set(getter.clone()).map_err(|_| eg!()));
     pnk!(init_upstream(getter.clone()));
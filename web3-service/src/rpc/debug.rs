@@ -6,7 +6,9 @@ use {
             debug::DebugApi,
             event_listener::{ContractInfo, DebugEventListener},
             jsvm::func::parse_tracer,
-            types::TraceParams,
+            types::{
+                AccountRangeResult, DumpAccount, StorageEntry, StorageRangeResult, TraceParams,
+            },
         },
         internal_err,
     },
@@ -24,10 +26,21 @@ use {
     },
     evm_exporter::Getter,
     jsonrpc_core::{Error, Result, Value},
-    std::sync::{Arc, Mutex},
-    web3_rpc_core::types::{BlockNumber, CallRequest},
+    sha3::{Digest, Keccak256},
+    std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+    },
+    web3_rpc_core::types::{BlockNumber, Bytes, CallRequest},
 };
 
+/// Page size of `debug_accountRange` when none or a larger one is asked for,
+/// the same as geth's.
+const ACCOUNT_RANGE_MAX_RESULTS: usize = 256;
+
+/// Largest page `debug_storageRangeAt` returns, whatever `maxResult` asks for.
+const STORAGE_RANGE_MAX_RESULTS: usize = 1024;
+
 pub struct DebugApiImpl {
     chain_id: u32,
     gas_price: u64,
@@ -473,4 +486,155 @@ impl DebugApi for DebugApiImpl {
             err
         })
     }
+
+    /// Storage of `address` before the transaction `tx_index` of the block.
+    /// Only the state before the whole block is exported, so `tx_index` has
+    /// to be 0. Unlike geth, pages go in slot order rather than in the order
+    /// of the slot hashes, `key_start` and `nextKey` are slots.
+    fn storage_range_at(
+        &self,
+        block_hash: H256,
+        tx_index: usize,
+        address: H160,
+        key_start: H256,
+        max_result: usize,
+    ) -> Result<StorageRangeResult> {
+        if tx_index != 0 {
+            return Err(internal_err(
+                "debug api storage_range_at only supports txIndex 0",
+            ));
+        }
+        let height = match self.getter.get_height_by_block_hash(block_hash) {
+            Ok(Some(height)) => height.as_u32(),
+            Ok(None) => return Err(internal_err("block not found")),
+            Err(e) => {
+                return Err(internal_err(format!(
+                    "debug api storage_range_at get_height_by_block_hash error:{:?}",
+                    e.to_string()
+                )));
+            }
+        };
+        let range = self
+            .getter
+            .get_storage_range(
+                height.saturating_sub(1),
+                address,
+                key_start,
+                max_result.min(STORAGE_RANGE_MAX_RESULTS),
+            )
+            .map_err(|e| {
+                internal_err(format!(
+                    "debug api storage_range_at get_storage_range error:{:?}",
+                    e.to_string()
+                ))
+            })?;
+        Ok(StorageRangeResult {
+            storage: range
+                .slots
+                .into_iter()
+                .map(|(key, value)| {
+                    let hash = H256::from_slice(Keccak256::digest(key.as_bytes()).as_slice());
+                    (
+                        hash,
+                        StorageEntry {
+                            key: Some(key),
+                            value,
+                        },
+                    )
+                })
+                .collect(),
+            next_key: range.next,
+        })
+    }
+
+    /// Accounts at the end of the block `number`, from the address `start` on.
+    /// Pages go in address order, so `start` and `next` are addresses rather
+    /// than address hashes as with geth. Storage is never included, it is
+    /// paged by `debug_storageRangeAt`.
+    fn account_range(
+        &self,
+        number: BlockNumber,
+        start: Bytes,
+        max_results: usize,
+        nocode: Option<bool>,
+        _nostorage: Option<bool>,
+        _incompletes: Option<bool>,
+    ) -> Result<AccountRangeResult> {
+        let height = block_number_to_height(Some(number), self.getter.clone()).map_err(|e| {
+            internal_err(format!(
+                "debug api account_range block_number_to_height error:{:?}",
+                e.to_string()
+            ))
+        })?;
+        let block = self
+            .getter
+            .get_block_hash_by_height(U256::from(height))
+            .and_then(|hash| match hash {
+                Some(hash) => self.getter.get_block_by_hash(hash),
+                None => Ok(None),
+            })
+            .map_err(|e| {
+                internal_err(format!(
+                    "debug api account_range get_block_by_hash error:{:?}",
+                    e.to_string()
+                ))
+            })?
+            .ok_or_else(|| internal_err("block not found"))?;
+
+        // a shorter `start` is a prefix of the address, as with geth
+        let mut start_address = H160::zero();
+        let len = start.0.len().min(H160::len_bytes());
+        start_address.as_bytes_mut()[..len].copy_from_slice(&start.0[..len]);
+        let limit = match max_results {
+            0 => ACCOUNT_RANGE_MAX_RESULTS,
+            n => n.min(ACCOUNT_RANGE_MAX_RESULTS),
+        };
+
+        let range = self
+            .getter
+            .get_account_range(height, start_address, limit)
+            .map_err(|e| {
+                internal_err(format!(
+                    "debug api account_range get_account_range error:{:?}",
+                    e.to_string()
+                ))
+            })?;
+        let empty_code_hash = H256::from_slice(Keccak256::digest(b"").as_slice());
+        let mut accounts = BTreeMap::new();
+        for (address, summary) in range.accounts {
+            let code = match summary.code_hash {
+                Some(hash) if !nocode.unwrap_or_default() => self
+                    .getter
+                    .get_code_by_hash(hash)
+                    .map_err(|e| {
+                        internal_err(format!(
+                            "debug api account_range get_code_by_hash error:{:?}",
+                            e.to_string()
+                        ))
+                    })?
+                    .map(Bytes),
+                _ => None,
+            };
+            let nonce = u64::try_from(summary.nonce).map_err(|_| {
+                internal_err(format!(
+                    "debug api account_range nonce of {:?} overflows u64",
+                    address
+                ))
+            })?;
+            let account = DumpAccount {
+                balance: summary.balance.to_string(),
+                nonce,
+                code_hash: summary.code_hash.unwrap_or(empty_code_hash),
+                code,
+                address,
+                key: H256::from_slice(Keccak256::digest(address.as_bytes()).as_slice()),
+            };
+            accounts.insert(address, account);
+        }
+        Ok(AccountRangeResult {
+            root: block.header.state_root,
+            accounts,
+            next: range.next,
+        })
+    }
 }
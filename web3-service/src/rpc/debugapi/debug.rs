@@ -1,9 +1,9 @@
 use {
-    super::types::TraceParams,
-    ethereum_types::H256,
+    super::types::{AccountRangeResult, StorageRangeResult, TraceParams},
+    ethereum_types::{H160, H256},
     jsonrpc_core::{Result, Value},
     jsonrpc_derive::rpc,
-    web3_rpc_core::types::{BlockNumber, CallRequest},
+    web3_rpc_core::types::{BlockNumber, Bytes, CallRequest},
 };
 
 #[rpc(server)]
@@ -16,4 +16,23 @@ pub trait DebugApi {
     fn trace_call(&self, _: CallRequest, _: BlockNumber, _: Option<TraceParams>) -> Result<Value>;
     #[rpc(name = "debug_traceTransaction")]
     fn trace_transaction(&self, _: H256, _: Option<TraceParams>) -> Result<Value>;
+    #[rpc(name = "debug_storageRangeAt")]
+    fn storage_range_at(
+        &self,
+        _: H256,
+        _: usize,
+        _: H160,
+        _: H256,
+        _: usize,
+    ) -> Result<StorageRangeResult>;
+    #[rpc(name = "debug_accountRange")]
+    fn account_range(
+        &self,
+        _: BlockNumber,
+        _: Bytes,
+        _: usize,
+        _: Option<bool>,
+        _: Option<bool>,
+        _: Option<bool>,
+    ) -> Result<AccountRangeResult>;
 }
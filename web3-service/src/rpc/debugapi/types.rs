@@ -1,8 +1,9 @@
 use {
-    ethereum_types::{H256, U256},
+    ethereum_types::{H160, H256, U256},
     evm::Opcode,
     serde::{Deserialize, Serialize},
     std::collections::BTreeMap,
+    web3_rpc_core::types::Bytes,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,3 +61,45 @@ pub struct TransactionTrace {
     pub(crate) return_value: Vec<u8>,
     pub(crate) step_logs: Vec<RawStepLog>,
 }
+
+/// Reply of `debug_storageRangeAt`, as geth sends it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageRangeResult {
+    /// Slots under the keccak hash of the slot.
+    pub storage: BTreeMap<H256, StorageEntry>,
+    /// First slot of the next page, `null` on the last one.
+    pub next_key: Option<H256>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageEntry {
+    pub key: Option<H256>,
+    pub value: H256,
+}
+
+/// Reply of `debug_accountRange`, as geth sends it.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountRangeResult {
+    pub root: H256,
+    pub accounts: BTreeMap<H160, DumpAccount>,
+    /// First address of the next page, left out on the last one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<H160>,
+}
+
+/// Account of `AccountRangeResult`. Storage roots are not exported, so unlike
+/// geth there is no `root`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpAccount {
+    /// Decimal.
+    pub balance: String,
+    pub nonce: u64,
+    pub code_hash: H256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    pub address: H160,
+    /// Keccak hash of the address.
+    pub key: H256,
+}
@@ -14,7 +14,7 @@ use {
         executor::stack::{StackExecutor, StackSubstateMetadata},
         ExitError, ExitReason,
     },
-    evm_exporter::{public_key, Getter, TransactionStatus},
+    evm_exporter::{public_key, Getter, IndexedLog, TransactionStatus},
     futures::TryFutureExt,
     jsonrpc_core::{futures::future, BoxFuture, Error, ErrorCode, Result, Value},
     lazy_static::lazy_static,
@@ -29,7 +29,8 @@ use {
     web3_rpc_core::{
         types::{
             Block, BlockNumber, BlockTransactions, Bytes, CallRequest, Filter, FilteredParams,
-            Index, Log, Receipt, RichBlock, SyncStatus, Transaction, TransactionRequest, Work,
+            Index, Log, Receipt, RichBlock, SyncStatus, Transaction, TransactionRequest,
+            VariadicValue, Work,
         },
         EthApi,
     },
@@ -1758,125 +1759,36 @@ impl EthApi for EthService {
                     ))
                 })?;
 
-                let from_number = match filter.from_block.clone() {
-                    Some(v) => match v.to_min_block_num() {
-                        Some(from) => (from as u32).max(current_number),
-                        None => current_number,
-                    },
-                    None => current_number,
-                };
-
-                let to_number = match filter.to_block.clone() {
-                    Some(v) => match v.to_min_block_num() {
-                        Some(to) => (to as u32).min(current_number),
-                        None => current_number,
-                    },
-                    None => current_number,
-                };
-
-                let topics_input = if filter.topics.is_some() {
-                    let filtered_params = FilteredParams::new(Some(filter.clone()));
-                    Some(filtered_params.flat_topics)
-                } else {
-                    None
-                };
-                let address_bloom_filter = FilteredParams::addresses_bloom_filter(&filter.address);
-                let topics_bloom_filter = FilteredParams::topics_bloom_filter(&topics_input);
-
-                let mut current = to_number;
-                while current >= from_number {
-                    let getter_clone = getter.clone();
-                    let block_hash_result = tokio::task::spawn_blocking(move || {
-                        getter_clone
-                            .get_block_hash_by_height(U256::from(current))
-                            .map_err(|e| {
-                                internal_err(format!(
-                                    "eth api logs get_block_hash_by_height error:{:?}",
-                                    e.to_string()
-                                ))
-                            })
-                    })
-                    .await
-                    .map_err(|e| {
-                        internal_err(format!(
-                            "eth api logs spawn_blocking get_block_hash_by_height error:{:?}",
-                            e.to_string()
-                        ))
-                    })?;
-
-                    let block_hash = match block_hash_result {
-                        Ok(Some(h)) => h,
-                        Ok(None) => return Err(internal_err("Block hash not found".to_string())),
-                        Err(e) => return Err(e),
-                    };
+                let (from_number, to_number) = filter_block_range(&filter, current_number)?;
 
-                    let getter_clone = getter.clone();
-                    let block_result = tokio::task::spawn_blocking(move || {
-                        getter_clone.get_block_by_hash(block_hash).map_err(|e| {
-                            internal_err(format!(
-                                "eth api logs get_block_by_hash error:{:?}",
-                                e.to_string()
-                            ))
+                let (addresses, topics) = filter_log_query(&filter);
+                let logs = tokio::task::spawn_blocking(move || {
+                    getter
+                        .get_logs(
+                            from_number,
+                            to_number,
+                            &addresses,
+                            &topics,
+                            MAX_PAST_LOGS as usize + 1,
+                        )
+                        .map_err(|e| {
+                            internal_err(format!("eth api logs get_logs error:{:?}", e.to_string()))
                         })
-                    })
-                    .await
-                    .map_err(|e| {
-                        internal_err(format!(
-                            "eth api logs spawn_blocking get_block_by_hash error:{:?}",
-                            e.to_string()
-                        ))
-                    })?;
-
-                    let block = match block_result {
-                        Ok(Some(b)) => b,
-                        Ok(None) => return Err(internal_err("Block not found".to_string())),
-                        Err(e) => return Err(e),
-                    };
-
-                    if FilteredParams::address_in_bloom(
-                        block.header.logs_bloom,
-                        &address_bloom_filter,
-                    ) && FilteredParams::topics_in_bloom(
-                        block.header.logs_bloom,
-                        &topics_bloom_filter,
-                    ) {
-                        let getter_clone = getter.clone();
-                        let statuses_result = tokio::task::spawn_blocking(move || {
-                        getter_clone .get_transaction_status_by_block_hash(block_hash)
-                            .map_err(|e| internal_err(format!(
-                                "eth api logs get_transaction_status_by_block_hash error:{:?}",
-                                e.to_string()
-                            )))
-                    })
-                    .await
-                    .map_err(|e| internal_err(format!(
-                        "eth api logs spawn_blocking get_transaction_status_by_block_hash error:{:?}",
+                })
+                .await
+                .map_err(|e| {
+                    internal_err(format!(
+                        "eth api logs spawn_blocking get_logs error:{:?}",
                         e.to_string()
-                    )))?;
-
-                        let statuses = match statuses_result {
-                            Ok(Some(s)) => s,
-                            Ok(None) => {
-                                return Err(internal_err(
-                                    "Transaction statuses not found".to_string(),
-                                ))
-                            }
-                            Err(e) => return Err(e),
-                        };
-
-                        let mut logs: Vec<Log> = Vec::new();
-                        filter_block_logs(&mut logs, &filter, block, statuses);
-                        ret.append(&mut logs);
-                    }
-
-                    if ret.len() as u32 > MAX_PAST_LOGS {
-                        break;
-                    }
-                    if current == 0 {
-                        break;
-                    }
-                    current -= 1;
+                    ))
+                })??;
+                if logs.len() as u32 > MAX_PAST_LOGS {
+                    return Err(internal_err(format!(
+                        "query returned more than {} results",
+                        MAX_PAST_LOGS
+                    )));
                 }
+                ret = logs.into_iter().map(indexed_log_to_log).collect();
             }
 
             Ok(ret)
@@ -1903,6 +1815,68 @@ impl EthApi for EthService {
         Ok(false)
     }
 }
+/// Heights `from..=to` asked for by `filter`. A missing bound is the latest
+/// height `current`, which also caps `to`.
+pub fn filter_block_range(filter: &Filter, current: u32) -> Result<(u32, u32)> {
+    let bound = |block: &Option<BlockNumber>| {
+        block
+            .clone()
+            .and_then(|v| v.to_min_block_num())
+            .map_or(current, |n| u32::try_from(n).unwrap_or(u32::MAX))
+    };
+    let (from, to) = (
+        bound(&filter.from_block),
+        bound(&filter.to_block).min(current),
+    );
+    if from > to {
+        return Err(internal_err(format!(
+            "invalid block range params, from {} is above to {}",
+            from, to
+        )));
+    }
+    Ok((from, to))
+}
+
+/// Address and per-position topic constraints of `filter`, in the shape taken
+/// by `Getter::get_logs`. A `None` position matches any topic.
+pub fn filter_log_query(filter: &Filter) -> (Vec<H160>, Vec<Option<Vec<H256>>>) {
+    let addresses = match &filter.address {
+        Some(VariadicValue::Single(address)) => vec![*address],
+        Some(VariadicValue::Multiple(addresses)) => addresses.clone(),
+        _ => vec![],
+    };
+    let positions = match &filter.topics {
+        Some(VariadicValue::Single(position)) => vec![position.clone()],
+        Some(VariadicValue::Multiple(positions)) => positions.clone(),
+        _ => vec![],
+    };
+    let topics = positions
+        .into_iter()
+        .map(|position| match position {
+            Some(VariadicValue::Single(Some(topic))) => Some(vec![topic]),
+            // A null inside the alternatives makes the whole position a wildcard.
+            Some(VariadicValue::Multiple(topics)) => topics.into_iter().collect(),
+            _ => None,
+        })
+        .collect();
+    (addresses, topics)
+}
+
+pub fn indexed_log_to_log(log: IndexedLog) -> Log {
+    Log {
+        address: log.address,
+        topics: log.topics,
+        data: Bytes(log.data),
+        block_hash: Some(log.block_hash),
+        block_number: Some(U256::from(log.block_number)),
+        transaction_hash: Some(log.transaction_hash),
+        transaction_index: Some(U256::from(log.transaction_index)),
+        log_index: Some(U256::from(log.log_index)),
+        transaction_log_index: Some(U256::from(log.transaction_log_index)),
+        removed: false,
+    }
+}
+
 pub fn filter_block_logs<'a>(
     ret: &'a mut Vec<Log>,
     filter: &'a Filter,
@@ -1961,3 +1935,68 @@ pub fn filter_block_logs<'a>(
     }
     ret
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{filter_block_range, filter_log_query},
+        ethereum_types::{H160, H256},
+        serde_json::json,
+        web3_rpc_core::types::Filter,
+    };
+
+    fn filter(value: serde_json::Value) -> Filter {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_filter_log_query() {
+        let (addresses, topics) = filter_log_query(&filter(json!({})));
+        assert!(addresses.is_empty());
+        assert!(topics.is_empty());
+
+        let address = H160::repeat_byte(1);
+        let (a, b) = (H256::repeat_byte(2), H256::repeat_byte(3));
+        let (addresses, topics) = filter_log_query(&filter(json!({
+            "address": address,
+            "topics": [a, null, [a, b], [a, null]],
+        })));
+        assert_eq!(addresses, vec![address]);
+        // A null among the alternatives makes the position a wildcard.
+        assert_eq!(topics, vec![Some(vec![a]), None, Some(vec![a, b]), None]);
+
+        let (addresses, topics) = filter_log_query(&filter(json!({
+            "address": [address, H160::repeat_byte(4)],
+            "topics": a,
+        })));
+        assert_eq!(addresses, vec![address, H160::repeat_byte(4)]);
+        assert_eq!(topics, vec![Some(vec![a])]);
+    }
+
+    #[test]
+    fn test_filter_block_range() {
+        assert_eq!(
+            filter_block_range(&filter(json!({})), 10).unwrap(),
+            (10, 10)
+        );
+        // The whole range goes to `get_logs`, not just the latest block.
+        assert_eq!(
+            filter_block_range(&filter(json!({"fromBlock": "0x2", "toBlock": "0x8"})), 10).unwrap(),
+            (2, 8)
+        );
+        assert_eq!(
+            filter_block_range(&filter(json!({"fromBlock": "0x2"})), 10).unwrap(),
+            (2, 10)
+        );
+        assert_eq!(
+            filter_block_range(&filter(json!({"fromBlock": "0x2", "toBlock": "0x20"})), 10)
+                .unwrap(),
+            (2, 10)
+        );
+        assert!(
+            filter_block_range(&filter(json!({"fromBlock": "0x8", "toBlock": "0x2"})), 10).is_err()
+        );
+        // Heights past u32 are not truncated into range.
+        assert!(filter_block_range(&filter(json!({"fromBlock": "0x100000002"})), 10).is_err());
+    }
+}
@@ -1,7 +1,10 @@
 use {
-    super::{eth::filter_block_logs, internal_err, MAX_PAST_LOGS, MAX_STORED_FILTERS},
+    super::{
+        eth::{filter_log_query, indexed_log_to_log},
+        internal_err, MAX_PAST_LOGS, MAX_STORED_FILTERS,
+    },
     ethereum_types::{H256, U256},
-    evm_exporter::{Block, Getter, TransactionStatus},
+    evm_exporter::{Block, Getter},
     futures::executor::ThreadPool,
     jsonrpc_core::Result,
     lazy_static::lazy_static,
@@ -12,8 +15,7 @@ use {
     },
     web3_rpc_core::{
         types::{
-            BlockNumber, Filter, FilterChanges, FilterPool, FilterPoolItem, FilterType,
-            FilteredParams, Index, Log,
+            BlockNumber, Filter, FilterChanges, FilterPool, FilterPoolItem, FilterType, Index, Log,
         },
         EthFilterApi,
     },
@@ -23,7 +25,6 @@ lazy_static! {
     static ref POOL_FILTER: ThreadPool =
         ThreadPool::new().expect("Failed to create EthFilter thread pool executor");
 }
-const MAX_FILTER_SECS: u64 = 10;
 const FILTER_RETAIN_THRESHOLD: u64 = 100;
 
 pub struct EthFilterApiImpl {
@@ -130,21 +131,6 @@ impl EthFilterApiImpl {
             .get_block_by_hash(hash)
             .map_err(|e| internal_err(e.to_string()))
     }
-    fn get_transaction_statuses(&self, height: u64) -> Result<Option<Vec<TransactionStatus>>> {
-        let hash = match self
-            .getter
-            .get_block_hash_by_height(U256::from(height))
-            .map_err(|e| internal_err(e.to_string()))?
-        {
-            Some(hash) => hash,
-            None => {
-                return Ok(None);
-            }
-        };
-        self.getter
-            .get_transaction_status_by_block_hash(hash)
-            .map_err(|e| internal_err(e.to_string()))
-    }
 
     fn filter_range_logs(
         &self,
@@ -154,56 +140,28 @@ impl EthFilterApiImpl {
         from: u64,
         to: u64,
     ) -> Result<()> {
-        // Max request duration of 10 seconds.
-        let max_duration = time::Duration::from_secs(MAX_FILTER_SECS);
-        let begin_request = time::Instant::now();
-
-        // Pre-calculate BloomInput for reuse.
-        let topics_input = if filter.topics.is_some() {
-            let filtered_params = FilteredParams::new(Some(filter.clone()));
-            Some(filtered_params.flat_topics)
-        } else {
-            None
-        };
-        let address_bloom_filter = FilteredParams::addresses_bloom_filter(&filter.address);
-        let topics_bloom_filter = FilteredParams::topics_bloom_filter(&topics_input);
-
-        let mut current = to;
-        while current >= from {
-            let block = self.get_block(current)?;
-
-            if let Some(block) = block {
-                if FilteredParams::address_in_bloom(block.header.logs_bloom, &address_bloom_filter)
-                    && FilteredParams::topics_in_bloom(
-                        block.header.logs_bloom,
-                        &topics_bloom_filter,
-                    )
-                {
-                    let statuses = self.get_transaction_statuses(current)?;
-                    if let Some(statuses) = statuses {
-                        filter_block_logs(ret, filter, block, statuses);
-                    }
-                }
-            } else {
-                // stop when past the 1st ethereum block
-                break;
-            }
-
-            // Check for restrictions
-            if ret.len() as u32 > MAX_PAST_LOGS {
-                return Err(internal_err(format!(
-                    "query returned more than {} results",
-                    MAX_PAST_LOGS
-                )));
-            }
-            if begin_request.elapsed() > max_duration {
-                return Err(internal_err(format!(
-                    "query timeout of {} seconds exceeded",
-                    max_duration.as_secs()
-                )));
-            }
-            current = current.saturating_sub(1);
+        // nothing new since the last poll
+        if from > to {
+            return Ok(());
+        }
+        let (addresses, topics) = filter_log_query(filter);
+        let logs = self
+            .getter
+            .get_logs(
+                u32::try_from(from).unwrap_or(u32::MAX),
+                u32::try_from(to).unwrap_or(u32::MAX),
+                &addresses,
+                &topics,
+                MAX_PAST_LOGS as usize + 1,
+            )
+            .map_err(|e| internal_err(e.to_string()))?;
+        if logs.len() as u32 > MAX_PAST_LOGS {
+            return Err(internal_err(format!(
+                "query returned more than {} results",
+                MAX_PAST_LOGS
+            )));
         }
+        ret.extend(logs.into_iter().map(indexed_log_to_log));
         Ok(())
     }
 }